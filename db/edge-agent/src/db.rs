@@ -1,6 +1,7 @@
 use crate::models::{Command, TelemetryData};
 use anyhow::Result;
 use rusqlite::{params, Connection};
+use std::time::Duration;
 
 pub struct Database {
     conn: Connection,
@@ -14,6 +15,18 @@ impl Database {
         Ok(db)
     }
 
+    /// Opens the database so that other local processes (e.g. edge-runner) can
+    /// write to it concurrently: WAL journal plus a busy timeout instead of
+    /// failing immediately on a locked database.
+    pub fn new_shared(path: &str, busy_timeout: Duration) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.busy_timeout(busy_timeout)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        let db = Self { conn };
+        db.init_schema()?;
+        Ok(db)
+    }
+
     fn init_schema(&self) -> Result<()> {
         self.conn.execute_batch(
             r#"
@@ -1,4 +1,5 @@
 use anyhow::Result;
+use std::time::Duration;
 use edge_agent::{db::Database, sync::SyncAgent};
 use tracing::info;
 use uuid::Uuid;
@@ -7,8 +8,13 @@ use uuid::Uuid;
 async fn main() -> Result<()> {
     tracing_subscriber::fmt::init();
 
-    let db = Database::new("edge.db")?;
-    info!("Database initialized");
+    let db_path = std::env::var("EDGE_DB_PATH").unwrap_or_else(|_| "edge.db".to_string());
+    let db = if std::env::var("EDGE_DB_SHARED").map(|v| v == "1" || v == "true").unwrap_or(false) {
+        Database::new_shared(&db_path, Duration::from_secs(5))?
+    } else {
+        Database::new(&db_path)?
+    };
+    info!("Database initialized at {}", db_path);
 
     let device_id = std::env::var("DEVICE_ID").unwrap_or_else(|_| Uuid::new_v4().to_string());
     let api_url = std::env::var("API_URL").unwrap_or_else(|_| "http://localhost:8080".to_string());
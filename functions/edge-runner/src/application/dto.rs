@@ -1,51 +1,124 @@
-use serde::{Deserialize, Serialize};
+use crate::domain::{InvocationError, RouteMatch};
+use serde::Serialize;
+use std::collections::BTreeMap;
 
-#[derive(Serialize, Deserialize)]
-pub struct HeartbeatRequest {
-    pub node_id: String,
-    pub pop_id: String,
-    pub status: String,
-    pub cached_functions: Vec<CachedFunctionDto>,
+#[derive(Serialize)]
+pub struct InvocationRequest {
+    pub request_id: String,
+    pub method: String,
+    pub path: String,
+    pub query: Option<String>,
+    pub host: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-pub struct CachedFunctionDto {
-    pub function_id: String,
-    pub version: String,
-    pub state: String,
-}
+impl InvocationRequest {
+    /// ゲストに渡すリクエストターゲット (`path?query`)
+    pub fn target(&self) -> String {
+        match &self.query {
+            Some(query) => format!("{}?{}", self.path, query),
+            None => self.path.clone(),
+        }
+    }
 
-#[derive(Deserialize)]
-pub struct HeartbeatResponse {
-    #[allow(dead_code)]
-    pub deployments: Vec<DeploymentNotificationDto>,
+    /// ヘッダーを `Name: value\r\n` 形式でエンコード
+    pub fn encode_headers(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for (name, value) in &self.headers {
+            buf.extend_from_slice(name.as_bytes());
+            buf.extend_from_slice(b": ");
+            buf.extend_from_slice(value.as_bytes());
+            buf.extend_from_slice(b"\r\n");
+        }
+        buf
+    }
 }
 
-#[derive(Deserialize, Clone)]
-pub struct DeploymentNotificationDto {
-    #[allow(dead_code)]
-    pub function_id: String,
-    #[allow(dead_code)]
-    pub version: String,
-    #[allow(dead_code)]
-    pub artifact_url: String,
-    #[allow(dead_code)]
-    pub sha256: String,
-    #[allow(dead_code)]
-    pub memory_pages: i32,
-    #[allow(dead_code)]
-    pub max_execution_ms: i32,
+/// 一致したルートの情報。`handle` が 5 組目の引数を受け取る場合に JSON で渡す
+///
+/// ```text
+/// {"route_id":"files","host":"shop.example.com:8080","params":{"bucket":"docs","rest":"a/b.txt"},"rest":"a/b.txt"}
+/// ```
+#[derive(Serialize, Debug)]
+pub struct InvocationContext {
+    pub route_id: String,
+    // リクエストの Host ヘッダーそのまま (ポートを含む)
+    pub host: String,
+    pub params: BTreeMap<String, String>,
+    pub rest: Option<String>,
 }
 
-#[derive(Serialize)]
-pub struct InvocationRequest {
-    pub method: String,
-    pub path: String,
-    pub host: String,
+impl InvocationContext {
+    pub fn new(route_match: &RouteMatch, request: &InvocationRequest) -> Self {
+        Self {
+            route_id: route_match.route_id.clone(),
+            host: request.host.clone(),
+            params: route_match.path_params.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            rest: route_match.rest.clone(),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap_or_default()
+    }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Debug)]
 pub struct InvocationResponse {
     pub status_code: u16,
+    pub headers: Vec<(String, String)>,
     pub body: Vec<u8>,
 }
+
+impl InvocationResponse {
+    /// ゲストが書き込んだレスポンスエンベロープをデコード
+    ///
+    /// ```text
+    /// 201\r\n
+    /// Content-Type: application/json\r\n
+    /// \r\n
+    /// {"id":1}
+    /// ```
+    pub fn decode(data: &[u8]) -> Result<Self, InvocationError> {
+        let (status_line, mut rest) = split_line(data)
+            .ok_or_else(|| invalid("missing status line"))?;
+        
+        let status_code = std::str::from_utf8(status_line)
+            .ok()
+            .and_then(|s| s.trim().parse::<u16>().ok())
+            .filter(|code| (100..=599).contains(code))
+            .ok_or_else(|| invalid("bad status code"))?;
+        
+        let mut headers = Vec::new();
+        loop {
+            let (line, next) = split_line(rest)
+                .ok_or_else(|| invalid("unterminated headers"))?;
+            rest = next;
+            if line.is_empty() {
+                break;
+            }
+            
+            let line = std::str::from_utf8(line)
+                .map_err(|_| invalid("header is not UTF-8"))?;
+            let (name, value) = line.split_once(':')
+                .ok_or_else(|| invalid(&format!("malformed header '{}'", line)))?;
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+        
+        Ok(Self {
+            status_code,
+            headers,
+            body: rest.to_vec(),
+        })
+    }
+}
+
+fn invalid(reason: &str) -> InvocationError {
+    InvocationError::InvalidResponse(reason.to_string())
+}
+
+fn split_line(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let pos = data.windows(2).position(|w| w == b"\r\n")?;
+    Some((&data[..pos], &data[pos + 2..]))
+}
@@ -0,0 +1,63 @@
+#[cfg(test)]
+mod tests {
+    use crate::application::dto::*;
+
+    #[test]
+    fn test_decode_status_headers_and_body() {
+        let data = b"201\r\nContent-Type: application/json\r\nSet-Cookie: a=b; Path=/\r\n\r\n{\"id\":1}";
+        let response = InvocationResponse::decode(data).unwrap();
+
+        assert_eq!(response.status_code, 201);
+        assert_eq!(response.headers.len(), 2);
+        assert_eq!(response.headers[0], ("Content-Type".to_string(), "application/json".to_string()));
+        assert_eq!(response.headers[1], ("Set-Cookie".to_string(), "a=b; Path=/".to_string()));
+        assert_eq!(response.body, b"{\"id\":1}");
+    }
+
+    #[test]
+    fn test_decode_without_headers_or_body() {
+        let response = InvocationResponse::decode(b"302\r\n\r\n").unwrap();
+
+        assert_eq!(response.status_code, 302);
+        assert!(response.headers.is_empty());
+        assert!(response.body.is_empty());
+    }
+
+    #[test]
+    fn test_decode_keeps_crlf_in_body() {
+        let response = InvocationResponse::decode(b"200\r\n\r\nline1\r\n\r\nline2").unwrap();
+        assert_eq!(response.body, b"line1\r\n\r\nline2");
+    }
+
+    #[test]
+    fn test_decode_rejects_invalid_envelopes() {
+        assert!(InvocationResponse::decode(b"").is_err());
+        assert!(InvocationResponse::decode(b"{\"message\":\"raw\"}").is_err());
+        assert!(InvocationResponse::decode(b"abc\r\n\r\n").is_err());
+        assert!(InvocationResponse::decode(b"999\r\n\r\n").is_err());
+        assert!(InvocationResponse::decode(b"200\r\nContent-Type: text/plain\r\n").is_err());
+        assert!(InvocationResponse::decode(b"200\r\nno-colon\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn test_encode_request_headers() {
+        let request = InvocationRequest {
+            request_id: "req-1".to_string(),
+            method: "POST".to_string(),
+            path: "/api/users".to_string(),
+            query: Some("page=2".to_string()),
+            host: "localhost".to_string(),
+            headers: vec![
+                ("content-type".to_string(), "application/json".to_string()),
+                ("authorization".to_string(), "Bearer token".to_string()),
+            ],
+            body: b"{}".to_vec(),
+        };
+
+        assert_eq!(request.target(), "/api/users?page=2");
+        assert_eq!(
+            request.encode_headers(),
+            b"content-type: application/json\r\nauthorization: Bearer token\r\n".to_vec()
+        );
+    }
+}
@@ -0,0 +1,123 @@
+#[cfg(test)]
+mod tests {
+    use crate::application::services::*;
+    use crate::domain::*;
+    use crate::infrastructure::*;
+    use crate::test_support::{self, TestNode};
+    use std::sync::Arc;
+
+    struct Fixture {
+        function_service: Arc<FunctionService>,
+        cache_repo: Arc<InMemoryCacheRepository>,
+        pool: Arc<HotInstancePool>,
+        heartbeat: HeartbeatService,
+    }
+
+    fn fixture(name: &str) -> Fixture {
+        let kv = test_support::kv_store();
+        let node = TestNode::with_pool(Arc::new(HotInstancePool::new(4, 300, 50, None, test_support::host_services(kv.clone()))));
+        let artifacts = Arc::new(ArtifactLoader::new(Arc::new(
+            LocalWasmCache::new(test_support::temp_dir("heartbeat-test", name), 1024 * 1024).unwrap(),
+        )));
+
+        let heartbeat = HeartbeatService::new(
+            // reconcile はコントロールプレーンに接続しない
+            Arc::new(ControlPlaneClient::new("http://127.0.0.1:9".to_string())),
+            node.function_service.clone(),
+            node.cache_repo.clone(),
+            artifacts,
+            node.pool.clone(),
+            Arc::new(WasmExecutor::new(1, 4)),
+            kv,
+        );
+        Fixture { function_service: node.function_service, cache_repo: node.cache_repo, pool: node.pool, heartbeat }
+    }
+
+    fn metadata(function_id: &str) -> FunctionMetadata {
+        FunctionMetadata { sha256: format!("sha-{}", function_id), ..test_support::metadata(function_id, "1.0.0") }
+    }
+
+    fn route(id: &str, path: &str) -> RouteDto {
+        RouteDto {
+            id: id.to_string(),
+            host: "*".to_string(),
+            path: path.to_string(),
+            function_id: "func1".to_string(),
+            methods: vec!["GET".to_string()],
+            priority: 0,
+            split: None,
+        }
+    }
+
+    async fn deploy(fixture: &Fixture, function_id: &str) {
+        fixture.function_service.register_function(metadata(function_id)).await;
+        fixture.function_service.activate_function(function_id, "1.0.0").await.unwrap();
+        fixture.cache_repo.add_cached(CachedFunction {
+            function_id: function_id.to_string(),
+            version: "1.0.0".to_string(),
+            state: "cached".to_string(),
+        }).await;
+    }
+
+    fn route_ids(routes: Vec<Route>) -> Vec<String> {
+        routes.into_iter().map(|route| route.id).collect()
+    }
+
+    #[tokio::test]
+    async fn test_stale_revision_is_skipped() {
+        let fixture = fixture("stale");
+
+        fixture.heartbeat.reconcile(Some(2), None, Some(vec![route("new", "/new")])).await;
+        assert_eq!(route_ids(fixture.function_service.list_routes().await), vec!["new"]);
+
+        // 遅れて届いた古い応答や同じリビジョンの応答では巻き戻さない
+        fixture.heartbeat.reconcile(Some(1), None, Some(vec![route("old", "/old")])).await;
+        fixture.heartbeat.reconcile(Some(2), None, Some(Vec::new())).await;
+        assert_eq!(route_ids(fixture.function_service.list_routes().await), vec!["new"]);
+
+        fixture.heartbeat.reconcile(Some(3), None, Some(Vec::new())).await;
+        assert!(fixture.function_service.list_routes().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_function_missing_from_state_is_undeployed() {
+        let fixture = fixture("undeploy");
+        deploy(&fixture, "func1").await;
+        deploy(&fixture, "func2").await;
+
+        fixture.heartbeat.reconcile(Some(1), Some(vec!["func1".to_string()]), None).await;
+
+        assert!(fixture.function_service.get_function("func1").await.is_some());
+        assert!(fixture.function_service.get_function("func2").await.is_none());
+        assert_eq!(fixture.function_service.function_ids().await, vec!["func1"]);
+        // 削除した関数はハートビートでキャッシュ済みとして報告しない
+        let cached: Vec<String> = fixture.function_service.get_cached_functions().await
+            .into_iter()
+            .map(|cached| cached.function_id)
+            .collect();
+        assert_eq!(cached, vec!["func1"]);
+    }
+
+    #[tokio::test]
+    async fn test_undeploy_drops_concurrency_limit() {
+        let fixture = fixture("limits");
+        deploy(&fixture, "func1").await;
+        deploy(&fixture, "func2").await;
+        drop(fixture.pool.acquire_slot("func1", 1).await.unwrap());
+        drop(fixture.pool.acquire_slot("func2", 1).await.unwrap());
+        assert_eq!(fixture.pool.limited_functions(), 2);
+
+        fixture.heartbeat.reconcile(Some(1), Some(vec!["func1".to_string()]), None).await;
+        assert_eq!(fixture.pool.limited_functions(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_omitted_functions_are_left_alone() {
+        let fixture = fixture("omitted");
+        deploy(&fixture, "func1").await;
+
+        // functions を省略した応答 (変更なし) では何も削除しない
+        fixture.heartbeat.reconcile(Some(1), None, None).await;
+        assert!(fixture.function_service.get_function("func1").await.is_some());
+    }
+}
@@ -0,0 +1,149 @@
+#[cfg(test)]
+mod tests {
+    use crate::application::dto::InvocationRequest;
+    use crate::application::services::*;
+    use crate::domain::*;
+    use crate::infrastructure::*;
+    use crate::infrastructure::guest::MAX_GUEST_READ_BYTES;
+    use crate::test_support::{self, TestNode};
+    use sha2::{Digest, Sha256};
+    use std::sync::Arc;
+
+    // handle に入ったまま戻らないゲスト
+    const LOOPING_GUEST: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+          (func (export "dealloc") (param i32 i32))
+          (func (export "handle") (param i32 i32 i32 i32 i32 i32 i32 i32) (result i64)
+            (loop $spin (br $spin))
+            (i64.const 0)))
+    "#;
+
+    // alloc / dealloc のどちらかをエクスポートしないゲスト
+    const GUEST_WITHOUT_ALLOC: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "dealloc") (param i32 i32))
+          (func (export "handle") (param i32 i32 i32 i32 i32 i32 i32 i32) (result i64)
+            (i64.const 0)))
+    "#;
+
+    const GUEST_WITHOUT_DEALLOC: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+          (func (export "handle") (param i32 i32 i32 i32 i32 i32 i32 i32) (result i64)
+            (i64.const 0)))
+    "#;
+
+    // (ptr << 32) | len をそのまま返すゲスト
+    fn guest_returning(packed: i64) -> String {
+        format!(r#"
+            (module
+              (memory (export "memory") 1)
+              (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+              (func (export "dealloc") (param i32 i32))
+              (func (export "handle") (param i32 i32 i32 i32 i32 i32 i32 i32) (result i64)
+                (i64.const {})))
+        "#, packed)
+    }
+
+    fn metadata(sha256: String) -> FunctionMetadata {
+        FunctionMetadata { sha256, max_execution_ms: 5, max_concurrency: 1, ..test_support::metadata("func1", "1.0.0") }
+    }
+
+    fn request() -> InvocationRequest {
+        InvocationRequest {
+            request_id: "req-1".to_string(),
+            method: "GET".to_string(),
+            path: "/loop".to_string(),
+            query: None,
+            host: "localhost".to_string(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    async fn invocation_service(name: &str, wasm: &[u8]) -> InvocationService {
+        let cache = Arc::new(LocalWasmCache::new(test_support::temp_dir("invocation-test", name), 1024 * 1024).unwrap());
+        let sha256 = format!("{:x}", Sha256::digest(wasm));
+        cache.put("func1", "1.0.0", wasm, &sha256).await.unwrap();
+
+        let node = TestNode::new();
+        node.deploy(metadata(sha256)).await;
+        node.function_service.upsert_route(Route {
+            id: "loop".to_string(),
+            host: "localhost".to_string(),
+            path: "/loop".to_string(),
+            function_id: "func1".to_string(),
+            methods: vec!["GET".to_string()],
+            priority: 0,
+            split: None,
+        }).await.unwrap();
+        node.invocation_service(cache)
+    }
+
+    #[tokio::test]
+    async fn test_infinite_loop_times_out() {
+        let service = invocation_service("loop", LOOPING_GUEST.as_bytes()).await;
+
+        let error = service.invoke(request()).await.err().unwrap();
+        assert_eq!(error, InvocationError::Timeout { limit_ms: 5 });
+        assert_eq!(crate::presentation::status_of(&error), axum::http::StatusCode::GATEWAY_TIMEOUT);
+
+        // タイムアウトしたインスタンスは破棄され、次の呼び出しも同じように打ち切られる
+        assert_eq!(service.invoke(request()).await.err(), Some(InvocationError::Timeout { limit_ms: 5 }));
+    }
+
+    #[tokio::test]
+    async fn test_missing_allocator_exports_are_reported() {
+        let service = invocation_service("no-alloc", GUEST_WITHOUT_ALLOC.as_bytes()).await;
+        assert_eq!(
+            service.invoke(request()).await.err(),
+            Some(InvocationError::Execution("Guest does not export `alloc(len: i32) -> i32`".to_string())),
+        );
+
+        let service = invocation_service("no-dealloc", GUEST_WITHOUT_DEALLOC.as_bytes()).await;
+        assert_eq!(
+            service.invoke(request()).await.err(),
+            Some(InvocationError::Execution("Guest does not export `dealloc(ptr: i32, len: i32)`".to_string())),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_out_of_bounds_response_region_is_rejected() {
+        // 1 ページ (64 KiB) のメモリの外を指す
+        let service = invocation_service("out-of-bounds", guest_returning((65536 << 32) | 16).as_bytes()).await;
+        assert_eq!(
+            service.invoke(request()).await.err(),
+            Some(InvocationError::InvalidResponse("Guest returned out-of-bounds region 65536+16".to_string())),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_response_larger_than_read_limit_is_rejected() {
+        let len = MAX_GUEST_READ_BYTES as i64 + 1;
+        let service = invocation_service("oversized", guest_returning((1024 << 32) | len).as_bytes()).await;
+        assert_eq!(
+            service.invoke(request()).await.err(),
+            Some(InvocationError::InvalidResponse(format!(
+                "Guest region of {} bytes exceeds the {} byte limit", len, MAX_GUEST_READ_BYTES,
+            ))),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_body_larger_than_guest_memory_is_rejected() {
+        let service = invocation_service("large-body", guest_returning(0).as_bytes()).await;
+        let request = InvocationRequest { body: vec![b'x'; 2 * 65536], ..request() };
+
+        // alloc が返した領域に本文が収まらない
+        match service.invoke(request).await.err() {
+            Some(InvocationError::Execution(message)) => {
+                assert!(message.starts_with("Guest alloc returned invalid pointer 1024"), "{}", message);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -1,4 +1,9 @@
 pub mod services;
 pub mod dto;
+pub mod traffic;
+mod envelope_tests;
+mod traffic_tests;
+mod invocation_tests;
+mod heartbeat_tests;
 
 pub use services::*;
@@ -1,7 +1,8 @@
 use crate::domain::*;
 use crate::infrastructure::*;
+use crate::application::dto::{InvocationContext, InvocationRequest, InvocationResponse};
+use crate::application::traffic;
 use std::sync::Arc;
-use std::collections::HashMap;
 
 pub struct FunctionService {
     function_repo: Arc<dyn FunctionRepository>,
@@ -25,18 +26,75 @@ impl FunctionService {
         }
     }
     
-    pub async fn register_function(&self, metadata: FunctionMetadata) {
-        self.function_repo.register(metadata).await;
+    pub async fn register_function(&self, metadata: FunctionMetadata) -> Vec<FunctionMetadata> {
+        self.function_repo.register(metadata).await
     }
     
-    pub async fn add_route(&self, route: Route) {
-        self.route_repo.add_route(route).await;
+    pub async fn get_function(&self, function_id: &str) -> Option<FunctionMetadata> {
+        self.function_repo.get(function_id).await
     }
     
-    pub async fn resolve_function(&self, host: &str, path: &str, method: &str) -> Option<(FunctionMetadata, HashMap<String, String>)> {
-        let route_match = self.route_repo.match_route(host, path, method).await?;
-        let metadata = self.function_repo.get(&route_match.function_id).await?;
-        Some((metadata, route_match.path_params))
+    pub async fn get_function_version(&self, function_id: &str, version: &str) -> Option<FunctionMetadata> {
+        self.function_repo.get_version(function_id, version).await
+    }
+    
+    pub async fn get_verified_version(&self, function_id: &str, version: &str) -> Option<FunctionMetadata> {
+        self.function_repo.get_verified(function_id, version).await
+    }
+    
+    pub async fn previous_version(&self, function_id: &str) -> Option<FunctionMetadata> {
+        self.function_repo.previous(function_id).await
+    }
+    
+    pub async fn mark_verified(&self, function_id: &str, version: &str) -> Result<Option<FunctionMetadata>, String> {
+        self.function_repo.mark_verified(function_id, version).await
+    }
+    
+    pub async fn activate_function(&self, function_id: &str, version: &str) -> Result<Option<FunctionMetadata>, String> {
+        self.function_repo.activate(function_id, version).await
+    }
+    
+    pub async fn rollback_function(&self, function_id: &str) -> Result<FunctionMetadata, String> {
+        self.function_repo.rollback(function_id).await
+    }
+    
+    pub async fn replace_routes(&self, routes: Vec<Route>) -> Vec<String> {
+        self.route_repo.replace_routes(routes).await
+    }
+    
+    pub async fn upsert_route(&self, route: Route) -> Result<(), String> {
+        self.route_repo.add_route(route).await
+    }
+    
+    pub async fn remove_route(&self, id: &str) -> Option<Route> {
+        self.route_repo.remove_route(id).await
+    }
+    
+    pub async fn list_routes(&self) -> Vec<Route> {
+        self.route_repo.list_routes().await
+    }
+    
+    pub async fn remove_function(&self, function_id: &str) -> Vec<FunctionMetadata> {
+        self.function_repo.remove(function_id).await
+    }
+    
+    pub async fn function_ids(&self) -> Vec<String> {
+        self.function_repo.function_ids().await
+    }
+    
+    pub async fn resolve_function(&self, request: &InvocationRequest) -> Result<(FunctionMetadata, RouteMatch), InvocationError> {
+        let route_match = self.route_repo.match_route(&request.host, &request.path, &request.method).await?;
+        
+        // 振り分け先のバージョンがこのノードで未検証なら有効なバージョンで処理する
+        if let Some(target) = route_match.split.as_ref().and_then(|split| traffic::pick_target(split, request)) {
+            if let Some(metadata) = self.function_repo.get_verified(&target.function_id, &target.version).await {
+                return Ok((metadata, route_match));
+            }
+        }
+        
+        let metadata = self.function_repo.get(&route_match.function_id).await
+            .ok_or_else(|| InvocationError::FunctionNotFound(route_match.function_id.clone()))?;
+        Ok((metadata, route_match))
     }
     
     pub async fn get_cached_functions(&self) -> Vec<CachedFunction> {
@@ -48,7 +106,12 @@ pub struct HeartbeatService {
     cp_client: Arc<ControlPlaneClient>,
     function_service: Arc<FunctionService>,
     cache_repo: Arc<dyn CacheRepository>,
-    wasm_cache: Arc<crate::infrastructure::LocalWasmCache>,
+    artifacts: Arc<ArtifactLoader>,
+    pool: Arc<HotInstancePool>,
+    executor: Arc<WasmExecutor>,
+    kv: Arc<kv::KvStore>,
+    // 最後に適用したコントロールプレーンの状態のリビジョン
+    applied_revision: tokio::sync::Mutex<Option<u64>>,
 }
 
 impl HeartbeatService {
@@ -56,19 +119,38 @@ impl HeartbeatService {
         cp_client: Arc<ControlPlaneClient>,
         function_service: Arc<FunctionService>,
         cache_repo: Arc<dyn CacheRepository>,
-        wasm_cache: Arc<crate::infrastructure::LocalWasmCache>,
+        artifacts: Arc<ArtifactLoader>,
+        pool: Arc<HotInstancePool>,
+        executor: Arc<WasmExecutor>,
+        kv: Arc<kv::KvStore>,
     ) -> Self {
         Self {
             cp_client,
             function_service,
             cache_repo,
-            wasm_cache,
+            artifacts,
+            pool,
+            executor,
+            kv,
+            applied_revision: tokio::sync::Mutex::new(None),
+        }
+    }
+    
+    // 再起動前からディスクに残っているアーティファクトを最初のハートビートで報告できるようにする
+    pub async fn restore_cached(&self) {
+        for (function_id, version) in self.artifacts.cached_artifacts().await {
+            self.cache_repo.add_cached(CachedFunction {
+                function_id,
+                version,
+                state: "cached".to_string(),
+            }).await;
         }
     }
     
-    pub async fn send_heartbeat(&self, node_info: &NodeInfo) -> Result<(Vec<DeploymentNotification>, Vec<crate::infrastructure::RouteDto>), String> {
+    pub async fn send_heartbeat(&self, node_info: &NodeInfo) -> Result<HeartbeatResponse, InvocationError> {
         let cached = self.function_service.get_cached_functions().await;
-        self.cp_client.send_heartbeat(&node_info.node_id, &node_info.pop_id, cached).await
+        let revision = *self.applied_revision.lock().await;
+        self.cp_client.send_heartbeat(&node_info.node_id, &node_info.pop_id, cached, revision).await
     }
     
     pub async fn handle_deployments(&self, deployments: Vec<DeploymentNotification>) {
@@ -80,133 +162,373 @@ impl HeartbeatService {
                 sha256: deployment.sha256.clone(),
                 memory_pages: deployment.memory_pages as u32,
                 max_execution_ms: deployment.max_execution_ms as u32,
+                max_concurrency: deployment.max_concurrency.max(0) as u32,
+                min_warm: deployment.min_warm.max(0) as u32,
+                isolation: deployment.isolation,
+                wasi: deployment.wasi,
+                wasi_preopen_dir: deployment.wasi_preopen_dir,
+                allowed_hosts: deployment.allowed_hosts,
+                telemetry: deployment.telemetry,
             };
             
-            self.function_service.register_function(metadata).await;
+            // 同じアーティファクトが再通知された場合は設定の更新のみ
+            let current = if deployment.canary {
+                self.function_service.get_verified_version(&metadata.function_id, &metadata.version).await
+            } else {
+                self.function_service.get_function(&metadata.function_id).await
+            };
+            let unchanged = current
+                .is_some_and(|current| current.version == metadata.version && current.sha256 == metadata.sha256);
             
-            if let Ok(artifact_data) = download_artifact(&deployment.artifact_url).await {
-                let _ = self.wasm_cache.put(
-                    &deployment.function_id,
-                    &deployment.version,
-                    &artifact_data,
-                    &deployment.sha256,
-                ).await;
+            let pruned = self.function_service.register_function(metadata.clone()).await;
+            for old in pruned {
+                self.discard_version(&old).await;
             }
             
-            self.cache_repo.add_cached(CachedFunction {
-                function_id: deployment.function_id,
-                version: deployment.version,
-                state: "cached".to_string(),
-            }).await;
+            if unchanged {
+                continue;
+            }
+            
+            if deployment.canary {
+                match self.stage(&metadata).await {
+                    Ok(()) => println!("Staged {} {} for canary traffic", metadata.function_id, metadata.version),
+                    Err(e) => eprintln!("Canary deployment of {} {} failed: {}", metadata.function_id, metadata.version, e),
+                }
+                continue;
+            }
+            
+            match self.activate(&metadata).await {
+                Ok(()) => println!("Activated {} {}", metadata.function_id, metadata.version),
+                Err(e) => eprintln!("Deployment of {} {} failed, keeping the active version: {}", metadata.function_id, metadata.version, e),
+            }
         }
     }
     
-    pub async fn handle_routes(&self, routes: Vec<crate::infrastructure::RouteDto>) {
-        for route_dto in routes {
-            let route = Route {
+    pub async fn handle_function_commands(&self, commands: Vec<FunctionCommand>) {
+        for command in commands {
+            let result = match command.action {
+                FunctionAction::Rollback => self.rollback(&command.function_id, command.version.as_deref()).await
+                    .map(|version| format!("rolled back to {}", version)),
+                FunctionAction::Undeploy => self.undeploy(&command.function_id).await
+                    .map(|count| format!("undeployed {} version(s)", count)),
+            };
+            match result {
+                Ok(message) => println!("{}: {}", command.function_id, message),
+                Err(e) => eprintln!("{:?} of {} failed: {}", command.action, command.function_id, e),
+            }
+        }
+    }
+    
+    // コントロールプレーンが返した望ましい状態に合わせる。
+    // routes / functions はそれぞれ全体集合で、None の項目は変更しない
+    pub async fn reconcile(&self, revision: Option<u64>, functions: Option<Vec<String>>, routes: Option<Vec<RouteDto>>) {
+        let mut applied = self.applied_revision.lock().await;
+        if let (Some(revision), Some(applied)) = (revision, *applied) {
+            if revision <= applied {
+                return;
+            }
+        }
+        
+        if let Some(routes) = routes {
+            self.handle_routes(routes).await;
+        }
+        
+        if let Some(functions) = functions {
+            for function_id in self.function_service.function_ids().await {
+                if functions.contains(&function_id) {
+                    continue;
+                }
+                match self.undeploy(&function_id).await {
+                    Ok(count) => println!("{}: undeployed {} version(s)", function_id, count),
+                    Err(e) => eprintln!("Undeploy of {} failed: {}", function_id, e),
+                }
+            }
+        }
+        
+        if revision.is_some() {
+            *applied = revision;
+        }
+    }
+    
+    // KV の名前空間は再デプロイに備えて残す (削除は kv_commands の clear で行う)
+    async fn undeploy(&self, function_id: &str) -> Result<usize, String> {
+        let removed = self.function_service.remove_function(function_id).await;
+        if removed.is_empty() {
+            return Err(format!("{} is not deployed", function_id));
+        }
+        for metadata in &removed {
+            self.discard_version(metadata).await;
+        }
+        self.pool.remove_function(function_id);
+        Ok(removed.len())
+    }
+    
+    // 保持しなくなったバージョンのインスタンス・アーティファクト・キャッシュ報告を削除する
+    async fn discard_version(&self, metadata: &FunctionMetadata) {
+        self.pool.remove_version(metadata);
+        self.cache_repo.remove_cached(&metadata.function_id, &metadata.version).await;
+        if let Err(e) = self.artifacts.remove(&metadata.function_id, &metadata.version).await {
+            eprintln!("Failed to remove cached artifact of {} {}: {}", metadata.function_id, metadata.version, e);
+        }
+    }
+    
+    async fn rollback(&self, function_id: &str, version: Option<&str>) -> Result<String, String> {
+        let target = match version {
+            Some(version) => self.function_service.get_function_version(function_id, version).await
+                .ok_or_else(|| format!("Version {} of {} is not retained", version, function_id))?,
+            None => self.function_service.previous_version(function_id).await
+                .ok_or_else(|| format!("No previous version of {} to roll back to", function_id))?,
+        };
+        
+        let artifact_data = self.prepare(&target).await?;
+        let deactivated = match version {
+            Some(version) => self.function_service.activate_function(function_id, version).await?,
+            None => Some(self.function_service.rollback_function(function_id).await?),
+        };
+        self.switched(&target, deactivated, artifact_data).await;
+        
+        Ok(target.version)
+    }
+    
+    // アーティファクトの取得・検証・インスタンス化がすべて成功してから切り替える
+    async fn activate(&self, metadata: &FunctionMetadata) -> Result<(), String> {
+        let artifact_data = self.prepare(metadata).await?;
+        let deactivated = self.function_service.activate_function(&metadata.function_id, &metadata.version).await?;
+        self.switched(metadata, deactivated, artifact_data).await;
+        Ok(())
+    }
+    
+    // 有効なバージョンは変えず、ルートの split から振り分けられるようにする
+    async fn stage(&self, metadata: &FunctionMetadata) -> Result<(), String> {
+        let artifact_data = self.prepare(metadata).await?;
+        let replaced = self.function_service.mark_verified(&metadata.function_id, &metadata.version).await?;
+        self.switched(metadata, replaced, artifact_data).await;
+        Ok(())
+    }
+    
+    async fn prepare(&self, metadata: &FunctionMetadata) -> Result<Vec<u8>, String> {
+        let artifact_data = self.artifacts.fetch(metadata).await.map_err(|e| e.to_string())?;
+        
+        let pool = self.pool.clone();
+        let staged = metadata.clone();
+        let (artifact_data, verified) = self.executor.run(move || {
+            let verified = pool.verify(&staged, &artifact_data);
+            (artifact_data, verified)
+        }).await.map_err(|e| e.to_string())?;
+        verified.map_err(|e| e.to_string())?;
+        
+        Ok(artifact_data)
+    }
+    
+    async fn switched(&self, metadata: &FunctionMetadata, deactivated: Option<FunctionMetadata>, artifact_data: Vec<u8>) {
+        if let Some(deactivated) = deactivated {
+            self.pool.retire(&deactivated);
+        }
+        
+        self.cache_repo.add_cached(CachedFunction {
+            function_id: metadata.function_id.clone(),
+            version: metadata.version.clone(),
+            state: "cached".to_string(),
+        }).await;
+        
+        if metadata.min_warm > 0 {
+            self.prewarm(metadata.clone(), artifact_data).await;
+        }
+    }
+    
+    async fn prewarm(&self, metadata: FunctionMetadata, artifact_data: Vec<u8>) {
+        let pool = self.pool.clone();
+        let function_id = metadata.function_id.clone();
+        let result = self.executor.run(move || pool.prewarm(&metadata, &artifact_data)).await;
+        
+        match result.and_then(|r| r) {
+            Ok(created) => println!("Pre-warmed {} instance(s) of {}", created, function_id),
+            Err(e) => eprintln!("Failed to pre-warm {}: {}", function_id, e),
+        }
+    }
+    
+    // SQLite への書き込みはブロックするのでランタイムのスレッドでは行わない
+    pub async fn handle_kv_commands(&self, commands: Vec<KvCommand>) {
+        let kv = self.kv.clone();
+        let applied = tokio::task::spawn_blocking(move || {
+            for command in commands {
+                match kv.apply(&command) {
+                    Ok(Some(count)) => println!("KV {:?} for {}: {} key(s)", command.action, command.function_id, count),
+                    Ok(None) => println!("KV {:?} for {}: already applied", command.action, command.function_id),
+                    Err(e) => eprintln!("KV {:?} for {} failed: {:?}", command.action, command.function_id, e),
+                }
+            }
+        }).await;
+        if let Err(e) = applied {
+            eprintln!("KV commands failed: {}", e);
+        }
+    }
+    
+    async fn handle_routes(&self, routes: Vec<RouteDto>) {
+        let routes: Vec<Route> = routes.into_iter()
+            .map(|route_dto| Route {
                 id: route_dto.id,
                 host: route_dto.host,
                 path: route_dto.path,
                 function_id: route_dto.function_id,
                 methods: route_dto.methods,
                 priority: route_dto.priority,
-            };
-            
-            self.function_service.add_route(route).await;
+                split: route_dto.split,
+            })
+            .collect();
+        
+        let total = routes.len();
+        let rejected = self.function_service.replace_routes(routes).await;
+        for reason in &rejected {
+            eprintln!("Skipped route: {}", reason);
         }
+        println!("Synced {} route(s)", total - rejected.len());
     }
 }
 
-async fn download_artifact(url: &str) -> Result<Vec<u8>, String> {
-    let client = reqwest::Client::new();
-    let resp = client.get(url)
-        .send()
-        .await
-        .map_err(|e| format!("Download failed: {}", e))?;
-    
-    resp.bytes()
-        .await
-        .map(|b| b.to_vec())
-        .map_err(|e| format!("Failed to read response: {}", e))
-}
-
 pub struct InvocationService {
     function_service: Arc<FunctionService>,
     pool: Arc<HotInstancePool>,
-    wasm_bytes: Vec<u8>,
-    cache: Arc<crate::infrastructure::LocalWasmCache>,
+    executor: Arc<WasmExecutor>,
+    artifacts: Arc<ArtifactLoader>,
 }
 
 impl InvocationService {
     pub fn new(
         function_service: Arc<FunctionService>,
         pool: Arc<HotInstancePool>,
-        wasm_bytes: Vec<u8>,
-        cache: Arc<crate::infrastructure::LocalWasmCache>,
+        executor: Arc<WasmExecutor>,
+        artifacts: Arc<ArtifactLoader>,
     ) -> Self {
         Self {
             function_service,
             pool,
-            wasm_bytes,
-            cache,
+            executor,
+            artifacts,
         }
     }
     
-    pub async fn invoke(&self, host: &str, path: &str, method: &str) -> Result<Vec<u8>, String> {
-        let (metadata, _path_params) = self.function_service.resolve_function(host, path, method)
-            .await
-            .ok_or_else(|| "Route not found".to_string())?;
+    pub async fn invoke(&self, request: InvocationRequest) -> Result<InvocationResponse, InvocationError> {
+        let (metadata, route_match) = self.function_service.resolve_function(&request).await?;
+        let context = InvocationContext::new(&route_match, &request);
         
-        let wasm_bytes = if let Some(cached) = self.cache.get(&metadata.function_id, &metadata.version, &metadata.sha256).await {
-            cached
-        } else {
-            self.wasm_bytes.clone()
+        let result = self.execute(&metadata, request, context).await;
+        let outcome = if result.is_ok() { "ok" } else { "error" };
+        VERSION_INVOCATIONS.with_label_values(&[&metadata.function_id, &metadata.version, outcome]).inc();
+        result
+    }
+    
+    async fn execute(&self, metadata: &FunctionMetadata, request: InvocationRequest, context: InvocationContext) -> Result<InvocationResponse, InvocationError> {
+        // キャッシュから消えていれば取り直す。取得を待たずに 503 を返し、クライアントに再試行させる
+        let wasm_bytes = match self.artifacts.cached(metadata).await {
+            Some(cached) => cached,
+            None => {
+                self.artifacts.load(metadata);
+                return Err(InvocationError::Loading(metadata.function_id.clone()));
+            }
         };
+        let function = metadata.clone();
         
-        let mut pooled = self.pool.get_or_create(
-            &metadata.function_id,
-            &wasm_bytes,
-            metadata.memory_pages,
-        ).await?;
+        let mut lease = self.pool.checkout(metadata).await?;
         
-        let result = execute_wasm(&mut pooled, method, path)?;
+        // コンパイルと wasmer の呼び出しは同期的なので専用ワーカーで実行する
+        let pool = self.pool.clone();
+        let (lease, result, recycled) = self.executor.run(move || {
+            let result = pool.ensure_instance(&mut lease, &function, &wasm_bytes)
+                .and_then(|_| execute_wasm(lease.instance(), &request, &context, &function));
+            // トラップやタイムアウトの後はインスタンスの状態が信用できないので再利用しない
+            let recycled = match &result {
+                Ok(_) => pool.recycle(&mut lease, function.isolation),
+                Err(_) => Err("error"),
+            };
+            (lease, result, recycled)
+        }).await?;
         
-        self.pool.return_instance(&metadata.function_id, pooled).await;
+        match recycled {
+            Ok(()) => self.pool.return_instance(lease).await,
+            Err(reason) => self.pool.discard(lease, reason),
+        }
         
-        Ok(result)
+        InvocationResponse::decode(&result?)
     }
 }
 
-fn execute_wasm(pooled: &mut PooledInstance, method: &str, path: &str) -> Result<Vec<u8>, String> {
+// ゲスト ABI:
+//   alloc(len) -> ptr / dealloc(ptr, len) をエクスポートし、
+//   handle(method, path, headers, body の ptr/len) -> (ptr << 32) | len でエンベロープを返す。
+//   handle が 5 組目 (10 個目までの引数) を受け取る場合はルートのコンテキスト (JSON) も渡す。
+// 入力とレスポンスの領域は呼び出し後にランナーが dealloc する。
+fn execute_wasm(pooled: &mut PooledInstance, request: &InvocationRequest, context: &InvocationContext, function: &FunctionMetadata) -> Result<Vec<u8>, InvocationError> {
+    let max_execution_ms = function.max_execution_ms;
     let handle = pooled.instance.exports.get_function("handle")
-        .map_err(|_| "handle function not found".to_string())?;
+        .map_err(|_| InvocationError::Execution("handle function not found".to_string()))?
+        .clone();
+    let guest = GuestMemory::from_instance(&pooled.instance, &pooled.store).map_err(InvocationError::Execution)?;
+    
+    metering::set_budget(&mut pooled.store, &pooled.instance, max_execution_ms);
+    if let Some(env) = &pooled.wasi {
+        wasi::begin_invocation(&mut pooled.store, env, function, &request.request_id);
+    }
+    if let Some(env) = &pooled.host {
+        host::begin_invocation(&mut pooled.store, env, function, &request.request_id);
+    }
     
-    let memory = pooled.instance.exports.get_memory("memory")
-        .map_err(|_| "memory not found".to_string())?;
+    let target = request.target();
+    let headers = request.encode_headers();
+    let encoded_context = context.encode();
+    let mut inputs = vec![
+        request.method.as_bytes(),
+        target.as_bytes(),
+        headers.as_slice(),
+        request.body.as_slice(),
+    ];
+    // 4 組の引数しか受け取らない既存のモジュールにはコンテキストを渡さない
+    if handle.ty(&pooled.store).params().len() == (inputs.len() + 1) * 2 {
+        inputs.push(encoded_context.as_slice());
+    }
     
-    let method_bytes = method.as_bytes();
-    let path_bytes = path.as_bytes();
-    let response_buf = vec![0u8; 4096];
+    let mut allocations = Vec::with_capacity(inputs.len());
+    let mut written = Ok(());
+    for input in inputs {
+        match guest.write(&mut pooled.store, input) {
+            Ok(ptr) => allocations.push((ptr, input.len() as i32)),
+            Err(e) => {
+                written = Err(InvocationError::Execution(e));
+                break;
+            }
+        }
+    }
     
-    {
-        let mem_view = memory.view(&pooled.store);
-        let _ = mem_view.write(0, method_bytes);
-        let _ = mem_view.write(256, path_bytes);
-        let _ = mem_view.write(1024, &response_buf);
+    let result = written.and_then(|_| {
+        let args: Vec<wasmer::Value> = allocations.iter()
+            .flat_map(|(ptr, len)| [(*ptr).into(), (*len).into()])
+            .collect();
+        handle.call(&mut pooled.store, &args).map_err(|e| {
+            let deadline_exceeded = pooled.host.as_ref()
+                .is_some_and(|env| host::deadline_exceeded(&mut pooled.store, env));
+            if deadline_exceeded || metering::budget_exhausted(&mut pooled.store, &pooled.instance) {
+                InvocationError::Timeout { limit_ms: metering::effective_timeout_ms(max_execution_ms) }
+            } else {
+                InvocationError::Execution(e.to_string())
+            }
+        })
+    });
+    
+    for (ptr, len) in allocations {
+        guest.free(&mut pooled.store, ptr, len);
+    }
+    if let Some(env) = &pooled.wasi {
+        wasi::end_invocation(&mut pooled.store, env);
     }
     
-    let result = handle.call(&mut pooled.store, &[
-        0i32.into(), (method_bytes.len() as i32).into(),
-        256i32.into(), (path_bytes.len() as i32).into(),
-        512i32.into(), 0i32.into(),
-        768i32.into(), 0i32.into(),
-        1024i32.into(), (response_buf.len() as i32).into(),
-    ]).map_err(|e| format!("WASM error: {}", e))?;
+    let packed = result?
+        .first()
+        .and_then(|v| v.i64())
+        .ok_or_else(|| InvocationError::Execution("handle must return an i64 (ptr << 32 | len)".to_string()))?;
+    let (ptr, len) = ((packed >> 32) as u32, packed as u32);
     
-    let len = result[0].i32().unwrap_or(0) as usize;
-    let mut response_data = vec![0u8; len];
-    let mem_view = memory.view(&pooled.store);
-    let _ = mem_view.read(1024, &mut response_data);
+    let response = guest.read(&pooled.store, ptr, len).map_err(InvocationError::InvalidResponse);
+    guest.free(&mut pooled.store, ptr as i32, len as i32);
     
-    Ok(response_data)
+    response
 }
@@ -0,0 +1,49 @@
+use crate::application::dto::InvocationRequest;
+use crate::domain::{RouteTarget, StickyKey, TrafficSplit};
+use sha2::{Digest, Sha256};
+
+// 重みに従ってターゲットを 1 つ選ぶ。
+// sticky のキーがリクエストにあればその値のハッシュで、なければリクエスト ID で振り分ける
+pub fn pick_target<'a>(split: &'a TrafficSplit, request: &InvocationRequest) -> Option<&'a RouteTarget> {
+    let total: u64 = split.targets.iter().map(|t| t.weight as u64).sum();
+    if total == 0 {
+        return None;
+    }
+
+    let key = split.sticky.as_ref()
+        .and_then(|sticky| sticky_value(sticky, &request.headers))
+        .unwrap_or(request.request_id.as_str());
+
+    let mut point = bucket(key) % total;
+    for target in &split.targets {
+        let weight = target.weight as u64;
+        if point < weight {
+            return Some(target);
+        }
+        point -= weight;
+    }
+    None
+}
+
+fn sticky_value<'a>(sticky: &StickyKey, headers: &'a [(String, String)]) -> Option<&'a str> {
+    match sticky {
+        StickyKey::Header(name) => headers.iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str()),
+        StickyKey::Cookie(name) => headers.iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case("cookie"))
+            .flat_map(|(_, value)| value.split(';'))
+            .find_map(|pair| {
+                let (key, value) = pair.trim().split_once('=')?;
+                (key == name).then_some(value)
+            }),
+    }
+}
+
+// ノード間で同じ値が同じターゲットになるよう、プロセスに依存しないハッシュを使う
+fn bucket(key: &str) -> u64 {
+    let digest = Sha256::digest(key.as_bytes());
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    u64::from_be_bytes(bytes)
+}
@@ -0,0 +1,75 @@
+#[cfg(test)]
+mod tests {
+    use crate::application::dto::InvocationRequest;
+    use crate::application::traffic::pick_target;
+    use crate::domain::{RouteTarget, StickyKey, TrafficSplit};
+
+    fn traffic_split(weights: &[(&str, u32)], sticky: Option<StickyKey>) -> TrafficSplit {
+        TrafficSplit {
+            targets: weights.iter()
+                .map(|(version, weight)| RouteTarget {
+                    function_id: "func1".to_string(),
+                    version: version.to_string(),
+                    weight: *weight,
+                })
+                .collect(),
+            sticky,
+        }
+    }
+
+    fn request(request_id: &str, headers: &[(&str, &str)]) -> InvocationRequest {
+        InvocationRequest {
+            request_id: request_id.to_string(),
+            method: "GET".to_string(),
+            path: "/".to_string(),
+            query: None,
+            host: "localhost".to_string(),
+            headers: headers.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect(),
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_weights_are_respected() {
+        let split = traffic_split(&[("1.0.0", 95), ("2.0.0", 5)], None);
+
+        let canary = (0..10_000)
+            .filter(|i| pick_target(&split, &request(&format!("req-{}", i), &[])).unwrap().version == "2.0.0")
+            .count();
+        // 5% ± 1%
+        assert!((400..=600).contains(&canary), "canary got {} of 10000", canary);
+    }
+
+    #[test]
+    fn test_zero_weight_target_never_selected() {
+        let split = traffic_split(&[("1.0.0", 1), ("2.0.0", 0)], None);
+        for i in 0..100 {
+            assert_eq!(pick_target(&split, &request(&format!("req-{}", i), &[])).unwrap().version, "1.0.0");
+        }
+
+        let empty = traffic_split(&[("1.0.0", 0)], None);
+        assert!(pick_target(&empty, &request("req", &[])).is_none());
+    }
+
+    #[test]
+    fn test_sticky_header_pins_target() {
+        let split = traffic_split(&[("1.0.0", 50), ("2.0.0", 50)], Some(StickyKey::Header("x-user-id".to_string())));
+
+        let first = pick_target(&split, &request("req-1", &[("X-User-Id", "alice")])).unwrap().version.clone();
+        for i in 0..50 {
+            let picked = pick_target(&split, &request(&format!("req-{}", i), &[("x-user-id", "alice")])).unwrap();
+            assert_eq!(picked.version, first);
+        }
+    }
+
+    #[test]
+    fn test_sticky_cookie_pins_target() {
+        let split = traffic_split(&[("1.0.0", 50), ("2.0.0", 50)], Some(StickyKey::Cookie("session".to_string())));
+
+        let first = pick_target(&split, &request("req-1", &[("cookie", "theme=dark; session=abc")])).unwrap().version.clone();
+        for i in 0..50 {
+            let picked = pick_target(&split, &request(&format!("req-{}", i), &[("Cookie", "session=abc")])).unwrap();
+            assert_eq!(picked.version, first);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use std::fmt;
+
+// リクエストの処理に失敗した理由。HTTP ステータスへの対応は presentation 層で行う
+#[derive(Debug, Clone, PartialEq)]
+pub enum InvocationError {
+    // パスに一致するルートがない
+    RouteNotFound,
+    // パスは一致したがメソッドが許可されていない。allowed は Allow ヘッダーに使う
+    MethodNotAllowed { allowed: Vec<String> },
+    // ルートの関数がこのノードで有効になっていない
+    FunctionNotFound(String),
+    // 関数ごとの同時実行数の上限に達した
+    Throttled(String),
+    // ワーカーやインスタンスプールに空きがない
+    Overloaded(String),
+    // アーティファクトを取得中でまだ実行できない
+    Loading(String),
+    Timeout { limit_ms: u32 },
+    // コンパイル・インスタンス化の失敗
+    Instantiation(String),
+    // トラップやエクスポートの不足など、ゲストの実行の失敗
+    Execution(String),
+    // ゲストが返したレスポンスエンベロープが不正
+    InvalidResponse(String),
+    Download(String),
+    // アーティファクトがキャッシュの上限より大きい
+    ArtifactTooLarge { limit_bytes: u64 },
+    ChecksumMismatch { expected: String, actual: String },
+    // ローカルのアーティファクトキャッシュの読み書き
+    Cache(String),
+    ControlPlane(String),
+    Internal(String),
+}
+
+impl InvocationError {
+    // エラーレスポンスの "error" に入れる識別子
+    pub fn kind(&self) -> &'static str {
+        match self {
+            InvocationError::RouteNotFound => "route_not_found",
+            InvocationError::MethodNotAllowed { .. } => "method_not_allowed",
+            InvocationError::FunctionNotFound(_) => "function_not_found",
+            InvocationError::Throttled(_) => "throttled",
+            InvocationError::Overloaded(_) => "overloaded",
+            InvocationError::Loading(_) => "loading",
+            InvocationError::Timeout { .. } => "timeout",
+            InvocationError::Instantiation(_) => "instantiation_failed",
+            InvocationError::Execution(_) => "execution_failed",
+            InvocationError::InvalidResponse(_) => "invalid_response",
+            InvocationError::Download(_) => "download_failed",
+            InvocationError::ArtifactTooLarge { .. } => "artifact_too_large",
+            InvocationError::ChecksumMismatch { .. } => "checksum_mismatch",
+            InvocationError::Cache(_) => "cache_error",
+            InvocationError::ControlPlane(_) => "control_plane_error",
+            InvocationError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl fmt::Display for InvocationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvocationError::RouteNotFound => write!(f, "Route not found"),
+            InvocationError::MethodNotAllowed { allowed } => write!(f, "Method not allowed (allowed: {})", allowed.join(", ")),
+            InvocationError::FunctionNotFound(function_id) => write!(f, "Function {} is not deployed", function_id),
+            InvocationError::Throttled(function_id) => write!(f, "Function concurrency limit reached for {}", function_id),
+            InvocationError::Overloaded(reason) => write!(f, "{}", reason),
+            InvocationError::Loading(function_id) => write!(f, "Artifact of {} is loading, retry shortly", function_id),
+            InvocationError::Timeout { limit_ms } => write!(f, "Execution timed out after {}ms", limit_ms),
+            InvocationError::Instantiation(e) => write!(f, "Instantiation failed: {}", e),
+            InvocationError::Execution(e) => write!(f, "WASM error: {}", e),
+            InvocationError::InvalidResponse(e) => write!(f, "Invalid response envelope: {}", e),
+            InvocationError::Download(e) => write!(f, "Artifact download failed: {}", e),
+            InvocationError::ArtifactTooLarge { limit_bytes } => write!(f, "Artifact exceeds the {} byte limit", limit_bytes),
+            InvocationError::ChecksumMismatch { expected, actual } => write!(f, "SHA256 mismatch (expected {}, got {})", expected, actual),
+            InvocationError::Cache(e) => write!(f, "Artifact cache error: {}", e),
+            InvocationError::ControlPlane(e) => write!(f, "Control plane request failed: {}", e),
+            InvocationError::Internal(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for InvocationError {}
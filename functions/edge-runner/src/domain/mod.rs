@@ -1,5 +1,7 @@
 pub mod models;
 pub mod repository;
+pub mod error;
 
 pub use models::*;
 pub use repository::*;
+pub use error::*;
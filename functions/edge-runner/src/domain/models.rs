@@ -8,6 +8,19 @@ pub struct CachedFunction {
     pub state: String,
 }
 
+// リクエスト間でインスタンスの状態をどう扱うか
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IsolationPolicy {
+    // 線形メモリとグローバルをそのまま次のリクエストに引き継ぐ
+    #[default]
+    Reuse,
+    // インスタンス化直後のメモリスナップショットに戻してから再利用する
+    Reset,
+    // 1 リクエストごとに破棄する
+    SingleUse,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct FunctionMetadata {
     pub function_id: String,
@@ -16,6 +29,16 @@ pub struct FunctionMetadata {
     pub sha256: String,
     pub memory_pages: u32,
     pub max_execution_ms: u32,
+    pub max_concurrency: u32,
+    pub min_warm: u32,
+    pub isolation: IsolationPolicy,
+    pub wasi: bool,
+    // WASI で読み取り専用に公開するホスト側ディレクトリ
+    pub wasi_preopen_dir: Option<String>,
+    // http_fetch で呼び出せるホスト ("*.example.com" 形式も可)。空なら外部呼び出し不可
+    pub allowed_hosts: Vec<String>,
+    // edge-agent の telemetry_data への読み書きを許可する
+    pub telemetry: bool,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -26,6 +49,32 @@ pub struct Route {
     pub function_id: String,
     pub methods: Vec<String>,
     pub priority: i32,
+    // 指定がなければ function_id の有効なバージョンにすべて送る
+    #[serde(default)]
+    pub split: Option<TrafficSplit>,
+}
+
+// ルートのトラフィックをバージョン間で重み付きに振り分ける
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TrafficSplit {
+    pub targets: Vec<RouteTarget>,
+    // 同じキーのリクエストは常に同じターゲットに送る。なければリクエストごとに振り分ける
+    #[serde(default)]
+    pub sticky: Option<StickyKey>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RouteTarget {
+    pub function_id: String,
+    pub version: String,
+    pub weight: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "name", rename_all = "snake_case")]
+pub enum StickyKey {
+    Header(String),
+    Cookie(String),
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -36,18 +85,90 @@ pub struct DeploymentNotification {
     pub sha256: String,
     pub memory_pages: i32,
     pub max_execution_ms: i32,
+    #[serde(default)]
+    pub max_concurrency: i32,
+    #[serde(default)]
+    pub min_warm: i32,
+    #[serde(default)]
+    pub isolation: IsolationPolicy,
+    #[serde(default)]
+    pub wasi: bool,
+    #[serde(default)]
+    pub wasi_preopen_dir: Option<String>,
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+    #[serde(default)]
+    pub telemetry: bool,
+    // 有効化せず、ルートの split から参照できる状態にだけする
+    #[serde(default)]
+    pub canary: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KvAction {
+    // entries を追加/上書きする
+    Seed,
+    // 名前空間のキーをすべて削除する
+    Clear,
+}
+
+// ハートビート応答で届く KV 名前空間の操作
+#[derive(Clone, Serialize, Deserialize)]
+pub struct KvCommand {
+    // 名前空間ごとに増えていく番号。再送されたコマンドを二重に適用しないために使う
+    #[serde(default)]
+    pub command_id: Option<u64>,
+    pub function_id: String,
+    pub action: KvAction,
+    #[serde(default)]
+    pub entries: HashMap<String, String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FunctionAction {
+    // version で指定したバージョン、省略時は直前に有効だったバージョンに戻す
+    Rollback,
+    // 全バージョンを削除する (ルートはそのまま 404 になる)
+    Undeploy,
+}
+
+// ハートビート応答で届く関数単位の操作
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FunctionCommand {
+    pub function_id: String,
+    pub action: FunctionAction,
+    #[serde(default)]
+    pub version: Option<String>,
 }
 
 #[derive(Clone, Debug)]
 pub struct RouteMatch {
+    pub route_id: String,
     pub function_id: String,
     pub path_params: HashMap<String, String>,
+    // catch-all (`*` / `*name`) に一致した残りのパス
+    pub rest: Option<String>,
+    pub split: Option<TrafficSplit>,
 }
 
 pub struct PooledInstance {
     pub instance: wasmer::Instance,
     pub store: wasmer::Store,
     pub last_used: u64,
+    // IsolationPolicy::Reset 用の初期状態
+    pub snapshot: Option<InstanceSnapshot>,
+    // WASI を有効にした関数のみ
+    pub wasi: Option<wasmer::FunctionEnv<crate::infrastructure::wasi::WasiState>>,
+    // edgebase 名前空間のホスト関数をインポートしている場合のみ
+    pub host: Option<wasmer::FunctionEnv<crate::infrastructure::host::HostState>>,
+}
+
+// インスタンス化直後の線形メモリと、エクスポートされた可変グローバルの値
+pub struct InstanceSnapshot {
+    pub memory: Vec<u8>,
+    pub globals: Vec<(wasmer::Global, wasmer::Value)>,
 }
 
 #[derive(Clone)]
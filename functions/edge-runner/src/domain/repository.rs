@@ -1,26 +1,51 @@
+use crate::domain::error::InvocationError;
 use crate::domain::models::*;
 use async_trait::async_trait;
 
 #[async_trait]
 pub trait FunctionRepository: Send + Sync {
-    async fn register(&self, metadata: FunctionMetadata);
+    // バージョンを登録する (有効化はしない)。保持数を超えて削除したバージョンを返す
+    async fn register(&self, metadata: FunctionMetadata) -> Vec<FunctionMetadata>;
+    // 有効なバージョン
     async fn get(&self, function_id: &str) -> Option<FunctionMetadata>;
-    #[allow(dead_code)]
-    async fn remove(&self, function_id: &str);
+    // 別のアーティファクトで再登録中 (未検証) ならそちらを返す
+    async fn get_version(&self, function_id: &str, version: &str) -> Option<FunctionMetadata>;
+    // トラフィックを送れる (検証済みの) バージョン
+    async fn get_verified(&self, function_id: &str, version: &str) -> Option<FunctionMetadata>;
+    // ロールバック先 (直前に有効だったバージョン)
+    async fn previous(&self, function_id: &str) -> Option<FunctionMetadata>;
+    // 有効化せずにトラフィックを送れる状態にする (カナリア)。
+    // 再登録されたアーティファクトに差し替えた場合は差し替え前のメタデータを返す
+    async fn mark_verified(&self, function_id: &str, version: &str) -> Result<Option<FunctionMetadata>, String>;
+    // 登録済みのバージョンを有効化し、それまで有効だったバージョンを返す。
+    // 再登録されたアーティファクトは検証後のここで初めて get() から見えるようになる
+    async fn activate(&self, function_id: &str, version: &str) -> Result<Option<FunctionMetadata>, String>;
+    // 直前に有効だったバージョンに戻し、無効化したバージョンを返す
+    async fn rollback(&self, function_id: &str) -> Result<FunctionMetadata, String>;
+    // 関数を全バージョン削除し、削除したバージョンを返す
+    async fn remove(&self, function_id: &str) -> Vec<FunctionMetadata>;
+    async fn function_ids(&self) -> Vec<String>;
 }
 
 #[async_trait]
 pub trait RouteRepository: Send + Sync {
-    async fn add_route(&self, route: Route);
-    async fn match_route(&self, host: &str, path: &str, method: &str) -> Option<RouteMatch>;
-    #[allow(dead_code)]
+    // id が同じルートは置き換える。同じ host/path でメソッドが重なる別のルートがあればエラー
+    async fn add_route(&self, route: Route) -> Result<(), String>;
+    // ルート全体を置き換える (コントロールプレーンとの同期用)。衝突して読み込まなかったルートの理由を返す
+    async fn replace_routes(&self, routes: Vec<Route>) -> Vec<String>;
+    async fn remove_route(&self, id: &str) -> Option<Route>;
+    // パスに一致するルートがなければ RouteNotFound、メソッドだけが一致しなければ MethodNotAllowed
+    async fn match_route(&self, host: &str, path: &str, method: &str) -> Result<RouteMatch, InvocationError>;
+    // マッチングの評価順
     async fn list_routes(&self) -> Vec<Route>;
 }
 
 #[async_trait]
 pub trait CacheRepository: Send + Sync {
     async fn get_cached(&self) -> Vec<CachedFunction>;
+    // 同じ function_id/version のエントリは置き換える
     async fn add_cached(&self, func: CachedFunction);
+    async fn remove_cached(&self, function_id: &str, version: &str);
     #[allow(dead_code)]
     async fn clear_cached(&self);
 }
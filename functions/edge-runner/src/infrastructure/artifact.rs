@@ -0,0 +1,190 @@
+use crate::domain::{FunctionMetadata, InvocationError};
+use crate::infrastructure::cache::LocalWasmCache;
+use futures::future::{BoxFuture, FutureExt, Shared};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+type Pending = Shared<BoxFuture<'static, Result<Vec<u8>, InvocationError>>>;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// レスポンスが止まったまま次のデータが届かなければ打ち切る
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+// 失敗したダウンロードは 1s, 2s, 4s ... と間隔を空けて取り直す
+const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(300);
+
+// 直近に失敗したダウンロード。別のアーティファクトで再デプロイされたら待たずに取り直す
+struct Failure {
+    sha256: String,
+    attempts: u32,
+    retry_at: Instant,
+    error: InvocationError,
+}
+
+// デプロイされたアーティファクトをキャッシュから読み、なければダウンロードしてキャッシュする。
+// 同じ function_id/version のダウンロードは同時に 1 つだけ行い、待っている呼び出しで結果を共有する
+pub struct ArtifactLoader {
+    cache: Arc<LocalWasmCache>,
+    client: reqwest::Client,
+    in_flight: Mutex<HashMap<String, Pending>>,
+    failures: Mutex<HashMap<String, Failure>>,
+    retry_backoff: Duration,
+}
+
+impl ArtifactLoader {
+    pub fn new(cache: Arc<LocalWasmCache>) -> Self {
+        let client = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self {
+            cache,
+            client,
+            in_flight: Mutex::new(HashMap::new()),
+            failures: Mutex::new(HashMap::new()),
+            retry_backoff: RETRY_BACKOFF,
+        }
+    }
+
+    // 失敗後に最初に取り直すまでの間隔
+    #[cfg(test)]
+    pub fn with_retry_backoff(mut self, retry_backoff: Duration) -> Self {
+        self.retry_backoff = retry_backoff;
+        self
+    }
+
+    pub async fn cached(&self, metadata: &FunctionMetadata) -> Option<Vec<u8>> {
+        self.cache.get(&metadata.function_id, &metadata.version, &metadata.sha256).await
+    }
+
+    // キャッシュに残っているアーティファクトの (function_id, version)
+    pub async fn cached_artifacts(&self) -> Vec<(String, String)> {
+        self.cache.cached_artifacts().await
+    }
+
+    pub async fn remove(&self, function_id: &str, version: &str) -> Result<(), InvocationError> {
+        lock(&self.failures).remove(&format!("{}@{}", function_id, version));
+        self.cache.remove(function_id, version).await
+    }
+
+    // キャッシュになければダウンロードが終わるまで待つ
+    pub async fn fetch(self: &Arc<Self>, metadata: &FunctionMetadata) -> Result<Vec<u8>, InvocationError> {
+        if let Some(cached) = self.cached(metadata).await {
+            return Ok(cached);
+        }
+        self.pending(metadata).0.await
+    }
+
+    // 待たずにバックグラウンドで取得を始める。すでに取得中なら何もしない
+    pub fn load(self: &Arc<Self>, metadata: &FunctionMetadata) {
+        let (pending, started) = self.pending(metadata);
+        if !started {
+            return;
+        }
+
+        let label = format!("{} {}", metadata.function_id, metadata.version);
+        tokio::spawn(async move {
+            match pending.await {
+                Ok(_) => println!("Loaded artifact of {}", label),
+                Err(e) => eprintln!("Failed to load artifact of {}: {}", label, e),
+            }
+        });
+    }
+
+    // 取得中のダウンロードに合流するか、新しく始める。新しく始めた場合は true。
+    // 直前に失敗して再試行の時刻になっていなければ、取りに行かずに前回のエラーを返す
+    fn pending(self: &Arc<Self>, metadata: &FunctionMetadata) -> (Pending, bool) {
+        let key = key(metadata);
+        let mut in_flight = lock(&self.in_flight);
+        if let Some(pending) = in_flight.get(&key) {
+            return (pending.clone(), false);
+        }
+        if let Some(failure) = lock(&self.failures).get(&key) {
+            if failure.sha256 == metadata.sha256 && Instant::now() < failure.retry_at {
+                let error = failure.error.clone();
+                return (futures::future::ready(Err(error)).boxed().shared(), false);
+            }
+        }
+
+        let loader = self.clone();
+        let metadata = metadata.clone();
+        let done = key.clone();
+        let pending = async move {
+            let result = loader.download(&metadata).await;
+            loader.record(&done, &metadata.sha256, &result);
+            // キャッシュへの書き込みが終わってから外すので、後続の呼び出しはキャッシュに当たる
+            lock(&loader.in_flight).remove(&done);
+            result
+        }.boxed().shared();
+
+        in_flight.insert(key, pending.clone());
+        (pending, true)
+    }
+
+    fn record(&self, key: &str, sha256: &str, result: &Result<Vec<u8>, InvocationError>) {
+        let mut failures = lock(&self.failures);
+        let error = match result {
+            Ok(_) => {
+                failures.remove(key);
+                return;
+            }
+            Err(e) => e.clone(),
+        };
+
+        let attempts = failures.get(key)
+            .filter(|failure| failure.sha256 == sha256)
+            .map_or(1, |failure| failure.attempts + 1);
+        let backoff = self.retry_backoff
+            .saturating_mul(1 << (attempts - 1).min(16))
+            .min(MAX_RETRY_BACKOFF);
+        failures.insert(key.to_string(), Failure {
+            sha256: sha256.to_string(),
+            attempts,
+            retry_at: Instant::now() + backoff,
+            error,
+        });
+    }
+
+    async fn download(&self, metadata: &FunctionMetadata) -> Result<Vec<u8>, InvocationError> {
+        let download_error = |e: reqwest::Error| InvocationError::Download(e.to_string());
+        let mut resp = self.client.get(&metadata.artifact_url)
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .map_err(download_error)?;
+
+        // キャッシュに収まらないアーティファクトはメモリに読み込む前に打ち切る
+        let limit_bytes = self.cache.max_size_bytes();
+        if resp.content_length().is_some_and(|len| len > limit_bytes) {
+            return Err(InvocationError::ArtifactTooLarge { limit_bytes });
+        }
+
+        let mut data = Vec::new();
+        loop {
+            let chunk = tokio::time::timeout(READ_TIMEOUT, resp.chunk())
+                .await
+                .map_err(|_| InvocationError::Download(format!("no data received for {}s", READ_TIMEOUT.as_secs())))?
+                .map_err(download_error)?;
+            match chunk {
+                // Content-Length がない、または偽っている場合
+                Some(chunk) if (data.len() + chunk.len()) as u64 > limit_bytes => {
+                    return Err(InvocationError::ArtifactTooLarge { limit_bytes });
+                }
+                Some(chunk) => data.extend_from_slice(&chunk),
+                None => break,
+            }
+        }
+
+        self.cache.put(&metadata.function_id, &metadata.version, &data, &metadata.sha256).await?;
+        Ok(data)
+    }
+}
+
+fn key(metadata: &FunctionMetadata) -> String {
+    format!("{}@{}", metadata.function_id, metadata.version)
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
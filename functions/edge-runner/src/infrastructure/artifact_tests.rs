@@ -0,0 +1,176 @@
+#[cfg(test)]
+mod tests {
+    use crate::domain::*;
+    use crate::infrastructure::*;
+    use crate::test_support;
+    use axum::{routing::get, Router};
+    use sha2::{Digest, Sha256};
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const ARTIFACT: &[u8] = b"\0asm artifact bytes";
+
+    // ダウンロード回数を数えるモックのアーティファクトストア
+    async fn artifact_server(downloads: Arc<AtomicUsize>) -> SocketAddr {
+        let app = Router::new().route("/artifact.wasm", get(move || {
+            let downloads = downloads.clone();
+            async move {
+                downloads.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                ARTIFACT
+            }
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    fn loader(name: &str) -> Arc<ArtifactLoader> {
+        loader_with_limit(name, 1024 * 1024)
+    }
+
+    fn loader_with_limit(name: &str, max_size_bytes: u64) -> Arc<ArtifactLoader> {
+        let dir = test_support::temp_dir("artifact-test", name);
+        let cache = Arc::new(LocalWasmCache::new(&dir, max_size_bytes).unwrap());
+        Arc::new(ArtifactLoader::new(cache).with_retry_backoff(Duration::from_millis(300)))
+    }
+
+    // Content-Length を付けずに分割して送る
+    async fn chunked_server() -> SocketAddr {
+        let app = Router::new().route("/artifact.wasm", get(|| async {
+            let chunks = ARTIFACT.chunks(4).map(|chunk| Ok::<_, std::io::Error>(chunk.to_vec()));
+            axum::body::Body::from_stream(futures::stream::iter(chunks.collect::<Vec<_>>()))
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    fn metadata(addr: SocketAddr, sha256: String) -> FunctionMetadata {
+        FunctionMetadata {
+            artifact_url: format!("http://{}/artifact.wasm", addr),
+            sha256,
+            ..test_support::metadata("func1", "1.0.0")
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_concurrent_fetches_share_one_download() {
+        let downloads = Arc::new(AtomicUsize::new(0));
+        let addr = artifact_server(downloads.clone()).await;
+        let loader = loader("single-flight");
+        let metadata = metadata(addr, format!("{:x}", Sha256::digest(ARTIFACT)));
+
+        let fetches = (0..8).map(|_| {
+            let loader = loader.clone();
+            let metadata = metadata.clone();
+            tokio::spawn(async move { loader.fetch(&metadata).await })
+        });
+        for fetch in futures::future::join_all(fetches).await {
+            assert_eq!(fetch.unwrap().unwrap(), ARTIFACT);
+        }
+        assert_eq!(downloads.load(Ordering::SeqCst), 1);
+
+        // 以降はキャッシュから読む
+        assert_eq!(loader.fetch(&metadata).await.unwrap(), ARTIFACT);
+        assert_eq!(loader.cached(&metadata).await.unwrap(), ARTIFACT);
+        assert_eq!(downloads.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_failed_download_is_retried_after_backoff() {
+        let downloads = Arc::new(AtomicUsize::new(0));
+        let addr = artifact_server(downloads.clone()).await;
+        let loader = loader("mismatch");
+        let metadata = metadata(addr, "0".repeat(64));
+
+        assert!(matches!(loader.fetch(&metadata).await, Err(InvocationError::ChecksumMismatch { .. })));
+        assert!(loader.cached(&metadata).await.is_none());
+
+        // 再試行までは取りに行かずに前回のエラーを返す
+        assert!(matches!(loader.fetch(&metadata).await, Err(InvocationError::ChecksumMismatch { .. })));
+        loader.load(&metadata);
+        assert_eq!(downloads.load(Ordering::SeqCst), 1);
+
+        tokio::time::sleep(Duration::from_millis(350)).await;
+        assert!(loader.fetch(&metadata).await.is_err());
+        assert_eq!(downloads.load(Ordering::SeqCst), 2);
+
+        // 2 回目の失敗で間隔が延びる
+        tokio::time::sleep(Duration::from_millis(350)).await;
+        assert!(loader.fetch(&metadata).await.is_err());
+        assert_eq!(downloads.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_redeployed_artifact_skips_backoff() {
+        let downloads = Arc::new(AtomicUsize::new(0));
+        let addr = artifact_server(downloads.clone()).await;
+        let loader = loader("redeploy");
+
+        assert!(loader.fetch(&metadata(addr, "0".repeat(64))).await.is_err());
+        let fixed = metadata(addr, format!("{:x}", Sha256::digest(ARTIFACT)));
+        assert_eq!(loader.fetch(&fixed).await.unwrap(), ARTIFACT);
+        assert_eq!(downloads.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_unreachable_store_fails_without_hanging() {
+        let loader = loader("unreachable");
+        // 何も待ち受けていないポート
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let result = tokio::time::timeout(Duration::from_secs(5), loader.fetch(&metadata(addr, "0".repeat(64)))).await;
+        assert!(matches!(result, Ok(Err(InvocationError::Download(_)))));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_background_load_fills_cache() {
+        let downloads = Arc::new(AtomicUsize::new(0));
+        let addr = artifact_server(downloads.clone()).await;
+        let loader = loader("background");
+        let metadata = metadata(addr, format!("{:x}", Sha256::digest(ARTIFACT)));
+
+        loader.load(&metadata);
+        loader.load(&metadata);
+        assert!(loader.cached(&metadata).await.is_none());
+
+        for _ in 0..50 {
+            if loader.cached(&metadata).await.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(loader.cached(&metadata).await.unwrap(), ARTIFACT);
+        assert_eq!(downloads.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_artifact_larger_than_cache_is_rejected() {
+        let addr = artifact_server(Arc::new(AtomicUsize::new(0))).await;
+        let loader = loader_with_limit("too-large", 8);
+        let metadata = metadata(addr, format!("{:x}", Sha256::digest(ARTIFACT)));
+
+        assert_eq!(loader.fetch(&metadata).await, Err(InvocationError::ArtifactTooLarge { limit_bytes: 8 }));
+        assert!(loader.cached(&metadata).await.is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_streamed_artifact_is_cut_off_at_limit() {
+        let addr = chunked_server().await;
+        let metadata = metadata(addr, format!("{:x}", Sha256::digest(ARTIFACT)));
+
+        let loader = loader_with_limit("streamed-too-large", 8);
+        assert_eq!(loader.fetch(&metadata).await, Err(InvocationError::ArtifactTooLarge { limit_bytes: 8 }));
+
+        // 上限ちょうどまでは受け取る
+        let loader = loader_with_limit("streamed", ARTIFACT.len() as u64);
+        assert_eq!(loader.fetch(&metadata).await.unwrap(), ARTIFACT);
+    }
+}
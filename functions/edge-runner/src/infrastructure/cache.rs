@@ -1,149 +1,441 @@
-use std::collections::HashMap;
+use crate::domain::InvocationError;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
 use tokio::sync::RwLock;
 use sha2::{Sha256, Digest};
 use std::time::SystemTime;
 
-#[derive(Clone)]
+// キャッシュディレクトリ直下に置く索引。再起動後にエントリを復元するために使う
+const INDEX_FILE: &str = "index.json";
+// アーティファクトの実体は内容の sha256 を名前にしてここに置く。同じ内容は関数をまたいで 1 つだけ保存する
+const OBJECTS_DIR: &str = "objects";
+
+#[derive(Clone, Serialize, Deserialize)]
 struct CacheEntry {
-    path: PathBuf,
+    function_id: String,
+    version: String,
     size: u64,
     sha256: String,
     last_used: u64,
 }
 
+// エントリとサイズは同じロックで更新する
+#[derive(Default)]
+struct CacheState {
+    // "{function_id}/{version}" -> エントリ
+    entries: HashMap<String, CacheEntry>,
+    // 索引を失ったときにオブジェクトを読み直して見つけた、どのバージョンのものか分からない内容。
+    // sha256 -> (サイズ, 最終更新時刻)。同じ内容の get/put があった時点でそのエントリになる
+    unclaimed: HashMap<String, (u64, u64)>,
+    // 参照されているオブジェクトと unclaimed の合計サイズ。同じ内容のオブジェクトは 1 回だけ数える
+    current_size: u64,
+}
+
+impl CacheState {
+    fn referenced(&self, sha256: &str) -> bool {
+        self.entries.values().any(|e| e.sha256 == sha256)
+    }
+
+    // オブジェクトがディスクにあるか
+    fn stored(&self, sha256: &str) -> bool {
+        self.unclaimed.contains_key(sha256) || self.referenced(sha256)
+    }
+
+    // unclaimed の内容をエントリとして引き取る。サイズは insert で数え直す
+    fn claim(&mut self, sha256: &str) -> bool {
+        match self.unclaimed.remove(sha256) {
+            Some((size, _)) => {
+                self.current_size -= size;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 上書きで参照されなくなったオブジェクトがあればその sha256 を返す
+    fn insert(&mut self, key: String, entry: CacheEntry) -> Option<String> {
+        if !self.referenced(&entry.sha256) {
+            self.current_size += entry.size;
+        }
+        let sha256 = entry.sha256.clone();
+        match self.entries.insert(key, entry) {
+            Some(old) if old.sha256 != sha256 && !self.referenced(&old.sha256) => {
+                self.current_size -= old.size;
+                Some(old.sha256)
+            }
+            _ => None,
+        }
+    }
+
+    // エントリを外し、参照されなくなったオブジェクトがあればその sha256 を返す
+    fn release(&mut self, key: &str) -> Option<String> {
+        let entry = self.entries.remove(key)?;
+        if self.referenced(&entry.sha256) {
+            return None;
+        }
+        self.current_size -= entry.size;
+        Some(entry.sha256)
+    }
+
+    // 上限に収まるまで unclaimed、次に最も長く使われていないエントリの順に外し、削除すべきオブジェクトを返す
+    fn evict(&mut self, max_size_bytes: u64) -> Vec<String> {
+        let mut orphaned = Vec::new();
+        while self.current_size > max_size_bytes {
+            let oldest = self.unclaimed.iter().min_by_key(|(_, (_, last_used))| *last_used).map(|(sha256, _)| sha256.clone());
+            if let Some(sha256) = oldest {
+                self.claim(&sha256);
+                orphaned.push(sha256);
+                continue;
+            }
+
+            let oldest = match self.entries.iter().min_by_key(|(_, e)| e.last_used) {
+                Some((key, _)) => key.clone(),
+                None => break,
+            };
+            orphaned.extend(self.release(&oldest));
+        }
+        orphaned
+    }
+}
+
 pub struct LocalWasmCache {
     cache_dir: PathBuf,
-    entries: Arc<RwLock<HashMap<String, CacheEntry>>>,
+    state: RwLock<CacheState>,
     max_size_bytes: u64,
-    current_size: Arc<RwLock<u64>>,
+    fsync: bool,
 }
 
 impl LocalWasmCache {
+    // 索引から前回のエントリを復元する。起動時に一度だけ呼ばれ、ファイルを読み直すのでブロックする
     pub fn new(cache_dir: impl AsRef<Path>, max_size_bytes: u64) -> std::io::Result<Self> {
         let cache_dir = cache_dir.as_ref().to_path_buf();
-        std::fs::create_dir_all(&cache_dir)?;
-        
+        std::fs::create_dir_all(cache_dir.join(OBJECTS_DIR))?;
+
+        let state = rebuild_index(&cache_dir, max_size_bytes);
+        write_index(&cache_dir, &state.entries)?;
+
         Ok(Self {
             cache_dir,
-            entries: Arc::new(RwLock::new(HashMap::new())),
+            state: RwLock::new(state),
             max_size_bytes,
-            current_size: Arc::new(RwLock::new(0)),
+            fsync: false,
         })
     }
-    
+
+    pub fn max_size_bytes(&self) -> u64 {
+        self.max_size_bytes
+    }
+
+    // 書き込んだファイルとディレクトリを rename の前後で fsync する。
+    // 遅くなる代わりに、電源断の後も索引が指すファイルが欠けない
+    pub fn with_fsync(mut self, fsync: bool) -> Self {
+        self.fsync = fsync;
+        self
+    }
+
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
+
+    // キャッシュ済みの (function_id, version)。起動直後のハートビートで報告する
+    pub async fn cached_artifacts(&self) -> Vec<(String, String)> {
+        let mut cached: Vec<(String, String)> = self.state.read().await
+            .entries
+            .values()
+            .map(|e| (e.function_id.clone(), e.version.clone()))
+            .collect();
+        cached.sort();
+        cached
+    }
+
     pub async fn get(&self, function_id: &str, version: &str, expected_sha256: &str) -> Option<Vec<u8>> {
         let key = format!("{}/{}", function_id, version);
-        let mut entries = self.entries.write().await;
-        
-        if let Some(entry) = entries.get_mut(&key) {
-            // Verify SHA256
-            if entry.sha256 != expected_sha256 {
-                return None;
+
+        // ロックは last_used の更新にだけ使い、ファイルはロックを外してから読む
+        let claimed_size = {
+            let mut state = self.state.write().await;
+            match state.entries.get_mut(&key) {
+                Some(entry) if entry.sha256 == expected_sha256 => {
+                    entry.last_used = now_secs();
+                    None
+                }
+                Some(_) => return None,
+                None => Some(state.unclaimed.get(expected_sha256)?.0),
             }
-            
-            // Update last_used
-            entry.last_used = SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs();
-            
-            std::fs::read(&entry.path).ok()
-        } else {
-            None
+        };
+
+        let data = tokio::fs::read(object_path(&self.cache_dir, expected_sha256)).await.ok()?;
+        if let Some(size) = claimed_size {
+            self.adopt(function_id, version, expected_sha256, size).await;
         }
+        Some(data)
     }
-    
-    pub async fn put(&self, function_id: &str, version: &str, data: &[u8], expected_sha256: &str) -> std::io::Result<()> {
-        // Verify SHA256
-        let mut hasher = Sha256::new();
-        hasher.update(data);
-        let hash = format!("{:x}", hasher.finalize());
-        
-        if hash != expected_sha256 {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "SHA256 mismatch",
-            ));
+
+    // 索引の再構築で見つかった内容を、要求された function_id/version のエントリにする
+    async fn adopt(&self, function_id: &str, version: &str, sha256: &str, size: u64) {
+        let mut state = self.state.write().await;
+        if !state.claim(sha256) {
+            return;
         }
-        
         let key = format!("{}/{}", function_id, version);
-        let path = self.cache_dir.join(&key).with_extension("wasm");
-        
-        if let Some(parent) = path.parent() {
-            std::fs::create_dir_all(parent)?;
-        }
-        
-        std::fs::write(&path, data)?;
-        
-        let size = data.len() as u64;
-        let now = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        
-        let mut entries = self.entries.write().await;
-        entries.insert(key, CacheEntry {
-            path,
+        let orphaned = state.insert(key, CacheEntry {
+            function_id: function_id.to_string(),
+            version: version.to_string(),
             size,
-            sha256: hash,
-            last_used: now,
+            sha256: sha256.to_string(),
+            last_used: now_secs(),
         });
-        
-        let mut current_size = self.current_size.write().await;
-        *current_size += size;
-        drop(current_size);
-        drop(entries);
-        
-        self.evict_if_needed().await;
-        
-        Ok(())
+        self.persist_index(&state).await;
+        if let Some(sha256) = orphaned {
+            let _ = tokio::fs::remove_file(object_path(&self.cache_dir, &sha256)).await;
+        }
     }
-    
-    async fn evict_if_needed(&self) {
-        loop {
-            let current_size = *self.current_size.read().await;
-            if current_size <= self.max_size_bytes {
-                break;
-            }
-            
-            let mut entries = self.entries.write().await;
-            if entries.is_empty() {
-                break;
-            }
-            
-            // Find LRU entry
-            let oldest_key = entries.iter()
-                .min_by_key(|(_, e)| e.last_used)
-                .map(|(k, _)| k.clone());
-            
-            if let Some(key) = oldest_key {
-                if let Some(entry) = entries.remove(&key) {
-                    let _ = std::fs::remove_file(&entry.path);
-                    let mut current_size = self.current_size.write().await;
-                    *current_size -= entry.size;
-                }
+
+    pub async fn put(&self, function_id: &str, version: &str, data: &[u8], expected_sha256: &str) -> Result<(), InvocationError> {
+        let hash = format!("{:x}", Sha256::digest(data));
+        if hash != expected_sha256 {
+            return Err(InvocationError::ChecksumMismatch {
+                expected: expected_sha256.to_string(),
+                actual: hash,
+            });
+        }
+
+        let key = format!("{}/{}", function_id, version);
+        let path = object_path(&self.cache_dir, &hash);
+
+        // 同じ内容がまだなければ、ロックを取る前に一時ファイルへ書いておく
+        let staged = if self.state.read().await.stored(&hash) {
+            None
+        } else {
+            Some(self.stage(&path, data).await.map_err(cache_error)?)
+        };
+
+        let mut state = self.state.write().await;
+        if state.stored(&hash) {
+            state.claim(&hash);
+            if let Some(tmp_path) = staged {
+                let _ = tokio::fs::remove_file(tmp_path).await;
             }
+        } else {
+            // 書き込みの間に同じ内容のエントリが追い出されていれば、ここで書き直す
+            let tmp_path = match staged {
+                Some(tmp_path) => tmp_path,
+                None => self.stage(&path, data).await.map_err(cache_error)?,
+            };
+            self.commit(&tmp_path, &path).await.map_err(cache_error)?;
+        }
+
+        let mut orphaned: Vec<String> = state.insert(key, CacheEntry {
+            function_id: function_id.to_string(),
+            version: version.to_string(),
+            size: data.len() as u64,
+            sha256: hash,
+            last_used: now_secs(),
+        }).into_iter().collect();
+        orphaned.extend(state.evict(self.max_size_bytes));
+
+        // 索引から外してからファイルを消す
+        self.persist_index(&state).await;
+        for sha256 in orphaned {
+            let _ = tokio::fs::remove_file(object_path(&self.cache_dir, &sha256)).await;
         }
+
+        Ok(())
     }
-    
-    #[allow(dead_code)]
-    pub async fn remove(&self, function_id: &str, version: &str) -> std::io::Result<()> {
+
+    pub async fn remove(&self, function_id: &str, version: &str) -> Result<(), InvocationError> {
         let key = format!("{}/{}", function_id, version);
-        let mut entries = self.entries.write().await;
-        
-        if let Some(entry) = entries.remove(&key) {
-            std::fs::remove_file(&entry.path)?;
-            let mut current_size = self.current_size.write().await;
-            *current_size -= entry.size;
-        }
-        
+        let mut state = self.state.write().await;
+        if !state.entries.contains_key(&key) {
+            return Ok(());
+        }
+
+        let orphaned = state.release(&key);
+        self.persist_index(&state).await;
+        if let Some(sha256) = orphaned {
+            tokio::fs::remove_file(object_path(&self.cache_dir, &sha256)).await.map_err(cache_error)?;
+        }
+
         Ok(())
     }
-    
-    #[allow(dead_code)]
+
+    #[cfg(test)]
     pub async fn get_size(&self) -> u64 {
-        *self.current_size.read().await
+        self.state.read().await.current_size
+    }
+
+    // 索引を書けなくてもキャッシュ自体は使えるので、失敗はログに残すだけにする
+    async fn persist_index(&self, state: &CacheState) {
+        let result = match serde_json::to_vec(&state.entries) {
+            Ok(bytes) => self.write_atomic(&self.cache_dir.join(INDEX_FILE), &bytes).await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to write wasm cache index: {}", e);
+        }
+    }
+
+    async fn write_atomic(&self, path: &Path, data: &[u8]) -> std::io::Result<()> {
+        let tmp_path = self.stage(path, data).await?;
+        self.commit(&tmp_path, path).await
     }
+
+    // 同じパスへの書き込みが重なっても混ざらないよう、一時ファイル名は書き込みごとに変える
+    async fn stage(&self, path: &Path, data: &[u8]) -> std::io::Result<PathBuf> {
+        let tmp_path = path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+        let result = async {
+            let mut file = tokio::fs::File::create(&tmp_path).await?;
+            file.write_all(data).await?;
+            if self.fsync {
+                file.sync_all().await?;
+            }
+            Ok::<(), std::io::Error>(())
+        }.await;
+
+        match result {
+            Ok(()) => Ok(tmp_path),
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&tmp_path).await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn commit(&self, tmp_path: &Path, path: &Path) -> std::io::Result<()> {
+        if let Err(e) = tokio::fs::rename(tmp_path, path).await {
+            let _ = tokio::fs::remove_file(tmp_path).await;
+            return Err(e);
+        }
+        if self.fsync {
+            if let Some(dir) = path.parent() {
+                tokio::fs::File::open(dir).await?.sync_all().await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn object_path(cache_dir: &Path, sha256: &str) -> PathBuf {
+    cache_dir.join(OBJECTS_DIR).join(format!("{}.wasm", sha256))
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+// 索引のエントリのうちオブジェクトが残っていてハッシュが一致するものだけを残し、
+// 参照されていないアーティファクト (書き込み途中で落ちたもの、壊れたものなど) は削除する。
+// 索引がない、または読めない場合は objects/ を読み直し、名前とハッシュが一致するものを unclaimed として残す
+fn rebuild_index(cache_dir: &Path, max_size_bytes: u64) -> CacheState {
+    let index: Option<HashMap<String, CacheEntry>> = match std::fs::read(cache_dir.join(INDEX_FILE)) {
+        Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| {
+            eprintln!("Rebuilding unreadable wasm cache index from {}: {}", OBJECTS_DIR, e);
+        }).ok(),
+        Err(_) => None,
+    };
+
+    let mut state = CacheState::default();
+    match index {
+        Some(index) => restore_entries(cache_dir, index, &mut state),
+        None => rescan_objects(cache_dir, &mut state),
+    }
+
+    // 前回より上限が下げられていれば古いものから外す
+    state.evict(max_size_bytes);
+
+    let known: HashSet<PathBuf> = state.entries.values().map(|e| &e.sha256)
+        .chain(state.unclaimed.keys())
+        .map(|sha256| object_path(cache_dir, sha256))
+        .collect();
+    let mut removed = 0;
+    for path in files_under(cache_dir) {
+        // コンパイル済みモジュール (.wasmu) はモジュールキャッシュが管理するので触らない
+        let orphaned = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("wasm") => !known.contains(&path),
+            Some("tmp") => true,
+            _ => false,
+        };
+        if orphaned && std::fs::remove_file(&path).is_ok() {
+            removed += 1;
+        }
+    }
+
+    println!(
+        "Restored {} cached artifact(s) and {} unclaimed object(s) ({} bytes), removed {} orphaned or corrupt file(s)",
+        state.entries.len(), state.unclaimed.len(), state.current_size, removed
+    );
+    state
+}
+
+fn restore_entries(cache_dir: &Path, index: HashMap<String, CacheEntry>, state: &mut CacheState) {
+    // 複数のエントリが同じオブジェクトを指していても読み直すのは 1 回だけ
+    let mut verified: HashMap<String, bool> = HashMap::new();
+    for (key, entry) in index {
+        let intact = *verified.entry(entry.sha256.clone()).or_insert_with(|| {
+            std::fs::read(object_path(cache_dir, &entry.sha256))
+                .is_ok_and(|data| data.len() as u64 == entry.size && format!("{:x}", Sha256::digest(&data)) == entry.sha256)
+        });
+        if intact {
+            state.insert(key, entry);
+        } else {
+            eprintln!("Discarding missing or corrupt cached artifact of {} {}", entry.function_id, entry.version);
+        }
+    }
+}
+
+fn rescan_objects(cache_dir: &Path, state: &mut CacheState) {
+    for path in files_under(&cache_dir.join(OBJECTS_DIR)) {
+        if path.extension().and_then(|ext| ext.to_str()) != Some("wasm") {
+            continue;
+        }
+        let Some(sha256) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        let Ok(data) = std::fs::read(&path) else {
+            continue;
+        };
+        if format!("{:x}", Sha256::digest(&data)) != sha256 {
+            continue;
+        }
+
+        let modified = std::fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs());
+        state.current_size += data.len() as u64;
+        state.unclaimed.insert(sha256.to_string(), (data.len() as u64, modified));
+    }
+}
+
+fn files_under(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    if let Ok(read_dir) = std::fs::read_dir(dir) {
+        for entry in read_dir.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                files.extend(files_under(&path));
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files
+}
+
+fn write_index(cache_dir: &Path, entries: &HashMap<String, CacheEntry>) -> std::io::Result<()> {
+    let bytes = serde_json::to_vec(entries)?;
+    let path = cache_dir.join(INDEX_FILE);
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, bytes)?;
+    std::fs::rename(&tmp_path, &path)
+}
+
+fn cache_error(e: std::io::Error) -> InvocationError {
+    InvocationError::Cache(e.to_string())
 }
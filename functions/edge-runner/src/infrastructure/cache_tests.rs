@@ -0,0 +1,205 @@
+#[cfg(test)]
+mod tests {
+    use crate::infrastructure::*;
+    use sha2::{Digest, Sha256};
+    use std::path::PathBuf;
+
+    const V1: &[u8] = b"\0asm version 1.0.0";
+    const V2: &[u8] = b"\0asm version 1.0.1";
+
+    fn sha(data: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(data))
+    }
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wasm-cache-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_versions_do_not_collide() {
+        let cache = LocalWasmCache::new(cache_dir("versions"), 1024 * 1024).unwrap();
+        cache.put("func1", "1.0.0", V1, &sha(V1)).await.unwrap();
+        cache.put("func1", "1.0.1", V2, &sha(V2)).await.unwrap();
+
+        assert_eq!(cache.get("func1", "1.0.0", &sha(V1)).await.unwrap(), V1);
+        assert_eq!(cache.get("func1", "1.0.1", &sha(V2)).await.unwrap(), V2);
+    }
+
+    #[tokio::test]
+    async fn test_reopen_restores_entries() {
+        let dir = cache_dir("reopen");
+        {
+            let cache = LocalWasmCache::new(&dir, 1024 * 1024).unwrap();
+            cache.put("func1", "1.0.0", V1, &sha(V1)).await.unwrap();
+            cache.put("func2", "1.0.1", V2, &sha(V2)).await.unwrap();
+        }
+
+        let cache = LocalWasmCache::new(&dir, 1024 * 1024).unwrap();
+        assert_eq!(cache.get("func1", "1.0.0", &sha(V1)).await.unwrap(), V1);
+        assert_eq!(cache.get_size().await, (V1.len() + V2.len()) as u64);
+        assert_eq!(cache.cached_artifacts().await, vec![
+            ("func1".to_string(), "1.0.0".to_string()),
+            ("func2".to_string(), "1.0.1".to_string()),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_artifact_is_discarded_on_reopen() {
+        let dir = cache_dir("corrupt");
+        {
+            let cache = LocalWasmCache::new(&dir, 1024 * 1024).unwrap();
+            cache.put("func1", "1.0.0", V1, &sha(V1)).await.unwrap();
+            cache.put("func1", "1.0.1", V2, &sha(V2)).await.unwrap();
+        }
+        let object = dir.join("objects").join(format!("{}.wasm", sha(V2)));
+        std::fs::write(&object, b"truncated").unwrap();
+
+        let cache = LocalWasmCache::new(&dir, 1024 * 1024).unwrap();
+        assert!(cache.get("func1", "1.0.1", &sha(V2)).await.is_none());
+        assert!(!object.exists());
+        assert_eq!(cache.get_size().await, V1.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn test_orphaned_files_are_removed_on_reopen() {
+        let dir = cache_dir("orphans");
+        {
+            let cache = LocalWasmCache::new(&dir, 1024 * 1024).unwrap();
+            cache.put("func1", "1.0.0", V1, &sha(V1)).await.unwrap();
+        }
+        let objects = dir.join("objects");
+        std::fs::write(objects.join(format!("{}.wasm", sha(V2))), V2).unwrap();
+        std::fs::write(objects.join(format!("{}.1234.tmp", sha(V1))), V1).unwrap();
+        // 以前のレイアウトで保存されたアーティファクト
+        std::fs::create_dir_all(dir.join("func1")).unwrap();
+        std::fs::write(dir.join("func1").join("0.9.0.wasm"), V2).unwrap();
+        // コンパイル済みモジュールはプールのモジュールキャッシュが管理するので残す
+        std::fs::write(dir.join("func1").join("1.0.0-abc.wasmu"), b"compiled").unwrap();
+
+        let cache = LocalWasmCache::new(&dir, 1024 * 1024).unwrap();
+        assert!(!objects.join(format!("{}.wasm", sha(V2))).exists());
+        assert!(!objects.join(format!("{}.1234.tmp", sha(V1))).exists());
+        assert!(!dir.join("func1").join("0.9.0.wasm").exists());
+        assert!(dir.join("func1").join("1.0.0-abc.wasmu").exists());
+        assert_eq!(cache.get("func1", "1.0.0", &sha(V1)).await.unwrap(), V1);
+    }
+
+    #[tokio::test]
+    async fn test_unreadable_index_is_rebuilt_from_objects() {
+        let dir = cache_dir("bad-index");
+        {
+            let cache = LocalWasmCache::new(&dir, 1024 * 1024).unwrap();
+            cache.put("func1", "1.0.0", V1, &sha(V1)).await.unwrap();
+            cache.put("func1", "1.0.1", V2, &sha(V2)).await.unwrap();
+        }
+        std::fs::write(dir.join("index.json"), b"{not json").unwrap();
+        // ハッシュが名前と一致しないオブジェクトは捨てる
+        let corrupt = dir.join("objects").join(format!("{}.wasm", sha(b"other")));
+        std::fs::write(&corrupt, b"truncated").unwrap();
+
+        let cache = LocalWasmCache::new(&dir, 1024 * 1024).unwrap();
+        assert!(!corrupt.exists());
+        assert!(dir.join("objects").join(format!("{}.wasm", sha(V1))).exists());
+        assert_eq!(cache.get_size().await, (V1.len() + V2.len()) as u64);
+        // どのバージョンのものかは分からないので、要求されるまでは報告しない
+        assert!(cache.cached_artifacts().await.is_empty());
+
+        // 同じ内容を要求されたらダウンロードせずにそのバージョンのエントリにする
+        assert_eq!(cache.get("func1", "1.0.0", &sha(V1)).await.unwrap(), V1);
+        cache.put("func1", "1.0.1", V2, &sha(V2)).await.unwrap();
+        assert_eq!(cache.get_size().await, (V1.len() + V2.len()) as u64);
+        assert_eq!(cache.cached_artifacts().await, vec![
+            ("func1".to_string(), "1.0.0".to_string()),
+            ("func1".to_string(), "1.0.1".to_string()),
+        ]);
+
+        // 引き取ったエントリは索引に書かれ、次の起動でも残る
+        drop(cache);
+        let cache = LocalWasmCache::new(&dir, 1024 * 1024).unwrap();
+        assert_eq!(cache.cached_artifacts().await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_unclaimed_objects_are_evicted_first() {
+        let dir = cache_dir("unclaimed-evict");
+        {
+            let cache = LocalWasmCache::new(&dir, 1024 * 1024).unwrap();
+            cache.put("func1", "1.0.0", V1, &sha(V1)).await.unwrap();
+        }
+        std::fs::remove_file(dir.join("index.json")).unwrap();
+
+        let cache = LocalWasmCache::new(&dir, V1.len() as u64).unwrap();
+        cache.put("func2", "1.0.1", V2, &sha(V2)).await.unwrap();
+        assert!(!dir.join("objects").join(format!("{}.wasm", sha(V1))).exists());
+        assert!(cache.get("func1", "1.0.0", &sha(V1)).await.is_none());
+        assert_eq!(cache.get("func2", "1.0.1", &sha(V2)).await.unwrap(), V2);
+    }
+
+    #[tokio::test]
+    async fn test_reopen_with_smaller_limit_evicts_least_recently_used() {
+        let dir = cache_dir("shrink");
+        {
+            let cache = LocalWasmCache::new(&dir, 1024 * 1024).unwrap();
+            cache.put("func1", "1.0.0", V1, &sha(V1)).await.unwrap();
+            cache.put("func2", "1.0.1", V2, &sha(V2)).await.unwrap();
+        }
+
+        let cache = LocalWasmCache::new(&dir, V1.len() as u64).unwrap();
+        assert_eq!(cache.cached_artifacts().await.len(), 1);
+        assert!(cache.get_size().await <= V1.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn test_overwrite_does_not_double_count() {
+        let cache = LocalWasmCache::new(cache_dir("overwrite"), 1024 * 1024).unwrap();
+        cache.put("func1", "1.0.0", V1, &sha(V1)).await.unwrap();
+        cache.put("func1", "1.0.0", V1, &sha(V1)).await.unwrap();
+        assert_eq!(cache.get_size().await, V1.len() as u64);
+
+        // 同じバージョンを別の内容で置き換えると古い内容は消える
+        cache.put("func1", "1.0.0", V2, &sha(V2)).await.unwrap();
+        assert_eq!(cache.get_size().await, V2.len() as u64);
+        assert!(cache.get("func1", "1.0.0", &sha(V1)).await.is_none());
+        assert_eq!(cache.get("func1", "1.0.0", &sha(V2)).await.unwrap(), V2);
+    }
+
+    #[tokio::test]
+    async fn test_identical_artifacts_are_stored_once() {
+        let dir = cache_dir("dedup");
+        let cache = LocalWasmCache::new(&dir, 1024 * 1024).unwrap();
+        cache.put("func1", "1.0.0", V1, &sha(V1)).await.unwrap();
+        cache.put("func2", "2.0.0", V1, &sha(V1)).await.unwrap();
+        assert_eq!(cache.get_size().await, V1.len() as u64);
+        assert_eq!(std::fs::read_dir(dir.join("objects")).unwrap().count(), 1);
+
+        // 片方を削除しても、もう片方が参照している内容は残る
+        cache.remove("func1", "1.0.0").await.unwrap();
+        assert_eq!(cache.get("func2", "2.0.0", &sha(V1)).await.unwrap(), V1);
+        assert_eq!(cache.get_size().await, V1.len() as u64);
+
+        cache.remove("func2", "2.0.0").await.unwrap();
+        assert_eq!(cache.get_size().await, 0);
+        assert_eq!(std::fs::read_dir(dir.join("objects")).unwrap().count(), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_concurrent_puts_leave_one_object() {
+        let dir = cache_dir("concurrent");
+        let cache = std::sync::Arc::new(LocalWasmCache::new(&dir, 1024 * 1024).unwrap().with_fsync(true));
+
+        let puts = (0..8).map(|i| {
+            let cache = cache.clone();
+            tokio::spawn(async move { cache.put(&format!("func{}", i % 2), "1.0.0", V1, &sha(V1)).await })
+        });
+        for put in futures::future::join_all(puts).await {
+            put.unwrap().unwrap();
+        }
+
+        assert_eq!(cache.get_size().await, V1.len() as u64);
+        let files: Vec<_> = std::fs::read_dir(dir.join("objects")).unwrap().flatten().map(|e| e.file_name()).collect();
+        assert_eq!(files, vec![std::ffi::OsString::from(format!("{}.wasm", sha(V1)))]);
+        assert_eq!(cache.get("func1", "1.0.0", &sha(V1)).await.unwrap(), V1);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::domain::{CachedFunction, DeploymentNotification};
+use crate::domain::{CachedFunction, DeploymentNotification, FunctionCommand, InvocationError, KvCommand, TrafficSplit};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize)]
@@ -7,12 +7,23 @@ struct HeartbeatRequest {
     pop_id: String,
     status: String,
     cached_functions: Vec<CachedFunction>,
+    // 適用済みのリビジョン。変更がなければコントロールプレーンは routes / functions を省略できる
+    revision: Option<u64>,
 }
 
 #[derive(Deserialize)]
-struct HeartbeatResponse {
-    deployments: Vec<DeploymentNotification>,
-    routes: Option<Vec<RouteDto>>,
+pub struct HeartbeatResponse {
+    pub deployments: Vec<DeploymentNotification>,
+    // 以下 3 つはコントロールプレーン上の望ましい状態。routes / functions は全体集合
+    #[serde(default)]
+    pub revision: Option<u64>,
+    pub routes: Option<Vec<RouteDto>>,
+    #[serde(default)]
+    pub functions: Option<Vec<String>>,
+    #[serde(default)]
+    pub kv_commands: Vec<KvCommand>,
+    #[serde(default)]
+    pub function_commands: Vec<FunctionCommand>,
 }
 
 #[derive(Deserialize, Clone)]
@@ -23,6 +34,8 @@ pub struct RouteDto {
     pub function_id: String,
     pub methods: Vec<String>,
     pub priority: i32,
+    #[serde(default)]
+    pub split: Option<TrafficSplit>,
 }
 
 pub struct ControlPlaneClient {
@@ -43,12 +56,14 @@ impl ControlPlaneClient {
         node_id: &str,
         pop_id: &str,
         cached_functions: Vec<CachedFunction>,
-    ) -> Result<(Vec<DeploymentNotification>, Vec<RouteDto>), String> {
+        revision: Option<u64>,
+    ) -> Result<HeartbeatResponse, InvocationError> {
         let req = HeartbeatRequest {
             node_id: node_id.to_string(),
             pop_id: pop_id.to_string(),
             status: "online".to_string(),
             cached_functions,
+            revision,
         };
         
         let url = format!("{}/api/v1/nodes/{}/heartbeat", self.cp_url, node_id);
@@ -57,12 +72,9 @@ impl ControlPlaneClient {
             .json(&req)
             .send()
             .await
-            .map_err(|e| format!("Heartbeat failed: {}", e))?;
+            .map_err(|e| InvocationError::ControlPlane(format!("Heartbeat failed: {}", e)))?;
         
-        let hb_resp: HeartbeatResponse = resp.json().await
-            .map_err(|e| format!("Failed to parse response: {}", e))?;
-        
-        let routes = hb_resp.routes.unwrap_or_default();
-        Ok((hb_resp.deployments, routes))
+        resp.json().await
+            .map_err(|e| InvocationError::ControlPlane(format!("Failed to parse response: {}", e)))
     }
 }
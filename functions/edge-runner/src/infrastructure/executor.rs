@@ -0,0 +1,85 @@
+use crate::domain::InvocationError;
+use crate::infrastructure::metrics::{EXECUTOR_QUEUE_DEPTH, EXECUTOR_QUEUE_WAIT, EXECUTOR_REJECTED};
+use std::panic::AssertUnwindSafe;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+struct QueuedJob {
+    job: Job,
+    enqueued_at: Instant,
+}
+
+// WASM 実行専用のワーカースレッドプール。
+// 同期的な wasmer 呼び出しを async エグゼキュータから切り離し、
+// キューが満杯のときは即座に拒否してバックプレッシャーをかける。
+pub struct WasmExecutor {
+    sender: SyncSender<QueuedJob>,
+}
+
+impl WasmExecutor {
+    pub fn new(workers: usize, queue_size: usize) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<QueuedJob>(queue_size);
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for i in 0..workers.max(1) {
+            let receiver = receiver.clone();
+            std::thread::Builder::new()
+                .name(format!("wasm-worker-{}", i))
+                .spawn(move || worker_loop(receiver))
+                .expect("Failed to spawn WASM worker thread");
+        }
+
+        Self { sender }
+    }
+
+    pub async fn run<F, T>(&self, f: F) -> Result<T, InvocationError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let job: Job = Box::new(move || {
+            let _ = tx.send(f());
+        });
+
+        EXECUTOR_QUEUE_DEPTH.inc();
+        match self.sender.try_send(QueuedJob { job, enqueued_at: Instant::now() }) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                EXECUTOR_QUEUE_DEPTH.dec();
+                EXECUTOR_REJECTED.inc();
+                return Err(InvocationError::Overloaded("Worker pool saturated".to_string()));
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                EXECUTOR_QUEUE_DEPTH.dec();
+                return Err(InvocationError::Internal("Worker pool stopped".to_string()));
+            }
+        }
+
+        rx.await.map_err(|_| InvocationError::Internal("Worker aborted the invocation".to_string()))
+    }
+}
+
+fn worker_loop(receiver: Arc<Mutex<Receiver<QueuedJob>>>) {
+    loop {
+        let queued = {
+            let receiver = match receiver.lock() {
+                Ok(receiver) => receiver,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            match receiver.recv() {
+                Ok(queued) => queued,
+                Err(_) => break,
+            }
+        };
+
+        EXECUTOR_QUEUE_DEPTH.dec();
+        EXECUTOR_QUEUE_WAIT.observe(queued.enqueued_at.elapsed().as_secs_f64());
+
+        // パニックしたジョブは送信側が破棄されるだけで、ワーカーは生き残る
+        let _ = std::panic::catch_unwind(AssertUnwindSafe(queued.job));
+    }
+}
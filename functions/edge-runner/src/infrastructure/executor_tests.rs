@@ -0,0 +1,56 @@
+#[cfg(test)]
+mod tests {
+    use crate::domain::InvocationError;
+    use crate::infrastructure::*;
+    use std::sync::{mpsc, Arc};
+
+    #[tokio::test]
+    async fn test_run_returns_result() {
+        let executor = WasmExecutor::new(2, 4);
+        let result = executor.run(|| 21 * 2).await;
+        assert_eq!(result, Ok(42));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_when_queue_is_full() {
+        let executor = Arc::new(WasmExecutor::new(1, 1));
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+
+        // 1つ目: ワーカーを占有する
+        let busy = {
+            let executor = executor.clone();
+            tokio::spawn(async move {
+                executor.run(move || {
+                    started_tx.send(()).unwrap();
+                    release_rx.recv().unwrap();
+                    1
+                }).await
+            })
+        };
+        tokio::task::spawn_blocking(move || started_rx.recv().unwrap()).await.unwrap();
+
+        // 2つ目: キューに入る
+        let queued = {
+            let executor = executor.clone();
+            tokio::spawn(async move { executor.run(|| 2).await })
+        };
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        // 3つ目: キューが満杯なので拒否される
+        let rejected = executor.run(|| 3).await;
+        assert_eq!(rejected, Err(InvocationError::Overloaded("Worker pool saturated".to_string())));
+
+        release_tx.send(()).unwrap();
+        assert_eq!(busy.await.unwrap(), Ok(1));
+        assert_eq!(queued.await.unwrap(), Ok(2));
+    }
+
+    #[tokio::test]
+    async fn test_worker_survives_panicking_job() {
+        let executor = WasmExecutor::new(1, 4);
+        let result: Result<(), InvocationError> = executor.run(|| panic!("guest bug")).await;
+        assert!(result.is_err());
+        assert_eq!(executor.run(|| "still alive").await, Ok("still alive"));
+    }
+}
@@ -0,0 +1,59 @@
+use wasmer::{AsStoreMut, AsStoreRef, Instance, Memory, TypedFunction};
+
+// ゲストのメモリから一度に読み出すサイズの上限。ゲストが返す長さでホストのメモリを確保するため
+pub const MAX_GUEST_READ_BYTES: u32 = 32 * 1024 * 1024;
+
+// ゲストがエクスポートするアロケータ経由で線形メモリを読み書きする
+pub struct GuestMemory {
+    memory: Memory,
+    alloc: TypedFunction<i32, i32>,
+    dealloc: TypedFunction<(i32, i32), ()>,
+}
+
+impl GuestMemory {
+    pub fn from_instance(instance: &Instance, store: &impl AsStoreRef) -> Result<Self, String> {
+        let memory = instance.exports.get_memory("memory")
+            .map_err(|_| "memory not found".to_string())?
+            .clone();
+        let alloc = instance.exports.get_typed_function::<i32, i32>(store, "alloc")
+            .map_err(|_| "Guest does not export `alloc(len: i32) -> i32`".to_string())?;
+        let dealloc = instance.exports.get_typed_function::<(i32, i32), ()>(store, "dealloc")
+            .map_err(|_| "Guest does not export `dealloc(ptr: i32, len: i32)`".to_string())?;
+
+        Ok(Self { memory, alloc, dealloc })
+    }
+
+    /// ゲスト側に `data.len()` バイトを確保して書き込み、ポインタを返す
+    pub fn write(&self, store: &mut impl AsStoreMut, data: &[u8]) -> Result<i32, String> {
+        let len = i32::try_from(data.len())
+            .map_err(|_| format!("Input of {} bytes exceeds guest address space", data.len()))?;
+        let ptr = self.alloc.call(store, len)
+            .map_err(|e| format!("Guest alloc failed: {}", e))?;
+
+        if let Err(e) = self.memory.view(&*store).write(ptr as u32 as u64, data) {
+            self.free(store, ptr, len);
+            return Err(format!("Guest alloc returned invalid pointer {}: {}", ptr as u32, e));
+        }
+
+        Ok(ptr)
+    }
+
+    pub fn read(&self, store: &impl AsStoreRef, ptr: u32, len: u32) -> Result<Vec<u8>, String> {
+        if len > MAX_GUEST_READ_BYTES {
+            return Err(format!("Guest region of {} bytes exceeds the {} byte limit", len, MAX_GUEST_READ_BYTES));
+        }
+        let view = self.memory.view(store);
+        if ptr as u64 + len as u64 > view.data_size() {
+            return Err(format!("Guest returned out-of-bounds region {}+{}", ptr, len));
+        }
+
+        let mut data = vec![0u8; len as usize];
+        view.read(ptr as u64, &mut data)
+            .map_err(|e| format!("Guest returned out-of-bounds region {}+{}: {}", ptr, len, e))?;
+        Ok(data)
+    }
+
+    pub fn free(&self, store: &mut impl AsStoreMut, ptr: i32, len: i32) {
+        let _ = self.dealloc.call(store, ptr, len);
+    }
+}
@@ -0,0 +1,104 @@
+use crate::infrastructure::metrics::{GUEST_LOGS, GUEST_LOGS_DROPPED};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Instant;
+
+// 1 レコードのメッセージ上限。超えた分は切り捨てる
+pub const MAX_LOG_MESSAGE_BYTES: usize = 8 * 1024;
+
+const LEVELS: [&str; 5] = ["trace", "debug", "info", "warn", "error"];
+
+pub fn level_name(level: i32) -> Option<&'static str> {
+    usize::try_from(level).ok().and_then(|i| LEVELS.get(i).copied())
+}
+
+#[derive(Clone, Serialize)]
+pub struct LogRecord {
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub level: &'static str,
+    pub node_id: String,
+    pub function_id: String,
+    pub version: String,
+    pub request_id: String,
+    pub message: String,
+}
+
+// 関数ごとのトークンバケットと直近のレコード
+struct FunctionLog {
+    records: VecDeque<LogRecord>,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+// ゲストのログ。標準出力に JSON で書き出し、管理エンドポイント用に直近分を保持する
+pub struct GuestLogStore {
+    node_id: String,
+    functions: Mutex<HashMap<String, FunctionLog>>,
+    capacity: usize,
+    rate_per_sec: f64,
+    burst: f64,
+}
+
+impl GuestLogStore {
+    pub fn new(node_id: String, capacity: usize, rate_per_sec: u32, burst: u32) -> Self {
+        Self {
+            node_id,
+            functions: Mutex::new(HashMap::new()),
+            capacity,
+            rate_per_sec: rate_per_sec as f64,
+            burst: burst as f64,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, FunctionLog>> {
+        self.functions.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // レート制限を超えた場合は false を返して破棄する
+    pub fn record(&self, level: &'static str, function_id: &str, version: &str, request_id: &str, message: String) -> bool {
+        let mut functions = self.lock();
+        let log = functions.entry(function_id.to_string()).or_insert_with(|| FunctionLog {
+            records: VecDeque::new(),
+            tokens: self.burst,
+            refilled_at: Instant::now(),
+        });
+
+        let elapsed = log.refilled_at.elapsed().as_secs_f64();
+        log.tokens = (log.tokens + elapsed * self.rate_per_sec).min(self.burst);
+        log.refilled_at = Instant::now();
+        if log.tokens < 1.0 {
+            GUEST_LOGS_DROPPED.with_label_values(&[function_id]).inc();
+            return false;
+        }
+        log.tokens -= 1.0;
+
+        let record = LogRecord {
+            timestamp: chrono::Utc::now(),
+            level,
+            node_id: self.node_id.clone(),
+            function_id: function_id.to_string(),
+            version: version.to_string(),
+            request_id: request_id.to_string(),
+            message,
+        };
+        if let Ok(line) = serde_json::to_string(&record) {
+            println!("{}", line);
+        }
+        GUEST_LOGS.with_label_values(&[function_id, level]).inc();
+
+        if log.records.len() >= self.capacity {
+            log.records.pop_front();
+        }
+        log.records.push_back(record);
+        true
+    }
+
+    // 新しい順に最大 limit 件
+    pub fn recent(&self, function_id: &str, limit: usize) -> Vec<LogRecord> {
+        self.lock()
+            .get(function_id)
+            .map(|log| log.records.iter().rev().take(limit).cloned().collect())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,45 @@
+#[cfg(test)]
+mod tests {
+    use crate::infrastructure::guest_log::*;
+
+    #[test]
+    fn test_level_names() {
+        assert_eq!(level_name(0), Some("trace"));
+        assert_eq!(level_name(4), Some("error"));
+        assert_eq!(level_name(5), None);
+        assert_eq!(level_name(-1), None);
+    }
+
+    #[test]
+    fn test_records_are_tagged_and_newest_first() {
+        let logs = GuestLogStore::new("node-1".to_string(), 2, 100, 100);
+
+        assert!(logs.record("info", "func1", "1.0.0", "req-1", "first".to_string()));
+        assert!(logs.record("warn", "func1", "1.0.0", "req-2", "second".to_string()));
+        assert!(logs.record("error", "func1", "1.0.0", "req-3", "third".to_string()));
+
+        // 容量を超えた古いレコードは捨てる
+        let recent = logs.recent("func1", 10);
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].message, "third");
+        assert_eq!(recent[0].request_id, "req-3");
+        assert_eq!(recent[0].node_id, "node-1");
+        assert_eq!(recent[1].level, "warn");
+
+        assert_eq!(logs.recent("func1", 1).len(), 1);
+        assert!(logs.recent("func2", 10).is_empty());
+    }
+
+    #[test]
+    fn test_rate_limit_per_function() {
+        let logs = GuestLogStore::new("node-1".to_string(), 100, 1, 2);
+
+        assert!(logs.record("info", "func1", "1.0.0", "req-1", "a".to_string()));
+        assert!(logs.record("info", "func1", "1.0.0", "req-1", "b".to_string()));
+        assert!(!logs.record("info", "func1", "1.0.0", "req-1", "c".to_string()));
+
+        // 他の関数は別のバケット
+        assert!(logs.record("info", "func2", "1.0.0", "req-2", "a".to_string()));
+        assert_eq!(logs.recent("func1", 10).len(), 2);
+    }
+}
@@ -0,0 +1,246 @@
+use crate::domain::FunctionMetadata;
+use crate::infrastructure::guest::GuestMemory;
+use crate::infrastructure::guest_log::{self, GuestLogStore, MAX_LOG_MESSAGE_BYTES};
+use crate::infrastructure::http_fetch::{FetchError, FetchRequest, HttpFetcher, DEFAULT_FETCH_TIMEOUT_MS};
+use crate::infrastructure::kv::{KvError, KvStore};
+use crate::infrastructure::metering;
+use crate::infrastructure::telemetry::{TelemetryBridge, TelemetryError, TelemetryQuery};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use wasmer::{AsStoreMut, Function, FunctionEnv, FunctionEnvMut, Imports, Instance, Module, RuntimeError};
+
+// ゲストに公開するホスト関数の名前空間
+pub const HOST_NAMESPACE: &str = "edgebase";
+
+// ホスト関数が使うノード共通のサービス
+pub struct HostServices {
+    pub fetcher: HttpFetcher,
+    pub kv: Arc<KvStore>,
+    pub logs: Arc<GuestLogStore>,
+    // edge-agent のデータベースが設定されている場合のみ
+    pub telemetry: Option<Arc<TelemetryBridge>>,
+}
+
+pub struct HostState {
+    function_id: String,
+    version: String,
+    request_id: String,
+    services: Arc<HostServices>,
+    guest: Option<GuestMemory>,
+    allowed_hosts: Vec<String>,
+    telemetry_enabled: bool,
+    deadline: Option<Instant>,
+    timed_out: bool,
+}
+
+pub fn imports_host(module: &Module) -> bool {
+    module.imports().any(|import| import.module() == HOST_NAMESPACE)
+}
+
+pub fn define_imports(
+    store: &mut impl AsStoreMut,
+    module: &Module,
+    import_object: &mut Imports,
+    function_id: &str,
+    services: Arc<HostServices>,
+) -> Result<FunctionEnv<HostState>, String> {
+    let env = FunctionEnv::new(store, HostState {
+        function_id: function_id.to_string(),
+        version: String::new(),
+        request_id: String::new(),
+        services,
+        guest: None,
+        allowed_hosts: Vec::new(),
+        telemetry_enabled: false,
+        deadline: None,
+        timed_out: false,
+    });
+
+    for import in module.imports() {
+        if import.module() != HOST_NAMESPACE {
+            continue;
+        }
+        let function = match import.name() {
+            "http_fetch" => Function::new_typed_with_env(store, &env, http_fetch),
+            "kv_get" => Function::new_typed_with_env(store, &env, kv_get),
+            "kv_put" => Function::new_typed_with_env(store, &env, kv_put),
+            "kv_delete" => Function::new_typed_with_env(store, &env, kv_delete),
+            "log" => Function::new_typed_with_env(store, &env, log),
+            "telemetry_write" => Function::new_typed_with_env(store, &env, telemetry_write),
+            "telemetry_read" => Function::new_typed_with_env(store, &env, telemetry_read),
+            name => return Err(format!("Unknown host function {}.{}", HOST_NAMESPACE, name)),
+        };
+        import_object.define(HOST_NAMESPACE, import.name(), function);
+    }
+
+    Ok(env)
+}
+
+// ホスト関数からゲストのメモリを確保できるようにする
+pub fn initialize(store: &mut impl AsStoreMut, env: &FunctionEnv<HostState>, instance: &Instance) -> Result<(), String> {
+    let guest = GuestMemory::from_instance(instance, &*store)?;
+    env.as_mut(store).guest = Some(guest);
+    Ok(())
+}
+
+// 呼び出しごとに最新のメタデータを反映し、実行時間の期限を設定する
+pub fn begin_invocation(store: &mut impl AsStoreMut, env: &FunctionEnv<HostState>, metadata: &FunctionMetadata, request_id: &str) {
+    let state = env.as_mut(store);
+    state.version = metadata.version.clone();
+    state.request_id = request_id.to_string();
+    let timeout_ms = metering::effective_timeout_ms(metadata.max_execution_ms) as u64;
+    state.allowed_hosts = metadata.allowed_hosts.clone();
+    state.telemetry_enabled = metadata.telemetry;
+    state.deadline = Some(Instant::now() + Duration::from_millis(timeout_ms));
+    state.timed_out = false;
+}
+
+// ホスト関数の待ち時間で max_execution_ms を使い切った場合
+pub fn deadline_exceeded(store: &mut impl AsStoreMut, env: &FunctionEnv<HostState>) -> bool {
+    env.as_mut(store).timed_out
+}
+
+// 成功時はゲストに確保したレスポンスエンベロープの (ptr << 32) | len、失敗時は負のエラーコードを返す
+fn http_fetch(mut env: FunctionEnvMut<HostState>, request_ptr: i32, request_len: i32) -> Result<i64, RuntimeError> {
+    let (state, mut store) = env.data_and_store_mut();
+    let guest = guest_memory(state)?;
+
+    let request = match guest.read(&store, request_ptr as u32, request_len as u32)
+        .map_err(FetchError::InvalidRequest)
+        .and_then(|bytes| FetchRequest::decode(&bytes))
+    {
+        Ok(request) => request,
+        Err(e) => return Ok(e.code()),
+    };
+
+    let remaining = state.deadline
+        .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+        .unwrap_or(Duration::from_millis(DEFAULT_FETCH_TIMEOUT_MS));
+    let timeout = remaining.min(Duration::from_millis(DEFAULT_FETCH_TIMEOUT_MS));
+
+    let result = state.services.fetcher.fetch(&state.function_id, &state.allowed_hosts, request, timeout);
+    let response = match result {
+        Ok(response) => response,
+        Err(FetchError::Timeout) if timeout == remaining => {
+            // 呼び出し全体の期限切れとして扱う
+            state.timed_out = true;
+            return Err(RuntimeError::new("Execution deadline exceeded during http_fetch"));
+        }
+        Err(e) => return Ok(e.code()),
+    };
+
+    let encoded = response.encode();
+    let ptr = guest.write(&mut store, &encoded).map_err(RuntimeError::new)?;
+    Ok(((ptr as u32 as i64) << 32) | encoded.len() as i64)
+}
+
+fn guest_memory(state: &HostState) -> Result<&GuestMemory, RuntimeError> {
+    state.guest.as_ref().ok_or_else(|| RuntimeError::new("Host functions are not initialized"))
+}
+
+// 成功時はゲストに確保した値の (ptr << 32) | len、キーがなければ -1
+fn kv_get(mut env: FunctionEnvMut<HostState>, key_ptr: i32, key_len: i32) -> Result<i64, RuntimeError> {
+    let (state, mut store) = env.data_and_store_mut();
+    let guest = guest_memory(state)?;
+    let key = guest.read(&store, key_ptr as u32, key_len as u32).map_err(RuntimeError::new)?;
+
+    let value = match state.services.kv.get(&state.function_id, &key) {
+        Ok(value) => value,
+        Err(e) => return Ok(e.code() as i64),
+    };
+    let ptr = guest.write(&mut store, &value).map_err(RuntimeError::new)?;
+    Ok(((ptr as u32 as i64) << 32) | value.len() as i64)
+}
+
+fn kv_put(mut env: FunctionEnvMut<HostState>, key_ptr: i32, key_len: i32, value_ptr: i32, value_len: i32) -> Result<i32, RuntimeError> {
+    let (state, store) = env.data_and_store_mut();
+    let guest = guest_memory(state)?;
+    if value_len as u32 as usize > state.services.kv.quota().max_value_bytes {
+        return Ok(KvError::TooLarge.code());
+    }
+    let key = guest.read(&store, key_ptr as u32, key_len as u32).map_err(RuntimeError::new)?;
+    let value = guest.read(&store, value_ptr as u32, value_len as u32).map_err(RuntimeError::new)?;
+
+    Ok(match state.services.kv.put(&state.function_id, &key, &value) {
+        Ok(()) => 0,
+        Err(e) => e.code(),
+    })
+}
+
+fn kv_delete(mut env: FunctionEnvMut<HostState>, key_ptr: i32, key_len: i32) -> Result<i32, RuntimeError> {
+    let (state, store) = env.data_and_store_mut();
+    let guest = guest_memory(state)?;
+    let key = guest.read(&store, key_ptr as u32, key_len as u32).map_err(RuntimeError::new)?;
+
+    Ok(match state.services.kv.delete(&state.function_id, &key) {
+        Ok(()) => 0,
+        Err(e) => e.code(),
+    })
+}
+
+// 0: 記録した, -1: レート制限で破棄, -2: 不正なレベル (0=trace .. 4=error)
+fn log(mut env: FunctionEnvMut<HostState>, level: i32, message_ptr: i32, message_len: i32) -> Result<i32, RuntimeError> {
+    let (state, store) = env.data_and_store_mut();
+    let guest = guest_memory(state)?;
+    let level = match guest_log::level_name(level) {
+        Some(level) => level,
+        None => return Ok(-2),
+    };
+
+    let len = (message_len as u32).min(MAX_LOG_MESSAGE_BYTES as u32);
+    let message = guest.read(&store, message_ptr as u32, len).map_err(RuntimeError::new)?;
+    let message = String::from_utf8_lossy(&message).into_owned();
+
+    let recorded = state.services.logs.record(level, &state.function_id, &state.version, &state.request_id, message);
+    Ok(if recorded { 0 } else { -1 })
+}
+
+fn telemetry_bridge(state: &HostState) -> Result<&TelemetryBridge, TelemetryError> {
+    match &state.services.telemetry {
+        Some(bridge) if state.telemetry_enabled => Ok(bridge),
+        _ => Err(TelemetryError::Unavailable),
+    }
+}
+
+// 読み取り値 (JSON のオブジェクトまたは配列) を書き込み、件数を返す。失敗時は負の値
+fn telemetry_write(mut env: FunctionEnvMut<HostState>, readings_ptr: i32, readings_len: i32) -> Result<i32, RuntimeError> {
+    let (state, store) = env.data_and_store_mut();
+    let guest = guest_memory(state)?;
+    let bridge = match telemetry_bridge(state) {
+        Ok(bridge) => bridge,
+        Err(e) => return Ok(e.code()),
+    };
+    let readings = guest.read(&store, readings_ptr as u32, readings_len as u32).map_err(RuntimeError::new)?;
+
+    Ok(match bridge.write(&state.function_id, &readings) {
+        Ok(count) => count as i32,
+        Err(e) => e.code(),
+    })
+}
+
+// query: {"device_id"?, "sensor_id"?, "limit"?}。新しい順の JSON 配列の (ptr << 32) | len を返す
+fn telemetry_read(mut env: FunctionEnvMut<HostState>, query_ptr: i32, query_len: i32) -> Result<i64, RuntimeError> {
+    let (state, mut store) = env.data_and_store_mut();
+    let guest = guest_memory(state)?;
+    let bridge = match telemetry_bridge(state) {
+        Ok(bridge) => bridge,
+        Err(e) => return Ok(e.code() as i64),
+    };
+    let query = guest.read(&store, query_ptr as u32, query_len as u32).map_err(RuntimeError::new)?;
+    let query: TelemetryQuery = if query.is_empty() {
+        TelemetryQuery::default()
+    } else {
+        match serde_json::from_slice(&query) {
+            Ok(query) => query,
+            Err(e) => return Ok(TelemetryError::InvalidReading(e.to_string()).code() as i64),
+        }
+    };
+
+    let records = match bridge.read(&state.function_id, &query) {
+        Ok(records) => records,
+        Err(e) => return Ok(e.code() as i64),
+    };
+    let encoded = serde_json::to_vec(&records).map_err(|e| RuntimeError::new(e.to_string()))?;
+    let ptr = guest.write(&mut store, &encoded).map_err(RuntimeError::new)?;
+    Ok(((ptr as u32 as i64) << 32) | encoded.len() as i64)
+}
@@ -0,0 +1,212 @@
+use crate::infrastructure::metrics::{OUTBOUND_FETCHES, OUTBOUND_FETCH_LATENCY};
+use reqwest::Url;
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+
+// 1 回の fetch に掛けられる最大時間。呼び出しの残り時間の方が短ければそちらを使う
+pub const DEFAULT_FETCH_TIMEOUT_MS: u64 = 10_000;
+pub const MAX_FETCH_REQUEST_BYTES: usize = 1024 * 1024;
+pub const MAX_FETCH_RESPONSE_BYTES: usize = 5 * 1024 * 1024;
+
+#[derive(Debug, PartialEq)]
+pub enum FetchError {
+    InvalidRequest(String),
+    Denied(String),
+    Timeout,
+    TooLarge,
+    Failed(String),
+}
+
+impl FetchError {
+    // ゲストには負の値で返す
+    pub fn code(&self) -> i64 {
+        match self {
+            FetchError::Denied(_) => -1,
+            FetchError::Timeout => -2,
+            FetchError::TooLarge => -3,
+            FetchError::Failed(_) => -4,
+            FetchError::InvalidRequest(_) => -5,
+        }
+    }
+
+    fn outcome(&self) -> &'static str {
+        match self {
+            FetchError::Denied(_) => "denied",
+            FetchError::Timeout => "timeout",
+            FetchError::TooLarge => "too_large",
+            FetchError::Failed(_) => "error",
+            FetchError::InvalidRequest(_) => "invalid",
+        }
+    }
+}
+
+pub struct FetchRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl FetchRequest {
+    // "<METHOD> <url>\r\n" + "Name: value\r\n"* + "\r\n" + body
+    pub fn decode(bytes: &[u8]) -> Result<Self, FetchError> {
+        let invalid = |msg: &str| FetchError::InvalidRequest(msg.to_string());
+
+        let (request_line, mut rest) = split_line(bytes).ok_or_else(|| invalid("missing request line"))?;
+        let request_line = std::str::from_utf8(request_line).map_err(|_| invalid("request line is not UTF-8"))?;
+        let (method, url) = request_line.split_once(' ').ok_or_else(|| invalid("expected `<METHOD> <url>`"))?;
+
+        let mut headers = Vec::new();
+        loop {
+            let (line, next) = split_line(rest).ok_or_else(|| invalid("missing blank line after headers"))?;
+            rest = next;
+            if line.is_empty() {
+                break;
+            }
+            let line = std::str::from_utf8(line).map_err(|_| invalid("header is not UTF-8"))?;
+            let (name, value) = line.split_once(':').ok_or_else(|| invalid("malformed header"))?;
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+
+        Ok(Self {
+            method: method.to_string(),
+            url: url.trim().to_string(),
+            headers,
+            body: rest.to_vec(),
+        })
+    }
+}
+
+pub struct FetchResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl FetchResponse {
+    // ゲストのレスポンスエンベロープと同じ形式
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = format!("{}\r\n", self.status).into_bytes();
+        for (name, value) in &self.headers {
+            out.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+        }
+        out.extend_from_slice(b"\r\n");
+        out.extend_from_slice(&self.body);
+        out
+    }
+}
+
+fn split_line(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let pos = bytes.windows(2).position(|w| w == b"\r\n")?;
+    Some((&bytes[..pos], &bytes[pos + 2..]))
+}
+
+// "api.example.com" は完全一致、"*.example.com" はサブドメインに一致する
+pub fn host_allowed(allowed_hosts: &[String], host: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    allowed_hosts.iter().any(|pattern| {
+        let pattern = pattern.to_ascii_lowercase();
+        match pattern.strip_prefix("*.") {
+            Some(suffix) => host.len() > suffix.len() && host.ends_with(&format!(".{}", suffix)),
+            None => host == pattern,
+        }
+    })
+}
+
+// ゲストからの外部 HTTP 呼び出し。WASM ワーカースレッドから同期的に呼ぶ
+pub struct HttpFetcher {
+    client: reqwest::Client,
+    runtime: Handle,
+}
+
+impl HttpFetcher {
+    pub fn new(runtime: Handle) -> Self {
+        // リダイレクト先で許可リストを迂回されないよう、リダイレクトは追わずにゲストへ返す
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("Failed to build HTTP client");
+        Self { client, runtime }
+    }
+
+    pub fn fetch(
+        &self,
+        function_id: &str,
+        allowed_hosts: &[String],
+        request: FetchRequest,
+        timeout: Duration,
+    ) -> Result<FetchResponse, FetchError> {
+        let start = Instant::now();
+        let result = self.send(allowed_hosts, request, timeout);
+
+        let outcome = match &result {
+            Ok(_) => "ok",
+            Err(e) => e.outcome(),
+        };
+        OUTBOUND_FETCHES.with_label_values(&[function_id, outcome]).inc();
+        OUTBOUND_FETCH_LATENCY.with_label_values(&[function_id]).observe(start.elapsed().as_secs_f64());
+
+        result
+    }
+
+    fn send(&self, allowed_hosts: &[String], request: FetchRequest, timeout: Duration) -> Result<FetchResponse, FetchError> {
+        let url = Url::parse(&request.url)
+            .map_err(|e| FetchError::InvalidRequest(format!("invalid url: {}", e)))?;
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(FetchError::Denied(format!("scheme {} is not allowed", url.scheme())));
+        }
+        let host = url.host_str().unwrap_or_default();
+        if !host_allowed(allowed_hosts, host) {
+            return Err(FetchError::Denied(format!("host {} is not in the allowlist", host)));
+        }
+        if request.body.len() > MAX_FETCH_REQUEST_BYTES {
+            return Err(FetchError::TooLarge);
+        }
+        if timeout.is_zero() {
+            return Err(FetchError::Timeout);
+        }
+
+        let method = reqwest::Method::from_bytes(request.method.as_bytes())
+            .map_err(|_| FetchError::InvalidRequest(format!("invalid method {}", request.method)))?;
+        let mut builder = self.client.request(method, url);
+        for (name, value) in &request.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        let builder = builder.body(request.body);
+
+        let call = async move {
+            let mut response = builder.send().await.map_err(map_error)?;
+
+            if response.content_length().is_some_and(|len| len > MAX_FETCH_RESPONSE_BYTES as u64) {
+                return Err(FetchError::TooLarge);
+            }
+
+            let status = response.status().as_u16();
+            let headers = response.headers().iter()
+                .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+                .collect();
+
+            let mut body = Vec::new();
+            while let Some(chunk) = response.chunk().await.map_err(map_error)? {
+                if body.len() + chunk.len() > MAX_FETCH_RESPONSE_BYTES {
+                    return Err(FetchError::TooLarge);
+                }
+                body.extend_from_slice(&chunk);
+            }
+
+            Ok(FetchResponse { status, headers, body })
+        };
+
+        self.runtime.block_on(async {
+            tokio::time::timeout(timeout, call).await.unwrap_or(Err(FetchError::Timeout))
+        })
+    }
+}
+
+fn map_error(e: reqwest::Error) -> FetchError {
+    if e.is_timeout() {
+        FetchError::Timeout
+    } else {
+        FetchError::Failed(e.to_string())
+    }
+}
@@ -0,0 +1,89 @@
+#[cfg(test)]
+mod tests {
+    use crate::infrastructure::http_fetch::*;
+    use axum::{routing::get, Router};
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    // ローカルのモックサーバー
+    async fn mock_server() -> SocketAddr {
+        let app = Router::new()
+            .route("/hello", get(|| async { "hello from upstream" }))
+            .route("/large", get(|| async { vec![b'x'; MAX_FETCH_RESPONSE_BYTES + 1] }))
+            .route("/slow", get(|| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                "late"
+            }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    fn request(url: String) -> FetchRequest {
+        FetchRequest { method: "GET".to_string(), url, headers: Vec::new(), body: Vec::new() }
+    }
+
+    // fetch はワーカースレッドから同期的に呼ばれる
+    async fn fetch(url: String, allowed: &[&str], timeout: Duration) -> Result<FetchResponse, FetchError> {
+        let fetcher = Arc::new(HttpFetcher::new(tokio::runtime::Handle::current()));
+        let allowed: Vec<String> = allowed.iter().map(|h| h.to_string()).collect();
+        tokio::task::spawn_blocking(move || fetcher.fetch("func1", &allowed, request(url), timeout))
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn test_decode_request_envelope() {
+        let request = FetchRequest::decode(b"POST https://api.example.com/v1\r\nContent-Type: application/json\r\n\r\n{}").unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.url, "https://api.example.com/v1");
+        assert_eq!(request.headers, vec![("Content-Type".to_string(), "application/json".to_string())]);
+        assert_eq!(request.body, b"{}");
+
+        assert!(matches!(FetchRequest::decode(b"GET\r\n\r\n"), Err(FetchError::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn test_host_allowlist() {
+        let allowed = vec!["api.example.com".to_string(), "*.internal.example".to_string()];
+        assert!(host_allowed(&allowed, "api.example.com"));
+        assert!(host_allowed(&allowed, "API.example.com"));
+        assert!(host_allowed(&allowed, "svc.internal.example"));
+        assert!(!host_allowed(&allowed, "internal.example"));
+        assert!(!host_allowed(&allowed, "evil.com"));
+        assert!(!host_allowed(&[], "api.example.com"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_fetch_allowed_host() {
+        let addr = mock_server().await;
+        let response = fetch(format!("http://{}/hello", addr), &["127.0.0.1"], Duration::from_secs(2)).await.unwrap();
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"hello from upstream");
+        assert!(response.encode().starts_with(b"200\r\n"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_fetch_denied_host() {
+        let addr = mock_server().await;
+        let result = fetch(format!("http://{}/hello", addr), &["api.example.com"], Duration::from_secs(2)).await;
+        assert!(matches!(result, Err(FetchError::Denied(_))));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_fetch_response_too_large() {
+        let addr = mock_server().await;
+        let result = fetch(format!("http://{}/large", addr), &["127.0.0.1"], Duration::from_secs(2)).await;
+        assert_eq!(result.err(), Some(FetchError::TooLarge));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_fetch_timeout() {
+        let addr = mock_server().await;
+        let result = fetch(format!("http://{}/slow", addr), &["127.0.0.1"], Duration::from_millis(100)).await;
+        assert_eq!(result.err(), Some(FetchError::Timeout));
+    }
+}
@@ -0,0 +1,217 @@
+use crate::domain::{KvAction, KvCommand};
+use crate::infrastructure::metrics::KV_OPERATIONS;
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+// 関数 (名前空間) ごとの上限
+#[derive(Clone, Copy)]
+pub struct KvQuota {
+    pub max_keys: u64,
+    pub max_total_bytes: u64,
+    pub max_key_bytes: usize,
+    pub max_value_bytes: usize,
+}
+
+impl Default for KvQuota {
+    fn default() -> Self {
+        Self {
+            max_keys: 1000,
+            max_total_bytes: 1024 * 1024,
+            max_key_bytes: 512,
+            max_value_bytes: 64 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum KvError {
+    NotFound,
+    QuotaExceeded,
+    TooLarge,
+    Storage(String),
+}
+
+impl KvError {
+    // ゲストには負の値で返す
+    pub fn code(&self) -> i32 {
+        match self {
+            KvError::NotFound => -1,
+            KvError::QuotaExceeded => -2,
+            KvError::TooLarge => -3,
+            KvError::Storage(_) => -4,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for KvError {
+    fn from(e: rusqlite::Error) -> Self {
+        KvError::Storage(e.to_string())
+    }
+}
+
+// ノードローカルの KV ストア (SQLite)。キーは function_id ごとの名前空間に分かれる
+pub struct KvStore {
+    conn: Mutex<Connection>,
+    quota: KvQuota,
+}
+
+impl KvStore {
+    pub fn open(path: &str, quota: KvQuota) -> Result<Self, String> {
+        let conn = Connection::open(path).map_err(|e| format!("Failed to open KV store {}: {}", path, e))?;
+        Self::with_connection(conn, quota)
+    }
+
+    #[cfg(test)]
+    pub fn in_memory(quota: KvQuota) -> Result<Self, String> {
+        let conn = Connection::open_in_memory().map_err(|e| e.to_string())?;
+        Self::with_connection(conn, quota)
+    }
+
+    fn with_connection(conn: Connection, quota: KvQuota) -> Result<Self, String> {
+        conn.execute_batch(
+            r#"
+            CREATE TABLE IF NOT EXISTS kv_entries (
+                function_id TEXT NOT NULL,
+                key BLOB NOT NULL,
+                value BLOB NOT NULL,
+                updated_at INTEGER DEFAULT (strftime('%s', 'now')),
+                PRIMARY KEY (function_id, key)
+            );
+            -- 名前空間ごとに最後に適用した kv_commands の command_id
+            CREATE TABLE IF NOT EXISTS kv_applied_commands (
+                function_id TEXT PRIMARY KEY,
+                command_id INTEGER NOT NULL
+            );
+            "#,
+        ).map_err(|e| format!("Failed to initialize KV store: {}", e))?;
+
+        Ok(Self { conn: Mutex::new(conn), quota })
+    }
+
+    pub fn quota(&self) -> KvQuota {
+        self.quota
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn get(&self, function_id: &str, key: &[u8]) -> Result<Vec<u8>, KvError> {
+        KV_OPERATIONS.with_label_values(&[function_id, "get"]).inc();
+        self.lock()
+            .query_row(
+                "SELECT value FROM kv_entries WHERE function_id = ?1 AND key = ?2",
+                params![function_id, key],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or(KvError::NotFound)
+    }
+
+    pub fn put(&self, function_id: &str, key: &[u8], value: &[u8]) -> Result<(), KvError> {
+        KV_OPERATIONS.with_label_values(&[function_id, "put"]).inc();
+        let mut conn = self.lock();
+        let tx = conn.transaction()?;
+        self.put_in(&tx, function_id, key, value)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn put_in(&self, tx: &Transaction, function_id: &str, key: &[u8], value: &[u8]) -> Result<(), KvError> {
+        if key.is_empty() || key.len() > self.quota.max_key_bytes || value.len() > self.quota.max_value_bytes {
+            return Err(KvError::TooLarge);
+        }
+
+        // 上書きするキー自身のサイズは除いて判定する
+        let (keys, bytes): (u64, u64) = tx.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(length(key) + length(value)), 0)
+             FROM kv_entries WHERE function_id = ?1 AND key != ?2",
+            params![function_id, key],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        if keys + 1 > self.quota.max_keys || bytes + (key.len() + value.len()) as u64 > self.quota.max_total_bytes {
+            KV_OPERATIONS.with_label_values(&[function_id, "quota_exceeded"]).inc();
+            return Err(KvError::QuotaExceeded);
+        }
+
+        tx.execute(
+            "INSERT INTO kv_entries (function_id, key, value, updated_at)
+             VALUES (?1, ?2, ?3, strftime('%s', 'now'))
+             ON CONFLICT (function_id, key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at",
+            params![function_id, key, value],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete(&self, function_id: &str, key: &[u8]) -> Result<(), KvError> {
+        KV_OPERATIONS.with_label_values(&[function_id, "delete"]).inc();
+        let deleted = self.lock().execute(
+            "DELETE FROM kv_entries WHERE function_id = ?1 AND key = ?2",
+            params![function_id, key],
+        )?;
+        if deleted == 0 { Err(KvError::NotFound) } else { Ok(()) }
+    }
+
+    // コントロールプレーンからの投入。エントリごとにクォータを適用し、1 つでも失敗すればどれも書かない
+    #[cfg(test)]
+    pub fn seed(&self, function_id: &str, entries: &HashMap<String, String>) -> Result<usize, KvError> {
+        let mut conn = self.lock();
+        let tx = conn.transaction()?;
+        let seeded = self.seed_in(&tx, function_id, entries)?;
+        tx.commit()?;
+        Ok(seeded)
+    }
+
+    fn seed_in(&self, tx: &Transaction, function_id: &str, entries: &HashMap<String, String>) -> Result<usize, KvError> {
+        for (key, value) in entries {
+            self.put_in(tx, function_id, key.as_bytes(), value.as_bytes())?;
+        }
+        Ok(entries.len())
+    }
+
+    #[cfg(test)]
+    pub fn clear(&self, function_id: &str) -> Result<usize, KvError> {
+        let deleted = self.lock().execute(
+            "DELETE FROM kv_entries WHERE function_id = ?1",
+            params![function_id],
+        )?;
+        Ok(deleted)
+    }
+
+    // kv_commands の 1 件を適用する。command_id がその名前空間で最後に適用したもの以下なら
+    // 再送とみなして何もせず None を返す。操作と command_id の記録は同じトランザクションで行う
+    pub fn apply(&self, command: &KvCommand) -> Result<Option<usize>, KvError> {
+        let mut conn = self.lock();
+        let tx = conn.transaction()?;
+
+        if let Some(command_id) = command.command_id {
+            let last_applied: Option<u64> = tx.query_row(
+                "SELECT command_id FROM kv_applied_commands WHERE function_id = ?1",
+                params![command.function_id],
+                |row| row.get(0),
+            ).optional()?;
+            if last_applied.is_some_and(|last| command_id <= last) {
+                return Ok(None);
+            }
+        }
+
+        let count = match command.action {
+            KvAction::Seed => self.seed_in(&tx, &command.function_id, &command.entries)?,
+            KvAction::Clear => tx.execute(
+                "DELETE FROM kv_entries WHERE function_id = ?1",
+                params![command.function_id],
+            )?,
+        };
+
+        if let Some(command_id) = command.command_id {
+            tx.execute(
+                "INSERT INTO kv_applied_commands (function_id, command_id) VALUES (?1, ?2)
+                 ON CONFLICT (function_id) DO UPDATE SET command_id = excluded.command_id",
+                params![command.function_id, command_id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(Some(count))
+    }
+}
@@ -0,0 +1,122 @@
+#[cfg(test)]
+mod tests {
+    use crate::domain::{KvAction, KvCommand};
+    use crate::infrastructure::kv::*;
+    use std::collections::HashMap;
+
+    fn store(quota: KvQuota) -> KvStore {
+        KvStore::in_memory(quota).unwrap()
+    }
+
+    #[test]
+    fn test_put_get_delete() {
+        let kv = store(KvQuota::default());
+
+        assert_eq!(kv.get("func1", b"counter"), Err(KvError::NotFound));
+        kv.put("func1", b"counter", b"1").unwrap();
+        kv.put("func1", b"counter", b"2").unwrap();
+        assert_eq!(kv.get("func1", b"counter").unwrap(), b"2");
+
+        kv.delete("func1", b"counter").unwrap();
+        assert_eq!(kv.delete("func1", b"counter"), Err(KvError::NotFound));
+    }
+
+    #[test]
+    fn test_namespaces_are_isolated() {
+        let kv = store(KvQuota::default());
+        kv.put("func1", b"flag", b"on").unwrap();
+
+        assert_eq!(kv.get("func2", b"flag"), Err(KvError::NotFound));
+        assert_eq!(kv.clear("func2").unwrap(), 0);
+        assert_eq!(kv.get("func1", b"flag").unwrap(), b"on");
+    }
+
+    #[test]
+    fn test_quota() {
+        let kv = store(KvQuota { max_keys: 2, max_total_bytes: 20, max_key_bytes: 4, max_value_bytes: 8 });
+
+        assert_eq!(kv.put("func1", b"toolong", b"v"), Err(KvError::TooLarge));
+        assert_eq!(kv.put("func1", b"k", b"123456789"), Err(KvError::TooLarge));
+
+        kv.put("func1", b"a", b"12345678").unwrap();
+        kv.put("func1", b"b", b"1").unwrap();
+        assert_eq!(kv.put("func1", b"c", b"1"), Err(KvError::QuotaExceeded));
+        // 既存キーの上書きはキー数に数えない
+        kv.put("func1", b"b", b"12345678").unwrap();
+        // 合計サイズの上限
+        kv.delete("func1", b"b").unwrap();
+        assert_eq!(kv.put("func1", b"bbbb", b"12345678"), Err(KvError::QuotaExceeded));
+        // 他の名前空間には影響しない
+        kv.put("func2", b"c", b"1").unwrap();
+    }
+
+    #[test]
+    fn test_seed_and_clear() {
+        let kv = store(KvQuota::default());
+        let entries = HashMap::from([
+            ("feature.beta".to_string(), "true".to_string()),
+            ("greeting".to_string(), "hello".to_string()),
+        ]);
+
+        assert_eq!(kv.seed("func1", &entries).unwrap(), 2);
+        assert_eq!(kv.get("func1", b"greeting").unwrap(), b"hello");
+
+        assert_eq!(kv.clear("func1").unwrap(), 2);
+        assert_eq!(kv.get("func1", b"feature.beta"), Err(KvError::NotFound));
+    }
+
+    #[test]
+    fn test_seed_is_all_or_nothing() {
+        let kv = store(KvQuota { max_keys: 2, ..KvQuota::default() });
+        kv.put("func1", b"existing", b"1").unwrap();
+        let entries = HashMap::from([
+            ("a".to_string(), "1".to_string()),
+            ("b".to_string(), "2".to_string()),
+        ]);
+
+        // 2 件目でクォータを超えるので 1 件目も書かない
+        assert_eq!(kv.seed("func1", &entries), Err(KvError::QuotaExceeded));
+        assert_eq!(kv.get("func1", b"a"), Err(KvError::NotFound));
+        assert_eq!(kv.get("func1", b"b"), Err(KvError::NotFound));
+        assert_eq!(kv.get("func1", b"existing").unwrap(), b"1");
+    }
+
+    fn command(command_id: Option<u64>, action: KvAction, entries: &[(&str, &str)]) -> KvCommand {
+        KvCommand {
+            command_id,
+            function_id: "func1".to_string(),
+            action,
+            entries: entries.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn test_retried_commands_are_applied_once() {
+        let kv = store(KvQuota::default());
+
+        assert_eq!(kv.apply(&command(Some(1), KvAction::Seed, &[("greeting", "hello")])).unwrap(), Some(1));
+        assert_eq!(kv.apply(&command(Some(2), KvAction::Clear, &[])).unwrap(), Some(1));
+
+        // 応答が届かずに再送された古いコマンドは適用しない
+        assert_eq!(kv.apply(&command(Some(1), KvAction::Seed, &[("greeting", "hello")])).unwrap(), None);
+        assert_eq!(kv.get("func1", b"greeting"), Err(KvError::NotFound));
+
+        // command_id のないコマンドは毎回適用する
+        assert_eq!(kv.apply(&command(None, KvAction::Seed, &[("greeting", "hi")])).unwrap(), Some(1));
+        assert_eq!(kv.get("func1", b"greeting").unwrap(), b"hi");
+    }
+
+    #[test]
+    fn test_failed_command_can_be_retried() {
+        let kv = store(KvQuota { max_keys: 1, ..KvQuota::default() });
+
+        let seed = command(Some(5), KvAction::Seed, &[("a", "1"), ("b", "2")]);
+        assert_eq!(kv.apply(&seed), Err(KvError::QuotaExceeded));
+
+        // 失敗したコマンドは適用済みにならない
+        kv.apply(&command(Some(4), KvAction::Clear, &[])).unwrap();
+        let retry = command(Some(5), KvAction::Seed, &[("a", "1")]);
+        assert_eq!(kv.apply(&retry).unwrap(), Some(1));
+        assert_eq!(kv.get("func1", b"a").unwrap(), b"1");
+    }
+}
@@ -0,0 +1,41 @@
+use std::sync::Arc;
+use wasmer::wasmparser::Operator;
+use wasmer::sys::EngineBuilder;
+use wasmer::{CompilerConfig, Cranelift, Engine, Instance, Store};
+use wasmer_middlewares::metering::{get_remaining_points, set_remaining_points, MeteringPoints};
+use wasmer_middlewares::Metering;
+
+// 1 命令 = 1 ポイントとし、max_execution_ms をポイント数に換算する
+pub const FUEL_PER_MS: u64 = 1_000_000;
+pub const DEFAULT_MAX_EXECUTION_MS: u32 = 30_000;
+
+fn cost_function(_operator: &Operator) -> u64 {
+    1
+}
+
+// Metering ミドルウェアを組み込んだエンジン。このエンジンでコンパイルしたモジュールは
+// インスタンスごとに残りポイントを持ち、使い切るとトラップする。
+// 初期値はインスタンス化時の start 関数用で、呼び出しごとに set_budget で再設定する。
+pub fn metered_engine() -> Engine {
+    let metering = Arc::new(Metering::new(execution_budget(DEFAULT_MAX_EXECUTION_MS), cost_function));
+    let mut compiler = Cranelift::default();
+    compiler.push_middleware(metering);
+    EngineBuilder::new(compiler).into()
+}
+
+// 0 は未設定として既定値を使う
+pub fn effective_timeout_ms(max_execution_ms: u32) -> u32 {
+    if max_execution_ms == 0 { DEFAULT_MAX_EXECUTION_MS } else { max_execution_ms }
+}
+
+pub fn execution_budget(max_execution_ms: u32) -> u64 {
+    effective_timeout_ms(max_execution_ms) as u64 * FUEL_PER_MS
+}
+
+pub fn set_budget(store: &mut Store, instance: &Instance, max_execution_ms: u32) {
+    set_remaining_points(store, instance, execution_budget(max_execution_ms));
+}
+
+pub fn budget_exhausted(store: &mut Store, instance: &Instance) -> bool {
+    matches!(get_remaining_points(store, instance), MeteringPoints::Exhausted)
+}
@@ -1,11 +1,35 @@
 use lazy_static::lazy_static;
-use prometheus::{Counter, HistogramOpts, HistogramVec};
+use prometheus::{
+    register_counter, register_counter_vec, register_histogram, register_histogram_vec, register_int_gauge,
+    register_int_gauge_vec, Counter, CounterVec, Histogram, HistogramVec, IntGauge, IntGaugeVec,
+};
 
 lazy_static! {
-    pub static ref INVOKE_COUNT: Counter = Counter::new("wasm_invoke_count_total", "Total WASM invocations").unwrap();
-    pub static ref INVOKE_LATENCY: HistogramVec = HistogramVec::new(
-        HistogramOpts::new("wasm_invoke_latency_seconds", "WASM invocation latency"),
+    pub static ref INVOKE_COUNT: Counter = register_counter!("wasm_invoke_count_total", "Total WASM invocations").unwrap();
+    pub static ref INVOKE_LATENCY: HistogramVec = register_histogram_vec!(
+        "wasm_invoke_latency_seconds", "WASM invocation latency",
         &["function"]
     ).unwrap();
-    pub static ref INVOKE_ERRORS: Counter = Counter::new("wasm_invoke_errors_total", "Total WASM errors").unwrap();
+    pub static ref INVOKE_ERRORS: Counter = register_counter!("wasm_invoke_errors_total", "Total WASM errors").unwrap();
+    pub static ref INVOKE_TIMEOUTS: Counter = register_counter!("wasm_invoke_timeouts_total", "Total WASM invocations that exceeded max_execution_ms").unwrap();
+    pub static ref INVOKE_THROTTLED: Counter = register_counter!("wasm_invoke_throttled_total", "Invocations rejected by a per-function concurrency limit").unwrap();
+    pub static ref EXECUTOR_QUEUE_DEPTH: IntGauge = register_int_gauge!("wasm_executor_queue_depth", "Invocations waiting for a WASM worker").unwrap();
+    pub static ref EXECUTOR_QUEUE_WAIT: Histogram = register_histogram!("wasm_executor_queue_wait_seconds", "Time spent waiting for a WASM worker").unwrap();
+    pub static ref POOL_IDLE_INSTANCES: IntGaugeVec = register_int_gauge_vec!("wasm_pool_idle_instances", "Idle pooled instances per function", &["function"]).unwrap();
+    pub static ref POOL_IN_FLIGHT_INSTANCES: IntGaugeVec = register_int_gauge_vec!("wasm_pool_in_flight_instances", "Checked-out or compiling instances per function", &["function"]).unwrap();
+    pub static ref POOL_TOTAL_INSTANCES: IntGauge = register_int_gauge!("wasm_pool_total_instances", "Idle plus in-flight instances on this node").unwrap();
+    pub static ref VERSION_INVOCATIONS: CounterVec = register_counter_vec!("wasm_version_invocations_total", "Invocations per function version, by outcome (ok, error)", &["function", "version", "outcome"]).unwrap();
+    pub static ref INSTANCE_STARTS: CounterVec = register_counter_vec!("wasm_instance_starts_total", "Invocations served by a new (cold) or pooled (warm) instance", &["function", "kind"]).unwrap();
+    pub static ref INSTANCE_DISCARDS: CounterVec = register_counter_vec!("wasm_instance_discards_total", "Instances dropped after an invocation instead of being returned to the pool", &["function", "reason"]).unwrap();
+    pub static ref MODULE_CACHE_LOOKUPS: CounterVec = register_counter_vec!("wasm_module_cache_lookups_total", "Compiled module lookups by source (memory, disk, compiled)", &["source"]).unwrap();
+    pub static ref OUTBOUND_FETCHES: CounterVec = register_counter_vec!("wasm_outbound_fetches_total", "Outbound HTTP calls made by guests, by outcome", &["function", "outcome"]).unwrap();
+    pub static ref OUTBOUND_FETCH_LATENCY: HistogramVec = register_histogram_vec!(
+        "wasm_outbound_fetch_latency_seconds", "Outbound HTTP call latency",
+        &["function"]
+    ).unwrap();
+    pub static ref KV_OPERATIONS: CounterVec = register_counter_vec!("wasm_kv_operations_total", "KV store operations by guests and the control plane", &["function", "op"]).unwrap();
+    pub static ref GUEST_LOGS: CounterVec = register_counter_vec!("wasm_guest_logs_total", "Log records emitted by guests", &["function", "level"]).unwrap();
+    pub static ref GUEST_LOGS_DROPPED: CounterVec = register_counter_vec!("wasm_guest_logs_dropped_total", "Guest log records dropped by the per-function rate limit", &["function"]).unwrap();
+    pub static ref TELEMETRY_OPERATIONS: CounterVec = register_counter_vec!("wasm_telemetry_operations_total", "Readings written to / queries against the edge-agent telemetry database", &["function", "op"]).unwrap();
+    pub static ref EXECUTOR_REJECTED: Counter = register_counter!("wasm_executor_rejected_total", "Invocations rejected because the worker queue was full").unwrap();
 }
@@ -1,12 +1,38 @@
 pub mod repositories;
+pub mod router;
 pub mod pool;
 pub mod cp_client;
 pub mod metrics;
 pub mod cache;
+pub mod artifact;
+pub mod guest;
+pub mod metering;
+pub mod executor;
+pub mod module_cache;
+pub mod wasi;
+pub mod http_fetch;
+pub mod host;
+pub mod kv;
+pub mod guest_log;
+pub mod telemetry;
 mod routing_tests;
+mod executor_tests;
+mod pool_tests;
+mod wasi_tests;
+mod http_fetch_tests;
+mod kv_tests;
+mod guest_log_tests;
+mod telemetry_tests;
+mod versioning_tests;
+mod artifact_tests;
+mod module_cache_tests;
+mod cache_tests;
 
 pub use repositories::*;
 pub use pool::*;
 pub use cp_client::*;
 pub use metrics::*;
 pub use cache::*;
+pub use artifact::*;
+pub use guest::*;
+pub use executor::*;
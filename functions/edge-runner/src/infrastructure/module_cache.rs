@@ -0,0 +1,190 @@
+use crate::domain::FunctionMetadata;
+use crate::infrastructure::metrics::MODULE_CACHE_LOOKUPS;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use wasmer::{Engine, Module};
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct ModuleKey {
+    function_id: String,
+    version: String,
+    sha256: String,
+}
+
+// メモリ上のモジュールと、最後に使った順番
+#[derive(Default)]
+struct Modules {
+    entries: HashMap<ModuleKey, (Module, u64)>,
+    clock: u64,
+}
+
+impl Modules {
+    fn get(&mut self, key: &ModuleKey) -> Option<Module> {
+        self.clock += 1;
+        let (module, last_used) = self.entries.get_mut(key)?;
+        *last_used = self.clock;
+        Some(module.clone())
+    }
+
+    // 上限を超えたら最も長く使われていないモジュールを外す
+    fn insert(&mut self, key: ModuleKey, module: Module, max_modules: usize) {
+        self.clock += 1;
+        self.entries.insert(key, (module, self.clock));
+        while self.entries.len() > max_modules {
+            let oldest = match self.entries.iter().min_by_key(|(_, (_, last_used))| *last_used) {
+                Some((key, _)) => key.clone(),
+                None => break,
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+// コンパイル済みモジュールのキャッシュ。
+// メモリ上には最大 max_modules 個を保持し、ディレクトリが指定されていればシリアライズして LocalWasmCache の隣に保存する。
+// メモリから外れたモジュールはディスクから読み直す
+pub struct ModuleCache {
+    engine: Engine,
+    modules: Mutex<Modules>,
+    max_modules: usize,
+    disk_dir: Option<PathBuf>,
+}
+
+impl ModuleCache {
+    pub fn new(engine: Engine, disk_dir: Option<PathBuf>, max_modules: usize) -> Self {
+        Self {
+            engine,
+            modules: Mutex::new(Modules::default()),
+            max_modules: max_modules.max(1),
+            disk_dir,
+        }
+    }
+
+    // ワーカースレッドから呼ぶこと (コンパイル/ディスク IO を伴う)
+    pub fn get_or_compile(&self, metadata: &FunctionMetadata, wasm_bytes: &[u8]) -> Result<Module, String> {
+        let key = key_of(metadata);
+
+        if let Some(module) = self.lock().get(&key) {
+            MODULE_CACHE_LOOKUPS.with_label_values(&["memory"]).inc();
+            return Ok(module.clone());
+        }
+
+        if let Some(module) = self.load_from_disk(&key) {
+            MODULE_CACHE_LOOKUPS.with_label_values(&["disk"]).inc();
+            self.lock().insert(key, module.clone(), self.max_modules);
+            return Ok(module);
+        }
+
+        let module = Module::new(&self.engine, wasm_bytes)
+            .map_err(|e| format!("Failed to compile WASM: {}", e))?;
+        MODULE_CACHE_LOOKUPS.with_label_values(&["compiled"]).inc();
+
+        if let Err(e) = self.store_to_disk(&key, &module) {
+            eprintln!("Failed to persist compiled module of {} {}: {}", key.function_id, key.version, e);
+        }
+        self.lock().insert(key, module.clone(), self.max_modules);
+
+        Ok(module)
+    }
+
+    // 無効化したバージョンのモジュールをメモリから外す。ロールバックに備えてディスクには残す
+    pub fn evict(&self, metadata: &FunctionMetadata) {
+        self.lock().entries.remove(&key_of(metadata));
+    }
+
+    // 保持しなくなったバージョンのモジュールをメモリとディスクから削除する。
+    // 同じバージョンを別のアーティファクトでコンパイルしたものも残さない
+    pub fn remove(&self, metadata: &FunctionMetadata) {
+        self.lock().entries.retain(|key, _| key.function_id != metadata.function_id || key.version != metadata.version);
+
+        let Some(dir) = self.disk_dir.as_ref().map(|dir| dir.join(&metadata.function_id)) else {
+            return;
+        };
+        let prefix = format!("{}-", metadata.version);
+        let Ok(read_dir) = std::fs::read_dir(&dir) else {
+            return;
+        };
+        for entry in read_dir.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            let compiled = name.strip_prefix(&prefix)
+                .and_then(|rest| rest.strip_suffix(".wasmu"))
+                .is_some_and(|sha256| !sha256.contains('-'));
+            if compiled {
+                if let Err(e) = std::fs::remove_file(entry.path()) {
+                    eprintln!("Failed to remove compiled module {}: {}", entry.path().display(), e);
+                }
+            }
+        }
+        // 最後のバージョンだった場合はディレクトリも消す (空でなければ失敗するだけ)
+        let _ = std::fs::remove_dir(&dir);
+    }
+
+    #[cfg(test)]
+    pub(crate) fn in_memory(&self, metadata: &FunctionMetadata) -> bool {
+        self.lock().entries.contains_key(&key_of(metadata))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Modules> {
+        self.modules.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // sha256 はアーティファクトのハッシュ。SHA が変わった同一バージョンは別ファイルになる
+    fn disk_path(&self, key: &ModuleKey) -> Option<PathBuf> {
+        self.disk_dir.as_ref().map(|dir| {
+            dir.join(&key.function_id)
+                .join(format!("{}-{}.wasmu", key.version, key.sha256))
+        })
+    }
+
+    fn load_from_disk(&self, key: &ModuleKey) -> Option<Module> {
+        let path = self.disk_path(key)?;
+        let bytes = std::fs::read(&path).ok()?;
+
+        // SAFETY: ランナー自身がこのエンジンでシリアライズしたファイルのみを読む。
+        // wasmer のバージョンやターゲットが異なる場合はエラーになるので再コンパイルする。
+        match unsafe { Module::deserialize(&self.engine, bytes) } {
+            Ok(module) => Some(module),
+            Err(e) => {
+                eprintln!("Discarding stale compiled module {}: {}", path.display(), e);
+                let _ = std::fs::remove_file(&path);
+                None
+            }
+        }
+    }
+
+    fn store_to_disk(&self, key: &ModuleKey, module: &Module) -> Result<(), String> {
+        let path = match self.disk_path(key) {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let bytes = module.serialize().map_err(|e| e.to_string())?;
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        // 同じモジュールを並行してコンパイルしたワーカー同士で一時ファイルを共有しない
+        let tmp_path = path.with_extension(format!("wasmu.{}.tmp", uuid::Uuid::new_v4()));
+        let result = write_synced(&tmp_path, &bytes).and_then(|()| std::fs::rename(&tmp_path, &path));
+        if result.is_err() {
+            let _ = std::fs::remove_file(&tmp_path);
+        }
+        result.map_err(|e| e.to_string())
+    }
+}
+
+fn write_synced(path: &std::path::Path, bytes: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut file = std::fs::File::create(path)?;
+    file.write_all(bytes)?;
+    file.sync_all()
+}
+
+fn key_of(metadata: &FunctionMetadata) -> ModuleKey {
+    ModuleKey {
+        function_id: metadata.function_id.clone(),
+        version: metadata.version.clone(),
+        sha256: metadata.sha256.clone(),
+    }
+}
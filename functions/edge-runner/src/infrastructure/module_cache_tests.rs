@@ -0,0 +1,101 @@
+#[cfg(test)]
+mod tests {
+    use crate::domain::FunctionMetadata;
+    use crate::infrastructure::module_cache::ModuleCache;
+    use crate::test_support;
+    use std::path::PathBuf;
+    use wasmer::Engine;
+
+    fn metadata(version: &str, sha256: &str) -> FunctionMetadata {
+        FunctionMetadata { sha256: sha256.to_string(), ..test_support::metadata("func1", version) }
+    }
+
+    fn wasm(value: i32) -> Vec<u8> {
+        format!("(module (func (export \"value\") (result i32) (i32.const {})))", value).into_bytes()
+    }
+
+    fn module_dir(name: &str) -> PathBuf {
+        test_support::temp_dir("module-cache-test", name)
+    }
+
+    #[test]
+    fn test_least_recently_used_module_is_dropped_from_memory() {
+        let dir = module_dir("lru");
+        let cache = ModuleCache::new(Engine::default(), Some(dir.clone()), 2);
+        let (v1, v2, v3) = (metadata("1.0.0", "aaa"), metadata("1.0.1", "bbb"), metadata("1.0.2", "ccc"));
+
+        cache.get_or_compile(&v1, &wasm(1)).unwrap();
+        cache.get_or_compile(&v2, &wasm(2)).unwrap();
+        // 1.0.0 を使い直したので 1.0.1 が外れる
+        cache.get_or_compile(&v1, &wasm(1)).unwrap();
+        cache.get_or_compile(&v3, &wasm(3)).unwrap();
+
+        assert!(cache.in_memory(&v1));
+        assert!(!cache.in_memory(&v2));
+        assert!(cache.in_memory(&v3));
+        // 外れたモジュールはディスクから読み直せる
+        assert!(dir.join("func1").join("1.0.1-bbb.wasmu").exists());
+        cache.get_or_compile(&v2, &wasm(2)).unwrap();
+        assert!(cache.in_memory(&v2));
+    }
+
+    #[test]
+    fn test_evict_keeps_compiled_module_on_disk() {
+        let dir = module_dir("evict");
+        let cache = ModuleCache::new(Engine::default(), Some(dir.clone()), 4);
+        let v1 = metadata("1.0.0", "aaa");
+
+        cache.get_or_compile(&v1, &wasm(1)).unwrap();
+        cache.evict(&v1);
+        assert!(!cache.in_memory(&v1));
+        assert!(dir.join("func1").join("1.0.0-aaa.wasmu").exists());
+    }
+
+    #[test]
+    fn test_remove_deletes_every_build_of_the_version() {
+        let dir = module_dir("remove");
+        let cache = ModuleCache::new(Engine::default(), Some(dir.clone()), 4);
+        let (old, rebuilt, other) = (metadata("1.0.0", "aaa"), metadata("1.0.0", "bbb"), metadata("1.0.0-beta", "ccc"));
+
+        cache.get_or_compile(&old, &wasm(1)).unwrap();
+        cache.get_or_compile(&rebuilt, &wasm(2)).unwrap();
+        cache.get_or_compile(&other, &wasm(3)).unwrap();
+
+        cache.remove(&rebuilt);
+        assert!(!cache.in_memory(&old));
+        assert!(!cache.in_memory(&rebuilt));
+        assert!(!dir.join("func1").join("1.0.0-aaa.wasmu").exists());
+        assert!(!dir.join("func1").join("1.0.0-bbb.wasmu").exists());
+        // 名前が前方一致するだけの別バージョンは残す
+        assert!(cache.in_memory(&other));
+        assert!(dir.join("func1").join("1.0.0-beta-ccc.wasmu").exists());
+
+        cache.remove(&other);
+        assert!(!dir.join("func1").exists());
+    }
+
+    #[test]
+    fn test_concurrent_compiles_do_not_share_temp_files() {
+        let dir = module_dir("concurrent");
+        let v1 = metadata("1.0.0", "aaa");
+
+        // 別々のキャッシュから同じモジュールを同時に保存する
+        let handles: Vec<_> = (0..4).map(|_| {
+            let (dir, v1) = (dir.clone(), v1.clone());
+            std::thread::spawn(move || {
+                ModuleCache::new(Engine::default(), Some(dir), 4).get_or_compile(&v1, &wasm(1)).unwrap();
+            })
+        }).collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let names: Vec<_> = std::fs::read_dir(dir.join("func1")).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, vec!["1.0.0-aaa.wasmu"]);
+        // 保存されたファイルはそのまま読み直せる
+        let cache = ModuleCache::new(Engine::default(), Some(dir), 4);
+        cache.get_or_compile(&v1, b"not wasm").unwrap();
+    }
+}
@@ -1,82 +1,627 @@
-use crate::domain::PooledInstance;
+use crate::domain::{FunctionMetadata, InstanceSnapshot, InvocationError, IsolationPolicy, PooledInstance};
+use crate::infrastructure::metering::metered_engine;
+use crate::infrastructure::module_cache::ModuleCache;
+use crate::infrastructure::host::{self, HostServices};
+use crate::infrastructure::wasi;
+use crate::infrastructure::metrics::{INSTANCE_DISCARDS, INSTANCE_STARTS, POOL_IDLE_INSTANCES, POOL_IN_FLIGHT_INSTANCES, POOL_TOTAL_INSTANCES};
 use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::Mutex;
-use wasmer::{Store, Module, Instance, imports, Memory, MemoryType, Pages};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use wasmer::{Engine, Extern, Store, Instance, imports, Memory, MemoryType, Mutability, Pages};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+// メモリ上に保持するコンパイル済みモジュールの数。外れたものはディスクから読み直す
+const MAX_CACHED_MODULES: usize = 64;
+
+struct FunctionSlots<T> {
+    idle: Vec<(u64, T)>,
+    in_flight: usize,
+    min_warm: usize,
+}
+
+impl<T> Default for FunctionSlots<T> {
+    fn default() -> Self {
+        Self { idle: Vec::new(), in_flight: 0, min_warm: 0 }
+    }
+}
+
+pub(crate) enum Checkout<T> {
+    Idle(T),
+    // 枠を空けるために追い出したアイドルインスタンスがあれば返す。ロックを外してから破棄すること
+    Reserved(Option<T>),
+    Full,
+}
+
+// インスタンス数の管理。ノード全体の合計 (idle + in-flight) を max_instances 以下に保つ。
+// コンパイル中のインスタンスは予約として in-flight に数える。
+// キーは関数のバージョン (function_id@version) で、バージョン間でインスタンスを共有しない。
+pub(crate) struct PoolState<T> {
+    functions: HashMap<String, FunctionSlots<T>>,
+    total: usize,
+    max_instances: usize,
+}
+
+impl<T> PoolState<T> {
+    pub(crate) fn new(max_instances: usize) -> Self {
+        Self {
+            functions: HashMap::new(),
+            total: 0,
+            max_instances,
+        }
+    }
+
+    pub(crate) fn checkout(&mut self, function_id: &str) -> Checkout<T> {
+        let slots = self.functions.entry(function_id.to_string()).or_default();
+        if let Some((_, instance)) = slots.idle.pop() {
+            slots.in_flight += 1;
+            return Checkout::Idle(instance);
+        }
+
+        let evicted = if self.total >= self.max_instances {
+            match self.evict_lru_idle() {
+                Some(instance) => Some(instance),
+                None => return Checkout::Full,
+            }
+        } else {
+            None
+        };
+
+        let slots = self.functions.entry(function_id.to_string()).or_default();
+        slots.in_flight += 1;
+        self.total += 1;
+        Checkout::Reserved(evicted)
+    }
+
+    // プレウォーム用の予約。他の関数のアイドルインスタンスは追い出さない
+    pub(crate) fn try_reserve_warm(&mut self, function_id: &str) -> bool {
+        if self.total >= self.max_instances {
+            return false;
+        }
+        let slots = self.functions.entry(function_id.to_string()).or_default();
+        if slots.idle.len() + slots.in_flight >= slots.min_warm {
+            return false;
+        }
+        slots.in_flight += 1;
+        self.total += 1;
+        true
+    }
+
+    pub(crate) fn set_min_warm(&mut self, function_id: &str, min_warm: usize) {
+        self.functions.entry(function_id.to_string()).or_default().min_warm = min_warm;
+    }
+
+    // 無効化されたバージョンのアイドルインスタンスを取り除き、以後は保持しない。
+    // 貸し出し中のものは返却後にアイドルタイムアウトで回収される
+    pub(crate) fn retire(&mut self, function_id: &str) -> Vec<T> {
+        let slots = match self.functions.get_mut(function_id) {
+            Some(slots) => slots,
+            None => return Vec::new(),
+        };
+        slots.min_warm = 0;
+        let idle = std::mem::take(&mut slots.idle);
+        self.total -= idle.len();
+        idle.into_iter().map(|(_, instance)| instance).collect()
+    }
+
+    pub(crate) fn checkin(&mut self, function_id: &str, instance: T, now: u64) {
+        let slots = self.functions.entry(function_id.to_string()).or_default();
+        slots.in_flight = slots.in_flight.saturating_sub(1);
+        slots.idle.push((now, instance));
+    }
+
+    // 予約の取り消し、または貸し出し中インスタンスの破棄
+    pub(crate) fn release(&mut self, function_id: &str) {
+        if let Some(slots) = self.functions.get_mut(function_id) {
+            slots.in_flight = slots.in_flight.saturating_sub(1);
+            self.total = self.total.saturating_sub(1);
+        }
+    }
+
+    // アイドルタイムアウトを過ぎたインスタンスを取り除く。min_warm 個のアイドルは残す
+    pub(crate) fn evict_expired(&mut self, now: u64, idle_timeout_secs: u64) -> Vec<T> {
+        let mut evicted = Vec::new();
+        for slots in self.functions.values_mut() {
+            // 新しい順に並べ、先頭 min_warm 個は期限切れでも保持する
+            slots.idle.sort_by_key(|(last_used, _)| std::cmp::Reverse(*last_used));
+            let keep_warm = slots.min_warm.min(slots.idle.len());
+            let candidates = slots.idle.split_off(keep_warm);
+            let (expired, kept): (Vec<_>, Vec<_>) = candidates
+                .into_iter()
+                .partition(|(last_used, _)| now.saturating_sub(*last_used) >= idle_timeout_secs);
+            slots.idle.extend(kept);
+            // checkout は末尾から取り出すので、最近使ったものを末尾に戻す
+            slots.idle.reverse();
+            evicted.extend(expired.into_iter().map(|(_, instance)| instance));
+        }
+        self.total -= evicted.len();
+        evicted
+    }
+
+    // 満杯のとき、最も長く使われていないアイドルインスタンスを 1 つ捨てて枠を空ける
+    fn evict_lru_idle(&mut self) -> Option<T> {
+        let oldest = self.functions.iter()
+            .filter_map(|(id, slots)| {
+                slots.idle.iter()
+                    .enumerate()
+                    .min_by_key(|(_, (last_used, _))| *last_used)
+                    .map(|(index, (last_used, _))| (*last_used, id.clone(), index))
+            })
+            .min_by_key(|(last_used, _, _)| *last_used);
+
+        let (_, function_id, index) = oldest?;
+        let (_, instance) = self.functions.get_mut(&function_id)?.idle.remove(index);
+        self.total -= 1;
+        Some(instance)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn idle_count(&self, function_id: &str) -> usize {
+        self.functions.get(function_id).map(|s| s.idle.len()).unwrap_or(0)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn in_flight_count(&self, function_id: &str) -> usize {
+        self.functions.get(function_id).map(|s| s.in_flight).unwrap_or(0)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn total(&self) -> usize {
+        self.total
+    }
+
+    // インスタンスが残っていない関数の枠はここで取り除き、ゲージのラベルも消す
+    pub(crate) fn publish_metrics(&mut self) {
+        self.functions.retain(|function_id, slots| {
+            if slots.idle.is_empty() && slots.in_flight == 0 && slots.min_warm == 0 {
+                let _ = POOL_IDLE_INSTANCES.remove_label_values(&[function_id]);
+                let _ = POOL_IN_FLIGHT_INSTANCES.remove_label_values(&[function_id]);
+                return false;
+            }
+            POOL_IDLE_INSTANCES.with_label_values(&[function_id]).set(slots.idle.len() as i64);
+            POOL_IN_FLIGHT_INSTANCES.with_label_values(&[function_id]).set(slots.in_flight as i64);
+            true
+        });
+        POOL_TOTAL_INSTANCES.set(self.total as i64);
+    }
+}
+
+fn lock_state(state: &Mutex<PoolState<PooledInstance>>) -> MutexGuard<'_, PoolState<PooledInstance>> {
+    state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// 貸し出し中のインスタンス枠。関数ごとの同時実行枠を保持し、
+// return_instance されずに破棄された場合はインスタンス枠も解放する。
+pub struct InstanceLease {
+    function_id: String,
+    slot: String,
+    pooled: Option<PooledInstance>,
+    returned: bool,
+    state: Arc<Mutex<PoolState<PooledInstance>>>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl InstanceLease {
+    pub fn instance(&mut self) -> &mut PooledInstance {
+        self.pooled.as_mut().expect("instance not created; call ensure_instance first")
+    }
+}
+
+impl Drop for InstanceLease {
+    fn drop(&mut self) {
+        if !self.returned {
+            let mut state = lock_state(&self.state);
+            state.release(&self.slot);
+            state.publish_metrics();
+        }
+    }
+}
+
+// 関数ごとに待たせる呼び出しは同時実行数のこの倍まで。超えた分は待たずに拒否する
+const WAITERS_PER_SLOT: usize = 4;
+
+struct ConcurrencyLimit {
+    max_concurrency: usize,
+    semaphore: Arc<Semaphore>,
+    waiting: Arc<AtomicUsize>,
+    // 上限を下げたときに、実行中の呼び出しが持っていてまだ回収できていない許可の数
+    debt: usize,
+}
+
+impl ConcurrencyLimit {
+    fn new(max_concurrency: usize) -> Self {
+        Self {
+            max_concurrency,
+            semaphore: Arc::new(Semaphore::new(max_concurrency)),
+            waiting: Arc::new(AtomicUsize::new(0)),
+            debt: 0,
+        }
+    }
+
+    // 同じセマフォのまま許可の数を変える。実行中の呼び出しの許可は返ってきた時点で回収する
+    fn resize(&mut self, max_concurrency: usize) {
+        if max_concurrency > self.max_concurrency {
+            let grow = max_concurrency - self.max_concurrency;
+            let repaid = grow.min(self.debt);
+            self.debt -= repaid;
+            self.semaphore.add_permits(grow - repaid);
+        } else {
+            self.debt += self.max_concurrency - max_concurrency;
+        }
+        self.max_concurrency = max_concurrency;
+        if self.debt > 0 {
+            self.debt -= self.semaphore.forget_permits(self.debt);
+        }
+    }
+}
+
+// 待っている呼び出しの数。待つのをやめた (タイムアウト・キャンセル) ときも減らす
+struct Waiting(Arc<AtomicUsize>);
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
 pub struct HotInstancePool {
-    pools: Arc<Mutex<HashMap<String, Vec<PooledInstance>>>>,
+    state: Arc<Mutex<PoolState<PooledInstance>>>,
+    limits: Mutex<HashMap<String, ConcurrencyLimit>>,
+    engine: Engine,
+    modules: ModuleCache,
     max_instances: usize,
     idle_timeout_secs: u64,
+    queue_timeout: Duration,
+    host_services: Arc<HostServices>,
 }
 
 impl HotInstancePool {
-    pub fn new(max_instances: usize, idle_timeout_secs: u64) -> Self {
+    pub fn new(
+        max_instances: usize,
+        idle_timeout_secs: u64,
+        queue_timeout_ms: u64,
+        module_cache_dir: Option<PathBuf>,
+        host_services: Arc<HostServices>,
+    ) -> Self {
+        let engine = metered_engine();
         Self {
-            pools: Arc::new(Mutex::new(HashMap::new())),
+            state: Arc::new(Mutex::new(PoolState::new(max_instances))),
+            limits: Mutex::new(HashMap::new()),
+            modules: ModuleCache::new(engine.clone(), module_cache_dir, MAX_CACHED_MODULES),
+            engine,
             max_instances,
             idle_timeout_secs,
+            queue_timeout: Duration::from_millis(queue_timeout_ms),
+            host_services,
         }
     }
-    
-    pub async fn get_or_create(
-        &self,
-        function_id: &str,
-        wasm_bytes: &[u8],
-        memory_pages: u32,
-    ) -> Result<PooledInstance, String> {
-        let mut pools = self.pools.lock().await;
-        let pool = pools.entry(function_id.to_string()).or_insert_with(Vec::new);
-        
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        
-        pool.retain(|inst| now - inst.last_used < self.idle_timeout_secs);
-        
-        if let Some(mut pooled) = pool.pop() {
-            pooled.last_used = now;
-            return Ok(pooled);
+
+    // 関数ごとのセマフォで待機するため、混雑した関数の待ち行列が他の関数を塞がない
+    pub(crate) async fn acquire_slot(&self, function_id: &str, max_concurrency: u32) -> Result<OwnedSemaphorePermit, InvocationError> {
+        let max_concurrency = if max_concurrency == 0 { self.max_instances } else { max_concurrency as usize };
+        let (semaphore, waiting) = {
+            let mut limits = self.limits.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let limit = limits.entry(function_id.to_string()).or_insert_with(|| ConcurrencyLimit::new(max_concurrency));
+            limit.resize(max_concurrency);
+            if let Ok(permit) = limit.semaphore.clone().try_acquire_owned() {
+                return Ok(permit);
+            }
+            if limit.waiting.load(Ordering::SeqCst) >= max_concurrency * WAITERS_PER_SLOT {
+                return Err(InvocationError::Throttled(function_id.to_string()));
+            }
+            limit.waiting.fetch_add(1, Ordering::SeqCst);
+            (limit.semaphore.clone(), Waiting(limit.waiting.clone()))
+        };
+
+        let acquired = tokio::time::timeout(self.queue_timeout, semaphore.acquire_owned()).await;
+        drop(waiting);
+        match acquired {
+            Ok(Ok(permit)) => Ok(permit),
+            Ok(Err(_)) => Err(InvocationError::Internal("Function concurrency limiter closed".to_string())),
+            Err(_) => Err(InvocationError::Throttled(function_id.to_string())),
+        }
+    }
+
+    // アイドルインスタンスを借りるか、新規作成の枠を予約する (コンパイルはしない)
+    pub async fn checkout(&self, metadata: &FunctionMetadata) -> Result<InstanceLease, InvocationError> {
+        let permit = self.acquire_slot(&metadata.function_id, metadata.max_concurrency).await?;
+        let slot = slot_key(metadata);
+        let now = now_secs();
+
+        let (checkout, expired) = {
+            let mut state = lock_state(&self.state);
+            let expired = state.evict_expired(now, self.idle_timeout_secs);
+            let checkout = state.checkout(&slot);
+            state.publish_metrics();
+            (checkout, expired)
+        };
+        // インスタンスの破棄は重いのでロックの外で行う
+        drop(expired);
+
+        let pooled = match checkout {
+            Checkout::Idle(mut pooled) => {
+                INSTANCE_STARTS.with_label_values(&[&metadata.function_id, "warm"]).inc();
+                pooled.last_used = now;
+                Some(pooled)
+            }
+            Checkout::Reserved(evicted) => {
+                drop(evicted);
+                None
+            }
+            Checkout::Full => {
+                // 枠を予約していないので Drop で解放させない
+                return Err(InvocationError::Overloaded("Pool at max capacity".to_string()));
+            }
+        };
+
+        Ok(InstanceLease {
+            function_id: metadata.function_id.clone(),
+            slot,
+            pooled,
+            returned: false,
+            state: self.state.clone(),
+            _permit: permit,
+        })
+    }
+
+    // 予約枠ならインスタンスを作成する。コンパイルを伴うためワーカースレッドで呼ぶこと
+    pub fn ensure_instance(&self, lease: &mut InstanceLease, metadata: &FunctionMetadata, wasm_bytes: &[u8]) -> Result<(), InvocationError> {
+        if lease.pooled.is_none() {
+            lease.pooled = Some(self.instantiate(metadata, wasm_bytes, now_secs()).map_err(InvocationError::Instantiation)?);
+            INSTANCE_STARTS.with_label_values(&[&metadata.function_id, "cold"]).inc();
         }
+        Ok(())
+    }
+    
+    // min_warm 個までインスタンスを事前作成してアイドルに置く。ワーカースレッドで呼ぶこと
+    pub fn prewarm(&self, metadata: &FunctionMetadata, wasm_bytes: &[u8]) -> Result<usize, InvocationError> {
+        let slot = slot_key(metadata);
+        lock_state(&self.state).set_min_warm(&slot, metadata.min_warm as usize);
         
-        if pool.len() < self.max_instances {
-            let mut store = Store::default();
-            let module = Module::new(&store, wasm_bytes)
-                .map_err(|e| format!("Failed to compile WASM: {}", e))?;
+        let mut created = 0;
+        loop {
+            if !lock_state(&self.state).try_reserve_warm(&slot) {
+                break;
+            }
             
-            let memory = Memory::new(&mut store, MemoryType::new(
-                Pages(memory_pages),
-                Some(Pages(memory_pages)),
-                false
-            )).map_err(|e| format!("Failed to create memory: {}", e))?;
-            
-            let import_object = imports! {
-                "env" => {
-                    "memory" => memory.clone(),
+            let now = now_secs();
+            match self.instantiate(metadata, wasm_bytes, now) {
+                Ok(pooled) => {
+                    let mut state = lock_state(&self.state);
+                    state.checkin(&slot, pooled, now);
+                    state.publish_metrics();
+                    created += 1;
                 }
-            };
-            
-            let instance = Instance::new(&mut store, &module, &import_object)
-                .map_err(|e| format!("Failed to instantiate WASM: {}", e))?;
-            
-            Ok(PooledInstance {
-                instance,
-                store,
-                last_used: now,
-            })
-        } else {
-            Err("Pool at max capacity".to_string())
+                Err(e) => {
+                    let mut state = lock_state(&self.state);
+                    state.release(&slot);
+                    state.publish_metrics();
+                    return Err(InvocationError::Instantiation(e));
+                }
+            }
         }
+        
+        Ok(created)
     }
     
-    pub async fn return_instance(&self, function_id: &str, pooled: PooledInstance) {
-        let mut pools = self.pools.lock().await;
-        let pool = pools.entry(function_id.to_string()).or_insert_with(Vec::new);
-        
-        if pool.len() < self.max_instances {
-            pool.push(pooled);
+    // 切り替え前の検証。インスタンス化まで成功することを確かめて破棄する (コンパイル結果はキャッシュに残る)。
+    // ワーカースレッドで呼ぶこと
+    pub fn verify(&self, metadata: &FunctionMetadata, wasm_bytes: &[u8]) -> Result<(), InvocationError> {
+        self.instantiate(metadata, wasm_bytes, now_secs())
+            .map(|_| ())
+            .map_err(InvocationError::Instantiation)
+    }
+    
+    // 無効化したバージョンのアイドルインスタンスとメモリ上のモジュールを解放する
+    pub fn retire(&self, metadata: &FunctionMetadata) -> usize {
+        let retired = {
+            let mut state = lock_state(&self.state);
+            let retired = state.retire(&slot_key(metadata));
+            state.publish_metrics();
+            retired
+        };
+        self.modules.evict(metadata);
+        retired.len()
+    }
+    
+    // 保持しなくなったバージョンを解放し、ディスクに保存したコンパイル済みモジュールも削除する
+    pub fn remove_version(&self, metadata: &FunctionMetadata) -> usize {
+        let retired = self.retire(metadata);
+        self.modules.remove(metadata);
+        retired
+    }
+    
+    // 関数の最後のバージョンを削除したあとに呼ぶ。同時実行数の管理をやめる。
+    // 実行中の呼び出しは手元の許可を返すだけなので、再デプロイ後は新しい上限から数え直す
+    pub fn remove_function(&self, function_id: &str) {
+        self.limits.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(function_id);
+    }
+    
+    // アイドルタイムアウトを過ぎたインスタンスを解放する。バックグラウンドタスクから定期的に呼ぶ
+    pub fn reap_idle(&self) -> usize {
+        let expired = {
+            let mut state = lock_state(&self.state);
+            let expired = state.evict_expired(now_secs(), self.idle_timeout_secs);
+            state.publish_metrics();
+            expired
+        };
+        expired.len()
+    }
+
+    fn instantiate(&self, metadata: &FunctionMetadata, wasm_bytes: &[u8], now: u64) -> Result<PooledInstance, String> {
+        let module = self.modules.get_or_compile(metadata, wasm_bytes)?;
+        let mut store = Store::new(self.engine.clone());
+
+        let memory = Memory::new(&mut store, MemoryType::new(
+            Pages(metadata.memory_pages),
+            Some(Pages(metadata.memory_pages)),
+            false
+        )).map_err(|e| format!("Failed to create memory: {}", e))?;
+
+        let mut import_object = imports! {
+            "env" => {
+                "memory" => memory.clone(),
+            }
+        };
+
+        let wasi_env = if wasi::imports_wasi(&module) {
+            if !metadata.wasi {
+                return Err(format!("Module imports WASI but WASI is not enabled for {}", metadata.function_id));
+            }
+            Some(wasi::define_imports(
+                &mut store,
+                &module,
+                &mut import_object,
+                &metadata.function_id,
+                metadata.wasi_preopen_dir.as_deref(),
+                self.host_services.logs.clone(),
+            )?)
+        } else {
+            None
+        };
+
+        let host_env = if host::imports_host(&module) {
+            Some(host::define_imports(
+                &mut store,
+                &module,
+                &mut import_object,
+                &metadata.function_id,
+                self.host_services.clone(),
+            )?)
+        } else {
+            None
+        };
+
+        let instance = Instance::new(&mut store, &module, &import_object)
+            .map_err(|e| format!("Failed to instantiate WASM: {}", e))?;
+        if let Some(env) = &host_env {
+            host::initialize(&mut store, env, &instance)?;
+        }
+        if let Some(env) = &wasi_env {
+            wasi::initialize(&mut store, env, &instance)?;
+        }
+
+        // データセグメントと start 関数の適用後の状態を初期状態として保存する
+        let snapshot = if metadata.isolation == IsolationPolicy::Reset {
+            Some(snapshot(&instance, &mut store)?)
+        } else {
+            None
+        };
+
+        Ok(PooledInstance {
+            instance,
+            store,
+            last_used: now,
+            snapshot,
+            wasi: wasi_env,
+            host: host_env,
+        })
+    }
+
+    // 実行後のインスタンスを分離ポリシーに従って再利用可能な状態にする。
+    // Err の場合は破棄すべき理由を返す。ワーカースレッドから呼ぶこと
+    pub fn recycle(&self, lease: &mut InstanceLease, isolation: IsolationPolicy) -> Result<(), &'static str> {
+        match isolation {
+            IsolationPolicy::Reuse => Ok(()),
+            IsolationPolicy::SingleUse => Err("single_use"),
+            IsolationPolicy::Reset => reset_memory(lease.instance()).map_err(|e| {
+                eprintln!("Failed to reset instance of {}: {}", lease.function_id, e);
+                "reset_failed"
+            }),
         }
     }
+
+    // トラップ・タイムアウトしたインスタンスや使い捨てのインスタンスはプールに戻さない
+    pub fn discard(&self, lease: InstanceLease, reason: &str) {
+        INSTANCE_DISCARDS.with_label_values(&[&lease.function_id, reason]).inc();
+        drop(lease);
+    }
+
+    #[cfg(test)]
+    pub(crate) fn limited_functions(&self) -> usize {
+        self.limits.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).len()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn idle_count(&self, metadata: &FunctionMetadata) -> usize {
+        lock_state(&self.state).idle_count(&slot_key(metadata))
+    }
+
+    pub async fn return_instance(&self, mut lease: InstanceLease) {
+        if let Some(mut pooled) = lease.pooled.take() {
+            let now = now_secs();
+            pooled.last_used = now;
+            let mut state = lock_state(&self.state);
+            state.checkin(&lease.slot, pooled, now);
+            state.publish_metrics();
+            lease.returned = true;
+        }
+    }
+}
+
+// エクスポートされていないグローバルはホストから読み書きできないので対象外
+fn snapshot(instance: &Instance, store: &mut Store) -> Result<InstanceSnapshot, String> {
+    let memory = instance.exports.get_memory("memory")
+        .map_err(|_| "memory not found".to_string())?
+        .view(store)
+        .copy_to_vec()
+        .map_err(|e| format!("Failed to snapshot memory: {}", e))?;
+
+    let mutable: Vec<_> = instance.exports.iter()
+        .filter_map(|(_, export)| match export {
+            Extern::Global(global) if global.ty(store).mutability == Mutability::Var => Some(global.clone()),
+            _ => None,
+        })
+        .collect();
+    let globals = mutable.into_iter()
+        .map(|global| {
+            let value = global.get(store);
+            (global, value)
+        })
+        .collect();
+
+    Ok(InstanceSnapshot { memory, globals })
+}
+
+// 線形メモリと可変グローバルをスナップショットの内容に戻す。
+// memory.grow で増えたページは縮められないため、ゼロで埋めておく
+fn reset_memory(pooled: &mut PooledInstance) -> Result<(), String> {
+    let snapshot = pooled.snapshot.as_ref()
+        .ok_or_else(|| "no memory snapshot".to_string())?;
+    let memory = pooled.instance.exports.get_memory("memory")
+        .map_err(|_| "memory not found".to_string())?;
+    let view = memory.view(&pooled.store);
+
+    view.write(0, &snapshot.memory).map_err(|e| e.to_string())?;
+
+    let zeros = vec![0u8; 64 * 1024];
+    let mut offset = snapshot.memory.len() as u64;
+    while offset < view.data_size() {
+        let len = (view.data_size() - offset).min(zeros.len() as u64);
+        view.write(offset, &zeros[..len as usize]).map_err(|e| e.to_string())?;
+        offset += len;
+    }
+
+    for (global, value) in &snapshot.globals {
+        global.set(&mut pooled.store, value.clone()).map_err(|e| e.to_string())?;
+    }
+
+    if let Some(env) = &pooled.wasi {
+        wasi::reset(&mut pooled.store, env);
+    }
+
+    Ok(())
+}
+
+fn slot_key(metadata: &FunctionMetadata) -> String {
+    format!("{}@{}", metadata.function_id, metadata.version)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
 }
@@ -0,0 +1,368 @@
+#[cfg(test)]
+mod tests {
+    use crate::domain::{FunctionMetadata, InvocationError, IsolationPolicy};
+    use crate::infrastructure::pool::{Checkout, PoolState};
+    use crate::infrastructure::*;
+    use crate::test_support;
+    use std::sync::Arc;
+
+    // 呼び出しごとにグローバルとメモリの値を書き換えるゲスト
+    const STATEFUL_GUEST: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (global $calls (export "calls") (mut i32) (i32.const 7))
+          (global (export "limit") i32 (i32.const 100))
+          (data (i32.const 16) "init")
+          (func (export "bump") (result i32)
+            (global.set $calls (i32.add (global.get $calls) (i32.const 1)))
+            (i32.store (i32.const 16) (global.get $calls))
+            (i32.store (i32.const 60000) (i32.const 1))
+            (global.get $calls)))
+    "#;
+
+    fn metadata(function_id: &str, max_concurrency: u32) -> FunctionMetadata {
+        FunctionMetadata { max_concurrency, ..test_support::metadata(function_id, "1.0.0") }
+    }
+
+    fn pool(max_instances: usize) -> HotInstancePool {
+        pool_with_queue_timeout(max_instances, 50)
+    }
+
+    fn pool_with_queue_timeout(max_instances: usize, queue_timeout_ms: u64) -> HotInstancePool {
+        test_support::pool(max_instances, queue_timeout_ms)
+    }
+
+    #[test]
+    fn test_in_flight_instances_count_toward_max() {
+        let mut state: PoolState<u32> = PoolState::new(2);
+
+        assert!(matches!(state.checkout("func1"), Checkout::Reserved(None)));
+        assert!(matches!(state.checkout("func1"), Checkout::Reserved(None)));
+        // 両方とも貸し出し中なのでアイドルは 0 だが上限に達している
+        assert!(matches!(state.checkout("func2"), Checkout::Full));
+
+        assert_eq!(state.in_flight_count("func1"), 2);
+        assert_eq!(state.idle_count("func1"), 0);
+        assert_eq!(state.total(), 2);
+    }
+
+    #[test]
+    fn test_checkin_makes_instance_reusable() {
+        let mut state: PoolState<u32> = PoolState::new(1);
+
+        assert!(matches!(state.checkout("func1"), Checkout::Reserved(None)));
+        state.checkin("func1", 7, 100);
+        assert_eq!(state.idle_count("func1"), 1);
+        assert_eq!(state.in_flight_count("func1"), 0);
+
+        assert!(matches!(state.checkout("func1"), Checkout::Idle(7)));
+        assert_eq!(state.total(), 1);
+    }
+
+    #[test]
+    fn test_full_pool_evicts_lru_idle_of_other_function() {
+        let mut state: PoolState<u32> = PoolState::new(2);
+
+        assert!(matches!(state.checkout("func1"), Checkout::Reserved(None)));
+        assert!(matches!(state.checkout("func2"), Checkout::Reserved(None)));
+        state.checkin("func1", 1, 100);
+        state.checkin("func2", 2, 200);
+
+        // func1 の方が古いので追い出され、破棄するために呼び出し側へ返される
+        assert!(matches!(state.checkout("func3"), Checkout::Reserved(Some(1))));
+        assert_eq!(state.idle_count("func1"), 0);
+        assert_eq!(state.idle_count("func2"), 1);
+        assert_eq!(state.total(), 2);
+    }
+
+    #[test]
+    fn test_release_frees_capacity() {
+        let mut state: PoolState<u32> = PoolState::new(1);
+
+        assert!(matches!(state.checkout("func1"), Checkout::Reserved(None)));
+        state.release("func1");
+        assert_eq!(state.total(), 0);
+        assert!(matches!(state.checkout("func2"), Checkout::Reserved(None)));
+    }
+
+    #[test]
+    fn test_evict_expired() {
+        let mut state: PoolState<u32> = PoolState::new(4);
+
+        assert!(matches!(state.checkout("func1"), Checkout::Reserved(None)));
+        assert!(matches!(state.checkout("func1"), Checkout::Reserved(None)));
+        state.checkin("func1", 1, 100);
+        state.checkin("func1", 2, 350);
+
+        let evicted = state.evict_expired(400, 300);
+        assert_eq!(evicted, vec![1]);
+        assert_eq!(state.idle_count("func1"), 1);
+        assert_eq!(state.total(), 1);
+    }
+
+    #[test]
+    fn test_evict_expired_keeps_min_warm() {
+        let mut state: PoolState<u32> = PoolState::new(4);
+        state.set_min_warm("func1", 1);
+
+        for _ in 0..3 {
+            assert!(matches!(state.checkout("func1"), Checkout::Reserved(None)));
+        }
+        state.checkin("func1", 1, 100);
+        state.checkin("func1", 2, 200);
+        state.checkin("func1", 3, 300);
+
+        // すべて期限切れだが最新の 1 つは残る
+        let mut evicted = state.evict_expired(1000, 300);
+        evicted.sort();
+        assert_eq!(evicted, vec![1, 2]);
+        assert!(matches!(state.checkout("func1"), Checkout::Idle(3)));
+    }
+
+    #[test]
+    fn test_try_reserve_warm_stops_at_min_warm_and_capacity() {
+        let mut state: PoolState<u32> = PoolState::new(3);
+        state.set_min_warm("func1", 2);
+        state.set_min_warm("func2", 5);
+
+        assert!(state.try_reserve_warm("func1"));
+        assert!(state.try_reserve_warm("func1"));
+        assert!(!state.try_reserve_warm("func1"));
+
+        // ノード上限に達したら他の関数のアイドルを追い出さずに止まる
+        assert!(state.try_reserve_warm("func2"));
+        assert!(!state.try_reserve_warm("func2"));
+        assert_eq!(state.total(), 3);
+    }
+
+    #[test]
+    fn test_retire_drops_idle_and_min_warm() {
+        let mut state: PoolState<u32> = PoolState::new(4);
+        state.set_min_warm("func1@1.0.0", 2);
+
+        assert!(state.try_reserve_warm("func1@1.0.0"));
+        assert!(state.try_reserve_warm("func1@1.0.0"));
+        state.checkin("func1@1.0.0", 1, 100);
+
+        let retired = state.retire("func1@1.0.0");
+        assert_eq!(retired, vec![1]);
+        assert_eq!(state.total(), 1);
+
+        // 作成中だったものは返却後にタイムアウトで回収され、補充もされない
+        state.checkin("func1@1.0.0", 2, 100);
+        assert_eq!(state.evict_expired(1000, 300), vec![2]);
+        assert_eq!(state.total(), 0);
+        assert!(!state.try_reserve_warm("func1@1.0.0"));
+    }
+
+    fn idle_gauge_labels() -> Vec<String> {
+        use prometheus::core::Collector;
+        crate::infrastructure::metrics::POOL_IDLE_INSTANCES.collect()
+            .iter()
+            .flat_map(|family| family.get_metric().iter().map(|m| m.get_label()[0].get_value().to_string()).collect::<Vec<_>>())
+            .collect()
+    }
+
+    #[test]
+    fn test_publish_metrics_drops_labels_of_empty_functions() {
+        let mut state: PoolState<u32> = PoolState::new(4);
+
+        assert!(matches!(state.checkout("gauge@1.0.0"), Checkout::Reserved(None)));
+        state.checkin("gauge@1.0.0", 1, 100);
+        state.publish_metrics();
+        assert!(idle_gauge_labels().contains(&"gauge@1.0.0".to_string()));
+
+        assert_eq!(state.retire("gauge@1.0.0"), vec![1]);
+        state.publish_metrics();
+        assert!(!idle_gauge_labels().contains(&"gauge@1.0.0".to_string()));
+        assert_eq!(state.idle_count("gauge@1.0.0"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_per_function_concurrency_limit() {
+        let pool = pool(10);
+
+        let first = pool.acquire_slot("func1", 2).await;
+        let second = pool.acquire_slot("func1", 2).await;
+        assert!(first.is_ok());
+        assert!(second.is_ok());
+
+        let third = pool.acquire_slot("func1", 2).await;
+        assert_eq!(third.unwrap_err(), InvocationError::Throttled("func1".to_string()));
+
+        // 他の関数は影響を受けない
+        assert!(pool.acquire_slot("func2", 2).await.is_ok());
+
+        // 枠が返れば待機中のリクエストが通る
+        drop(first);
+        assert!(pool.acquire_slot("func1", 2).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_lowering_limit_counts_running_invocations() {
+        let pool = pool(10);
+
+        let first = pool.acquire_slot("func1", 2).await.unwrap();
+        let second = pool.acquire_slot("func1", 2).await.unwrap();
+
+        // 実行中の 2 つが返るまでは下げた上限の分も空かない
+        assert!(pool.acquire_slot("func1", 1).await.is_err());
+        drop(first);
+        assert!(pool.acquire_slot("func1", 1).await.is_err());
+        drop(second);
+
+        let only = pool.acquire_slot("func1", 1).await.unwrap();
+        assert!(pool.acquire_slot("func1", 1).await.is_err());
+
+        // 上限を上げれば同じセマフォに許可が増える
+        let more = pool.acquire_slot("func1", 3).await.unwrap();
+        let most = pool.acquire_slot("func1", 3).await.unwrap();
+        assert!(pool.acquire_slot("func1", 3).await.is_err());
+        drop((only, more, most));
+    }
+
+    #[tokio::test]
+    async fn test_waiters_beyond_cap_are_rejected_immediately() {
+        let pool = Arc::new(pool_with_queue_timeout(10, 5000));
+        let held = pool.acquire_slot("func1", 1).await.unwrap();
+
+        // 同時実行数 1 なので 4 つまで待てる
+        let waiters: Vec<_> = (0..4).map(|_| {
+            let pool = pool.clone();
+            tokio::spawn(async move { pool.acquire_slot("func1", 1).await.map(drop) })
+        }).collect();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        let rejected = tokio::time::timeout(std::time::Duration::from_secs(1), pool.acquire_slot("func1", 1)).await;
+        assert_eq!(rejected.unwrap().unwrap_err(), InvocationError::Throttled("func1".to_string()));
+
+        // 待っていた呼び出しは順に実行される
+        drop(held);
+        for waiter in waiters {
+            assert!(waiter.await.unwrap().is_ok());
+        }
+        assert!(pool.acquire_slot("func1", 1).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_checkout_releases_slot_when_lease_dropped() {
+        let pool = pool(1);
+
+        let lease = pool.checkout(&metadata("func1", 1)).await.unwrap();
+        let full = pool.checkout(&metadata("func2", 1)).await;
+        assert!(matches!(full, Err(InvocationError::Overloaded(_))));
+
+        // 作成前に破棄された予約は枠を返す
+        drop(lease);
+        assert!(pool.checkout(&metadata("func2", 1)).await.is_ok());
+    }
+
+    fn bump(lease: &mut crate::infrastructure::pool::InstanceLease) -> i32 {
+        let pooled = lease.instance();
+        let bump = pooled.instance.exports.get_function("bump").unwrap().clone();
+        bump.call(&mut pooled.store, &[]).unwrap()[0].unwrap_i32()
+    }
+
+    fn memory_at(lease: &mut crate::infrastructure::pool::InstanceLease, offset: u64, len: usize) -> Vec<u8> {
+        let pooled = lease.instance();
+        let memory = pooled.instance.exports.get_memory("memory").unwrap();
+        let mut buf = vec![0u8; len];
+        memory.view(&pooled.store).read(offset, &mut buf).unwrap();
+        buf
+    }
+
+    #[tokio::test]
+    async fn test_reset_restores_memory_and_mutable_globals() {
+        let pool = pool(2);
+        let mut function = metadata("func1", 1);
+        function.isolation = IsolationPolicy::Reset;
+        let wasm = STATEFUL_GUEST.as_bytes();
+
+        let mut lease = pool.checkout(&function).await.unwrap();
+        pool.ensure_instance(&mut lease, &function, wasm).unwrap();
+        assert_eq!(bump(&mut lease), 8);
+        assert_eq!(bump(&mut lease), 9);
+
+        assert!(pool.recycle(&mut lease, IsolationPolicy::Reset).is_ok());
+        assert_eq!(memory_at(&mut lease, 16, 4), b"init");
+        assert_eq!(memory_at(&mut lease, 60000, 4), vec![0; 4]);
+        pool.return_instance(lease).await;
+        assert_eq!(pool.idle_count(&function), 1);
+
+        // 再利用したインスタンスは初期状態から始まる
+        let mut lease = pool.checkout(&function).await.unwrap();
+        pool.ensure_instance(&mut lease, &function, wasm).unwrap();
+        assert_eq!(pool.idle_count(&function), 0);
+        assert_eq!(bump(&mut lease), 8);
+    }
+
+    #[tokio::test]
+    async fn test_reuse_keeps_state_between_calls() {
+        let pool = pool(2);
+        let function = metadata("func1", 1);
+        let wasm = STATEFUL_GUEST.as_bytes();
+
+        let mut lease = pool.checkout(&function).await.unwrap();
+        pool.ensure_instance(&mut lease, &function, wasm).unwrap();
+        assert_eq!(bump(&mut lease), 8);
+        assert!(pool.recycle(&mut lease, IsolationPolicy::Reuse).is_ok());
+        pool.return_instance(lease).await;
+
+        let mut lease = pool.checkout(&function).await.unwrap();
+        pool.ensure_instance(&mut lease, &function, wasm).unwrap();
+        assert_eq!(bump(&mut lease), 9);
+    }
+
+    #[tokio::test]
+    async fn test_discarded_instance_is_not_reused() {
+        let pool = pool(1);
+        let mut function = metadata("func1", 1);
+        function.isolation = IsolationPolicy::SingleUse;
+        let wasm = STATEFUL_GUEST.as_bytes();
+
+        let mut lease = pool.checkout(&function).await.unwrap();
+        pool.ensure_instance(&mut lease, &function, wasm).unwrap();
+        assert_eq!(bump(&mut lease), 8);
+        let reason = pool.recycle(&mut lease, IsolationPolicy::SingleUse).unwrap_err();
+        assert_eq!(reason, "single_use");
+
+        let discards = INSTANCE_DISCARDS.with_label_values(&["func1", reason]).get();
+        pool.discard(lease, reason);
+        assert_eq!(INSTANCE_DISCARDS.with_label_values(&["func1", reason]).get(), discards + 1.0);
+        assert_eq!(pool.idle_count(&function), 0);
+
+        // 枠は返っていて、次の呼び出しは新しいインスタンスで始まる
+        let mut lease = pool.checkout(&function).await.unwrap();
+        pool.ensure_instance(&mut lease, &function, wasm).unwrap();
+        assert_eq!(bump(&mut lease), 8);
+    }
+
+    #[tokio::test]
+    async fn test_prewarm_fills_and_reap_keeps_warm_floor() {
+        // アイドルタイムアウト 0 秒: min_warm を超える分はすぐに回収対象になる
+        let pool = HotInstancePool::new(4, 0, 50, None, test_support::host_services(test_support::kv_store()));
+        let function = FunctionMetadata { min_warm: 2, ..metadata("func1", 0) };
+        let wasm = STATEFUL_GUEST.as_bytes();
+
+        assert_eq!(pool.prewarm(&function, wasm).unwrap(), 2);
+        assert_eq!(pool.idle_count(&function), 2);
+        // すでに min_warm 個あれば作らない
+        assert_eq!(pool.prewarm(&function, wasm).unwrap(), 0);
+
+        // 同時に 3 つ使ってアイドルを min_warm より多くする
+        let mut leases = Vec::new();
+        for _ in 0..3 {
+            let mut lease = pool.checkout(&function).await.unwrap();
+            pool.ensure_instance(&mut lease, &function, wasm).unwrap();
+            leases.push(lease);
+        }
+        for lease in leases {
+            pool.return_instance(lease).await;
+        }
+        assert_eq!(pool.idle_count(&function), 3);
+
+        assert_eq!(pool.reap_idle(), 1);
+        assert_eq!(pool.idle_count(&function), 2);
+        assert_eq!(pool.reap_idle(), 0);
+    }
+}
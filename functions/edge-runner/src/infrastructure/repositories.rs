@@ -1,74 +1,272 @@
 use crate::domain::*;
-use std::collections::HashMap;
+use crate::infrastructure::router::{parse_path, CompiledRouter, HostPattern, Segment};
+use std::collections::{HashMap, HashSet};
 use tokio::sync::RwLock;
 use std::sync::Arc;
 
+#[derive(Default)]
+struct FunctionVersions {
+    // 登録順 (古い順)
+    versions: Vec<FunctionMetadata>,
+    // 有効化した順。末尾が現在有効なバージョン
+    history: Vec<String>,
+    // 取得・インスタンス化まで確認済みで、トラフィックを送れるバージョン
+    verified: HashSet<String>,
+    // 登録済みのバージョンが別のアーティファクトで再登録されたもの。
+    // 検証が終わるまでは versions 側のメタデータでトラフィックを処理する
+    candidates: HashMap<String, FunctionMetadata>,
+}
+
+impl FunctionVersions {
+    fn find(&self, version: &str) -> Option<&FunctionMetadata> {
+        self.versions.iter().find(|m| m.version == version)
+    }
+
+    // 検証が終わった再登録を versions に入れ替え、置き換えたメタデータを返す
+    fn promote(&mut self, version: &str) -> Option<FunctionMetadata> {
+        let candidate = self.candidates.remove(version)?;
+        let current = self.versions.iter_mut().find(|m| m.version == version)?;
+        Some(std::mem::replace(current, candidate))
+    }
+
+    fn active(&self) -> Option<&FunctionMetadata> {
+        self.history.last().and_then(|version| self.find(version))
+    }
+
+    fn previous(&self) -> Option<&FunctionMetadata> {
+        let len = self.history.len();
+        if len < 2 {
+            return None;
+        }
+        self.find(&self.history[len - 2])
+    }
+}
+
 pub struct InMemoryFunctionRepository {
-    functions: Arc<RwLock<HashMap<String, FunctionMetadata>>>,
+    functions: Arc<RwLock<HashMap<String, FunctionVersions>>>,
+    max_versions: usize,
 }
 
 impl InMemoryFunctionRepository {
-    pub fn new() -> Self {
+    // 関数ごとに max_versions 個 (最低 2: 有効 + 登録中) のバージョンを保持する
+    pub fn new(max_versions: usize) -> Self {
         Self {
             functions: Arc::new(RwLock::new(HashMap::new())),
+            max_versions: max_versions.max(2),
         }
     }
 }
 
 #[async_trait::async_trait]
 impl FunctionRepository for InMemoryFunctionRepository {
-    async fn register(&self, metadata: FunctionMetadata) {
-        self.functions.write().await.insert(metadata.function_id.clone(), metadata);
+    async fn register(&self, metadata: FunctionMetadata) -> Vec<FunctionMetadata> {
+        let mut functions = self.functions.write().await;
+        let entry = functions.entry(metadata.function_id.clone()).or_default();
+        if entry.find(&metadata.version).is_some_and(|m| m.sha256 != metadata.sha256) {
+            entry.candidates.insert(metadata.version.clone(), metadata);
+            return Vec::new();
+        }
+        entry.candidates.remove(&metadata.version);
+        entry.versions.retain(|m| m.version != metadata.version);
+        entry.versions.push(metadata);
+
+        // 有効なバージョンと今登録したバージョン以外を古い順に削除する
+        let mut pruned = Vec::new();
+        while entry.versions.len() > self.max_versions {
+            let active = entry.history.last().cloned();
+            let candidates = entry.versions.len() - 1;
+            let index = match entry.versions[..candidates].iter().position(|m| Some(&m.version) != active.as_ref()) {
+                Some(index) => index,
+                None => break,
+            };
+            let removed = entry.versions.remove(index);
+            entry.history.retain(|v| v != &removed.version);
+            entry.verified.remove(&removed.version);
+            entry.candidates.remove(&removed.version);
+            pruned.push(removed);
+        }
+        pruned
     }
-    
+
     async fn get(&self, function_id: &str) -> Option<FunctionMetadata> {
-        self.functions.read().await.get(function_id).cloned()
+        self.functions.read().await.get(function_id)?.active().cloned()
     }
-    
-    async fn remove(&self, function_id: &str) {
-        self.functions.write().await.remove(function_id);
+
+    async fn get_version(&self, function_id: &str, version: &str) -> Option<FunctionMetadata> {
+        let functions = self.functions.read().await;
+        let entry = functions.get(function_id)?;
+        entry.candidates.get(version).or_else(|| entry.find(version)).cloned()
+    }
+
+    async fn get_verified(&self, function_id: &str, version: &str) -> Option<FunctionMetadata> {
+        let functions = self.functions.read().await;
+        let entry = functions.get(function_id)?;
+        if !entry.verified.contains(version) {
+            return None;
+        }
+        entry.find(version).cloned()
+    }
+
+    async fn previous(&self, function_id: &str) -> Option<FunctionMetadata> {
+        self.functions.read().await.get(function_id)?.previous().cloned()
+    }
+
+    async fn mark_verified(&self, function_id: &str, version: &str) -> Result<Option<FunctionMetadata>, String> {
+        let mut functions = self.functions.write().await;
+        let entry = functions.get_mut(function_id)
+            .filter(|entry| entry.find(version).is_some())
+            .ok_or_else(|| format!("Version {} of {} is not registered", version, function_id))?;
+        let replaced = entry.promote(version);
+        entry.verified.insert(version.to_string());
+        Ok(replaced)
+    }
+
+    async fn activate(&self, function_id: &str, version: &str) -> Result<Option<FunctionMetadata>, String> {
+        let mut functions = self.functions.write().await;
+        let entry = functions.get_mut(function_id)
+            .filter(|entry| entry.find(version).is_some())
+            .ok_or_else(|| format!("Version {} of {} is not registered", version, function_id))?;
+
+        let deactivated = entry.active()
+            .filter(|active| active.version != version)
+            .cloned();
+        // 有効なバージョンのアーティファクトを差し替えた場合は、差し替え前のものを無効化したとして返す
+        let replaced = entry.promote(version);
+        let deactivated = deactivated.or(replaced.filter(|_| entry.history.last().is_some_and(|v| v == version)));
+        entry.history.retain(|v| v != version);
+        entry.history.push(version.to_string());
+        entry.verified.insert(version.to_string());
+        Ok(deactivated)
+    }
+
+    async fn rollback(&self, function_id: &str) -> Result<FunctionMetadata, String> {
+        let mut functions = self.functions.write().await;
+        let entry = functions.get_mut(function_id)
+            .filter(|entry| entry.previous().is_some())
+            .ok_or_else(|| format!("No previous version of {} to roll back to", function_id))?;
+
+        let deactivated = entry.active().cloned();
+        entry.history.pop();
+        deactivated.ok_or_else(|| format!("No active version of {}", function_id))
+    }
+
+    async fn remove(&self, function_id: &str) -> Vec<FunctionMetadata> {
+        self.functions.write().await
+            .remove(function_id)
+            .map(|entry| entry.versions)
+            .unwrap_or_default()
+    }
+
+    async fn function_ids(&self) -> Vec<String> {
+        self.functions.read().await.keys().cloned().collect()
     }
 }
 
+// ルートは id で一意。評価順 (route_order) に並べて保持し、変更のたびにルーターを組み直す
 pub struct InMemoryRouteRepository {
-    routes: Arc<RwLock<Vec<Route>>>,
+    table: Arc<RwLock<RouteTable>>,
+}
+
+#[derive(Default)]
+struct RouteTable {
+    routes: Vec<CompiledRoute>,
+    router: CompiledRouter,
+}
+
+impl RouteTable {
+    fn rebuild(&mut self) {
+        self.routes.sort_by(route_order);
+        self.router = CompiledRouter::build(self.routes.iter().map(|r| (&r.host, r.path.as_slice())));
+    }
+}
+
+struct CompiledRoute {
+    route: Route,
+    host: HostPattern,
+    path: Vec<Segment>,
+}
+
+impl CompiledRoute {
+    fn compile(route: Route) -> Result<Self, String> {
+        let path = parse_path(&route.path).map_err(|e| format!("Route {}: {}", route.id, e))?;
+        Ok(Self {
+            host: HostPattern::parse(&route.host),
+            path,
+            route,
+        })
+    }
 }
 
 impl InMemoryRouteRepository {
     pub fn new() -> Self {
         Self {
-            routes: Arc::new(RwLock::new(Vec::new())),
+            table: Arc::new(RwLock::new(RouteTable::default())),
         }
     }
 }
 
 #[async_trait::async_trait]
 impl RouteRepository for InMemoryRouteRepository {
-    async fn add_route(&self, route: Route) {
-        let mut routes = self.routes.write().await;
-        routes.push(route);
-        routes.sort_by(|a, b| b.priority.cmp(&a.priority));
+    async fn add_route(&self, route: Route) -> Result<(), String> {
+        let mut table = self.table.write().await;
+        upsert_route(&mut table.routes, route)?;
+        table.rebuild();
+        Ok(())
     }
     
-    async fn match_route(&self, host: &str, path: &str, method: &str) -> Option<RouteMatch> {
-        let routes = self.routes.read().await;
-        for route in routes.iter() {
-            if (route.host == host || route.host == "*") && path_matches(&route.path, path) {
-                if route.methods.contains(&method.to_string()) || route.methods.contains(&"*".to_string()) {
-                    let path_params = extract_path_params(&route.path, path);
-                    return Some(RouteMatch {
-                        function_id: route.function_id.clone(),
-                        path_params,
-                    });
-                }
+    async fn replace_routes(&self, new_routes: Vec<Route>) -> Vec<String> {
+        let mut table = RouteTable::default();
+        let mut rejected = Vec::new();
+        for route in new_routes {
+            if let Err(e) = upsert_route(&mut table.routes, route) {
+                rejected.push(e);
             }
         }
-        None
+        table.rebuild();
+        *self.table.write().await = table;
+        rejected
+    }
+    
+    async fn remove_route(&self, id: &str) -> Option<Route> {
+        let mut table = self.table.write().await;
+        let index = table.routes.iter().position(|r| r.route.id == id)?;
+        let removed = table.routes.remove(index);
+        table.rebuild();
+        Some(removed.route)
+    }
+    
+    async fn match_route(&self, host: &str, path: &str, method: &str) -> Result<RouteMatch, InvocationError> {
+        let table = self.table.read().await;
+        let (matched, other_methods): (Vec<_>, Vec<_>) = table.router.lookup(host, path)
+            .into_iter()
+            .partition(|m| method_allowed(&table.routes[m.route].route, method));
+        
+        // 添字は評価順なので、メソッドも一致する中で最小のものを選ぶ
+        let best = match matched.into_iter().min_by_key(|m| m.route) {
+            Some(best) => best,
+            None if other_methods.is_empty() => return Err(InvocationError::RouteNotFound),
+            None => {
+                let mut allowed: Vec<String> = other_methods.iter()
+                    .flat_map(|m| table.routes[m.route].route.methods.iter().cloned())
+                    .collect();
+                allowed.sort();
+                allowed.dedup();
+                return Err(InvocationError::MethodNotAllowed { allowed });
+            }
+        };
+        
+        let route = &table.routes[best.route].route;
+        Ok(RouteMatch {
+            route_id: route.id.clone(),
+            function_id: route.function_id.clone(),
+            path_params: best.params,
+            rest: best.rest,
+            split: route.split.clone(),
+        })
     }
     
-    #[allow(dead_code)]
     async fn list_routes(&self) -> Vec<Route> {
-        self.routes.read().await.clone()
+        self.table.read().await.routes.iter().map(|r| r.route.clone()).collect()
     }
 }
 
@@ -91,7 +289,13 @@ impl CacheRepository for InMemoryCacheRepository {
     }
     
     async fn add_cached(&self, func: CachedFunction) {
-        self.cached.write().await.push(func);
+        let mut cached = self.cached.write().await;
+        cached.retain(|c| c.function_id != func.function_id || c.version != func.version);
+        cached.push(func);
+    }
+    
+    async fn remove_cached(&self, function_id: &str, version: &str) {
+        self.cached.write().await.retain(|c| c.function_id != function_id || c.version != version);
     }
     
     #[allow(dead_code)]
@@ -100,52 +304,49 @@ impl CacheRepository for InMemoryCacheRepository {
     }
 }
 
-fn path_matches(pattern: &str, path: &str) -> bool {
-    if pattern == "*" || pattern == "/*" {
-        return true;
+fn upsert_route(routes: &mut Vec<CompiledRoute>, route: Route) -> Result<(), String> {
+    let compiled = CompiledRoute::compile(route)?;
+    if let Some(existing) = routes.iter().find(|r| r.route.id != compiled.route.id && overlaps(r, &compiled)) {
+        return Err(format!(
+            "Route {} conflicts with route {} ({} {})",
+            compiled.route.id, existing.route.id, compiled.route.host, compiled.route.path
+        ));
     }
     
-    // プレフィックスワイルドカード処理
-    if pattern.ends_with("/*") {
-        let prefix = &pattern[..pattern.len() - 2];
-        if prefix.is_empty() {
-            return true;
-        }
-        return path.starts_with(prefix) && (path.len() == prefix.len() || path.chars().nth(prefix.len()) == Some('/'));
+    match routes.iter_mut().find(|r| r.route.id == compiled.route.id) {
+        Some(slot) => *slot = compiled,
+        None => routes.push(compiled),
     }
-    
-    let pattern_parts: Vec<&str> = pattern.split('/').filter(|p| !p.is_empty()).collect();
-    let path_parts: Vec<&str> = path.split('/').filter(|p| !p.is_empty()).collect();
-    
-    if pattern_parts.len() != path_parts.len() {
-        if pattern.ends_with("*") && !pattern.ends_with("/*") {
-            let prefix = &pattern[..pattern.len() - 1];
-            return path.starts_with(prefix);
-        }
+    Ok(())
+}
+
+// 同じ host/path パターンでメソッドが重なるルートは、どちらが選ばれるか定まらない。
+// パスはパラメータ名を除いた形で比べる (/users/:id と /users/:uid は同じパターン)
+fn overlaps(a: &CompiledRoute, b: &CompiledRoute) -> bool {
+    let same_path = a.path.len() == b.path.len()
+        && a.path.iter().zip(&b.path).all(|(x, y)| x.shape() == y.shape());
+    if !a.route.host.eq_ignore_ascii_case(&b.route.host) || !same_path {
         return false;
     }
-    
-    for (p_part, path_part) in pattern_parts.iter().zip(path_parts.iter()) {
-        if p_part.starts_with(':') {
-            continue;
-        }
-        if p_part != path_part {
-            return false;
-        }
-    }
-    true
+    let any = |r: &Route| r.methods.iter().any(|m| m == "*");
+    let (a, b) = (&a.route, &b.route);
+    any(a) || any(b) || a.methods.iter().any(|m| b.methods.contains(m))
 }
 
-fn extract_path_params(pattern: &str, path: &str) -> HashMap<String, String> {
-    let mut params = HashMap::new();
-    let pattern_parts: Vec<&str> = pattern.split('/').filter(|p| !p.is_empty()).collect();
-    let path_parts: Vec<&str> = path.split('/').filter(|p| !p.is_empty()).collect();
-    
-    for (p_part, path_part) in pattern_parts.iter().zip(path_parts.iter()) {
-        if p_part.starts_with(':') {
-            let key = &p_part[1..];
-            params.insert(key.to_string(), path_part.to_string());
-        }
-    }
-    params
+fn method_allowed(route: &Route, method: &str) -> bool {
+    route.methods.iter().any(|m| m == method || m == "*")
+}
+
+// 優先度の高い順、同じ優先度なら具体的なものから。最後は id で順序を固定する
+fn route_order(a: &CompiledRoute, b: &CompiledRoute) -> std::cmp::Ordering {
+    b.route.priority.cmp(&a.route.priority)
+        .then_with(|| specificity(b).cmp(&specificity(a)))
+        .then_with(|| a.route.id.cmp(&b.route.id))
+}
+
+// (ホスト, パスの各セグメント, メソッド指定) の具体性。大きいほど具体的
+fn specificity(compiled: &CompiledRoute) -> (u8, Vec<u8>, u8) {
+    let segments = compiled.path.iter().map(Segment::specificity).collect();
+    let methods = if compiled.route.methods.iter().any(|m| m == "*") { 0 } else { 1 };
+    (compiled.host.specificity(), segments, methods)
 }
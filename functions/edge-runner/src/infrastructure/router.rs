@@ -0,0 +1,245 @@
+use regex::Regex;
+use std::collections::HashMap;
+
+// パスパターンの 1 セグメント
+#[derive(Clone)]
+pub enum Segment {
+    Static(String),
+    // :name または :name(regex)。正規表現はセグメント全体に一致する必要がある
+    Param { name: String, constraint: Option<Regex> },
+    // 末尾の * / *name。残りの 0 個以上のセグメントに一致し、name があれば "a/b" 形式で取り出す
+    CatchAll(Option<String>),
+}
+
+impl Segment {
+    // 同じ位置のセグメント同士の具体性。大きいほど具体的
+    pub fn specificity(&self) -> u8 {
+        match self {
+            Segment::Static(_) => 3,
+            Segment::Param { constraint: Some(_), .. } => 2,
+            Segment::Param { constraint: None, .. } => 1,
+            Segment::CatchAll(_) => 0,
+        }
+    }
+
+    // パラメータ名を除いた形。同じ形のセグメントは同じパスに一致する
+    pub fn shape(&self) -> String {
+        match self {
+            Segment::Static(value) => value.clone(),
+            Segment::Param { constraint: Some(regex), .. } => format!(":({})", regex.as_str()),
+            Segment::Param { constraint: None, .. } => ":".to_string(),
+            Segment::CatchAll(_) => "*".to_string(),
+        }
+    }
+}
+
+pub fn parse_path(pattern: &str) -> Result<Vec<Segment>, String> {
+    let parts: Vec<&str> = pattern.split('/').filter(|p| !p.is_empty()).collect();
+    let mut segments = Vec::with_capacity(parts.len());
+    for (i, part) in parts.iter().enumerate() {
+        let segment = if let Some(name) = part.strip_prefix('*') {
+            if i + 1 != parts.len() {
+                return Err(format!("Catch-all must be the last segment in '{}'", pattern));
+            }
+            Segment::CatchAll((!name.is_empty()).then(|| name.to_string()))
+        } else if let Some(param) = part.strip_prefix(':') {
+            parse_param(param).map_err(|e| format!("Invalid parameter in '{}': {}", pattern, e))?
+        } else if part.contains('*') {
+            // 以前は "/api*" を前方一致として扱っていた。曖昧なので "/api/*" に書き換えてもらう
+            return Err(format!("Wildcard must be a whole segment in '{}'", pattern));
+        } else {
+            Segment::Static(part.to_string())
+        };
+        segments.push(segment);
+    }
+    Ok(segments)
+}
+
+fn parse_param(param: &str) -> Result<Segment, String> {
+    let (name, constraint) = match param.find('(') {
+        Some(open) => {
+            let expr = param[open + 1..].strip_suffix(')').ok_or("missing ')'")?;
+            let regex = Regex::new(&format!("^(?:{})$", expr)).map_err(|e| e.to_string())?;
+            (&param[..open], Some(regex))
+        }
+        None => (param, None),
+    };
+    if name.is_empty() {
+        return Err("empty name".to_string());
+    }
+    Ok(Segment::Param { name: name.to_string(), constraint })
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum HostPattern {
+    Any,
+    // "*.example.com" は ".example.com" で終わるすべてのサブドメインに一致する
+    Suffix(String),
+    Exact(String),
+}
+
+impl HostPattern {
+    pub fn parse(host: &str) -> Self {
+        let host = host.to_ascii_lowercase();
+        if host == "*" {
+            HostPattern::Any
+        } else if let Some(domain) = host.strip_prefix("*.") {
+            HostPattern::Suffix(format!(".{}", domain))
+        } else {
+            HostPattern::Exact(host)
+        }
+    }
+
+    pub fn specificity(&self) -> u8 {
+        match self {
+            HostPattern::Exact(_) => 2,
+            HostPattern::Suffix(_) => 1,
+            HostPattern::Any => 0,
+        }
+    }
+}
+
+// Host ヘッダーからポートを除いて小文字にする
+pub fn normalize_host(host: &str) -> String {
+    let host = if host.starts_with('[') {
+        // IPv6 リテラル ([::1]:3000)
+        host.find(']').map(|end| &host[..=end]).unwrap_or(host)
+    } else {
+        host.rsplit_once(':').map(|(name, _)| name).unwrap_or(host)
+    };
+    host.to_ascii_lowercase()
+}
+
+pub struct PathMatch {
+    // build に渡したルートの添字
+    pub route: usize,
+    pub params: HashMap<String, String>,
+    // * に一致した残りのパス (名前の有無によらない)
+    pub rest: Option<String>,
+}
+
+struct ParamChild {
+    name: String,
+    constraint: Option<Regex>,
+    node: Node,
+}
+
+// セグメント単位の基数木。固定セグメントはハッシュで辿るのでルート数に比例しない
+#[derive(Default)]
+struct Node {
+    statics: HashMap<String, Node>,
+    params: Vec<ParamChild>,
+    catch_alls: Vec<(Option<String>, usize)>,
+    routes: Vec<usize>,
+}
+
+impl Node {
+    fn insert(&mut self, segments: &[Segment], route: usize) {
+        match segments.split_first() {
+            None => self.routes.push(route),
+            Some((Segment::Static(segment), rest)) => {
+                self.statics.entry(segment.clone()).or_default().insert(rest, route);
+            }
+            Some((Segment::Param { name, constraint }, rest)) => {
+                let existing = self.params.iter().position(|p| {
+                    p.name == *name && p.constraint.as_ref().map(Regex::as_str) == constraint.as_ref().map(Regex::as_str)
+                });
+                let index = match existing {
+                    Some(index) => index,
+                    None => {
+                        self.params.push(ParamChild {
+                            name: name.clone(),
+                            constraint: constraint.clone(),
+                            node: Node::default(),
+                        });
+                        self.params.len() - 1
+                    }
+                };
+                self.params[index].node.insert(rest, route);
+            }
+            Some((Segment::CatchAll(name), _)) => self.catch_alls.push((name.clone(), route)),
+        }
+    }
+
+    // パスに一致するルートをすべて集める
+    fn collect(&self, segments: &[&str], params: &mut Vec<(String, String)>, out: &mut Vec<PathMatch>) {
+        for (name, route) in &self.catch_alls {
+            let rest = segments.join("/");
+            let mut captured: HashMap<String, String> = params.iter().cloned().collect();
+            if let Some(name) = name {
+                captured.insert(name.clone(), rest.clone());
+            }
+            out.push(PathMatch { route: *route, params: captured, rest: Some(rest) });
+        }
+
+        let (segment, rest) = match segments.split_first() {
+            Some(split) => split,
+            None => {
+                for route in &self.routes {
+                    out.push(PathMatch { route: *route, params: params.iter().cloned().collect(), rest: None });
+                }
+                return;
+            }
+        };
+
+        if let Some(child) = self.statics.get(*segment) {
+            child.collect(rest, params, out);
+        }
+        for param in &self.params {
+            let allowed = match &param.constraint {
+                Some(regex) => regex.is_match(segment),
+                None => true,
+            };
+            if allowed {
+                params.push((param.name.clone(), segment.to_string()));
+                param.node.collect(rest, params, out);
+                params.pop();
+            }
+        }
+    }
+}
+
+// ホストごとの木。候補となる木は高々 (ラベル数 + 2) 個なので、探索はルート数によらない
+#[derive(Default)]
+pub struct CompiledRouter {
+    exact: HashMap<String, Node>,
+    suffixes: HashMap<String, Node>,
+    any: Node,
+}
+
+impl CompiledRouter {
+    pub fn build<'a>(routes: impl IntoIterator<Item = (&'a HostPattern, &'a [Segment])>) -> Self {
+        let mut router = Self::default();
+        for (index, (host, segments)) in routes.into_iter().enumerate() {
+            let node = match host {
+                HostPattern::Any => &mut router.any,
+                HostPattern::Suffix(suffix) => router.suffixes.entry(suffix.clone()).or_default(),
+                HostPattern::Exact(host) => router.exact.entry(host.clone()).or_default(),
+            };
+            node.insert(segments, index);
+        }
+        router
+    }
+
+    // host/path に一致するルートをすべて返す (メソッドは見ない)
+    pub fn lookup(&self, host: &str, path: &str) -> Vec<PathMatch> {
+        let host = normalize_host(host);
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let mut params = Vec::new();
+        let mut out = Vec::new();
+
+        if let Some(node) = self.exact.get(&host) {
+            node.collect(&segments, &mut params, &mut out);
+        }
+        if !self.suffixes.is_empty() {
+            for (i, _) in host.match_indices('.').filter(|(i, _)| *i > 0) {
+                if let Some(node) = self.suffixes.get(&host[i..]) {
+                    node.collect(&segments, &mut params, &mut out);
+                }
+            }
+        }
+        self.any.collect(&segments, &mut params, &mut out);
+
+        out
+    }
+}
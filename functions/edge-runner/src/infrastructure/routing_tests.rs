@@ -1,7 +1,11 @@
 #[cfg(test)]
 mod tests {
+    use crate::application::dto::InvocationRequest;
+    use crate::application::InvocationService;
     use crate::domain::*;
     use crate::infrastructure::*;
+    use crate::test_support::{self, TestNode};
+    use sha2::{Digest, Sha256};
     use std::sync::Arc;
 
     #[tokio::test]
@@ -14,10 +18,11 @@ mod tests {
             function_id: "func1".to_string(),
             methods: vec!["GET".to_string()],
             priority: 100,
-        }).await;
+            split: None,
+        }).await.unwrap();
 
         let result = repo.match_route("localhost", "/api/users", "GET").await;
-        assert!(result.is_some());
+        assert!(result.is_ok());
         assert_eq!(result.unwrap().function_id, "func1");
     }
 
@@ -31,10 +36,11 @@ mod tests {
             function_id: "func1".to_string(),
             methods: vec!["GET".to_string()],
             priority: 100,
-        }).await;
+            split: None,
+        }).await.unwrap();
 
         let result = repo.match_route("localhost", "/api/users/123", "GET").await;
-        assert!(result.is_some());
+        assert!(result.is_ok());
         let route_match = result.unwrap();
         assert_eq!(route_match.function_id, "func1");
         assert_eq!(route_match.path_params.get("id"), Some(&"123".to_string()));
@@ -50,10 +56,11 @@ mod tests {
             function_id: "func1".to_string(),
             methods: vec!["GET".to_string()],
             priority: 100,
-        }).await;
+            split: None,
+        }).await.unwrap();
 
         let result = repo.match_route("localhost", "/api/users/123/posts/456", "GET").await;
-        assert!(result.is_some());
+        assert!(result.is_ok());
         let route_match = result.unwrap();
         assert_eq!(route_match.path_params.get("user_id"), Some(&"123".to_string()));
         assert_eq!(route_match.path_params.get("post_id"), Some(&"456".to_string()));
@@ -69,13 +76,14 @@ mod tests {
             function_id: "func1".to_string(),
             methods: vec!["GET".to_string()],
             priority: 100,
-        }).await;
+            split: None,
+        }).await.unwrap();
 
         let result1 = repo.match_route("localhost", "/api/users", "GET").await;
         let result2 = repo.match_route("localhost", "/api/posts", "GET").await;
         
-        assert!(result1.is_some());
-        assert!(result2.is_some());
+        assert!(result1.is_ok());
+        assert!(result2.is_ok());
     }
 
     #[tokio::test]
@@ -88,10 +96,11 @@ mod tests {
             function_id: "func1".to_string(),
             methods: vec!["GET".to_string()],
             priority: 100,
-        }).await;
+            split: None,
+        }).await.unwrap();
 
         let result = repo.match_route("localhost", "/any/path", "GET").await;
-        assert!(result.is_some());
+        assert!(result.is_ok());
     }
 
     #[tokio::test]
@@ -104,15 +113,18 @@ mod tests {
             function_id: "func1".to_string(),
             methods: vec!["GET".to_string(), "POST".to_string()],
             priority: 100,
-        }).await;
+            split: None,
+        }).await.unwrap();
 
         let get_result = repo.match_route("localhost", "/api/users", "GET").await;
         let post_result = repo.match_route("localhost", "/api/users", "POST").await;
         let put_result = repo.match_route("localhost", "/api/users", "PUT").await;
 
-        assert!(get_result.is_some());
-        assert!(post_result.is_some());
-        assert!(put_result.is_none());
+        assert!(get_result.is_ok());
+        assert!(post_result.is_ok());
+        assert_eq!(put_result.unwrap_err(), InvocationError::MethodNotAllowed {
+            allowed: vec!["GET".to_string(), "POST".to_string()],
+        });
     }
 
     #[tokio::test]
@@ -125,15 +137,16 @@ mod tests {
             function_id: "func1".to_string(),
             methods: vec!["*".to_string()],
             priority: 100,
-        }).await;
+            split: None,
+        }).await.unwrap();
 
         let get_result = repo.match_route("localhost", "/api/users", "GET").await;
         let post_result = repo.match_route("localhost", "/api/users", "POST").await;
         let delete_result = repo.match_route("localhost", "/api/users", "DELETE").await;
 
-        assert!(get_result.is_some());
-        assert!(post_result.is_some());
-        assert!(delete_result.is_some());
+        assert!(get_result.is_ok());
+        assert!(post_result.is_ok());
+        assert!(delete_result.is_ok());
     }
 
     #[tokio::test]
@@ -146,13 +159,14 @@ mod tests {
             function_id: "func1".to_string(),
             methods: vec!["GET".to_string()],
             priority: 100,
-        }).await;
+            split: None,
+        }).await.unwrap();
 
         let match_result = repo.match_route("example.com", "/api/users", "GET").await;
         let no_match_result = repo.match_route("other.com", "/api/users", "GET").await;
 
-        assert!(match_result.is_some());
-        assert!(no_match_result.is_none());
+        assert!(match_result.is_ok());
+        assert_eq!(no_match_result.unwrap_err(), InvocationError::RouteNotFound);
     }
 
     #[tokio::test]
@@ -167,7 +181,8 @@ mod tests {
             function_id: "func1".to_string(),
             methods: vec!["GET".to_string()],
             priority: 10,
-        }).await;
+            split: None,
+        }).await.unwrap();
 
         // 高優先度ルート
         repo.add_route(Route {
@@ -177,10 +192,11 @@ mod tests {
             function_id: "func2".to_string(),
             methods: vec!["GET".to_string()],
             priority: 100,
-        }).await;
+            split: None,
+        }).await.unwrap();
 
         let result = repo.match_route("localhost", "/api/users", "GET").await;
-        assert!(result.is_some());
+        assert!(result.is_ok());
         assert_eq!(result.unwrap().function_id, "func2");
     }
 
@@ -194,10 +210,11 @@ mod tests {
             function_id: "func1".to_string(),
             methods: vec!["GET".to_string()],
             priority: 100,
-        }).await;
+            split: None,
+        }).await.unwrap();
 
         let result = repo.match_route("localhost", "/api/posts", "GET").await;
-        assert!(result.is_none());
+        assert!(result.is_err());
     }
 
     #[tokio::test]
@@ -211,7 +228,8 @@ mod tests {
             function_id: "func1".to_string(),
             methods: vec!["GET".to_string()],
             priority: 100,
-        }).await;
+            split: None,
+        }).await.unwrap();
 
         repo.add_route(Route {
             id: "r2".to_string(),
@@ -220,7 +238,8 @@ mod tests {
             function_id: "func2".to_string(),
             methods: vec!["GET".to_string()],
             priority: 50,
-        }).await;
+            split: None,
+        }).await.unwrap();
 
         let routes = repo.list_routes().await;
         assert_eq!(routes.len(), 2);
@@ -228,4 +247,376 @@ mod tests {
         assert_eq!(routes[0].function_id, "func1");
         assert_eq!(routes[1].function_id, "func2");
     }
+
+    #[tokio::test]
+    async fn test_replace_routes_removes_stale_routes() {
+        let repo = InMemoryRouteRepository::new();
+        let routes = || vec![
+            route("r1", "*", "/api/users", &["GET"], 100),
+            route("r2", "*", "/api/posts", &["GET"], 100),
+        ];
+
+        repo.replace_routes(routes()).await;
+        // 同じ集合が何度届いても重複しない
+        repo.replace_routes(routes()).await;
+        assert_eq!(repo.list_routes().await.len(), 2);
+
+        let rejected = repo.replace_routes(vec![route("r1", "*", "/api/users", &["GET"], 100)]).await;
+        assert!(rejected.is_empty());
+        assert_eq!(repo.list_routes().await.len(), 1);
+        assert!(repo.match_route("localhost", "/api/posts", "GET").await.is_err());
+        assert!(repo.match_route("localhost", "/api/users", "GET").await.is_ok());
+    }
+
+    fn route(id: &str, host: &str, path: &str, methods: &[&str], priority: i32) -> Route {
+        Route {
+            id: id.to_string(),
+            host: host.to_string(),
+            path: path.to_string(),
+            function_id: format!("fn-{}", id),
+            methods: methods.iter().map(|m| m.to_string()).collect(),
+            priority,
+            split: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_route_upserts_by_id() {
+        let repo = InMemoryRouteRepository::new();
+        repo.add_route(route("r1", "*", "/api/users", &["GET"], 100)).await.unwrap();
+        repo.add_route(route("r1", "*", "/api/posts", &["GET"], 100)).await.unwrap();
+
+        let routes = repo.list_routes().await;
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0].path, "/api/posts");
+        assert!(repo.match_route("localhost", "/api/users", "GET").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_conflicting_route_rejected() {
+        let repo = InMemoryRouteRepository::new();
+        repo.add_route(route("r1", "*", "/api/users", &["GET", "POST"], 100)).await.unwrap();
+
+        let conflict = repo.add_route(route("r2", "*", "/api/users", &["POST"], 50)).await;
+        assert!(conflict.unwrap_err().contains("conflicts with route r1"));
+        let wildcard = repo.add_route(route("r3", "*", "/api/users", &["*"], 100)).await;
+        assert!(wildcard.is_err());
+
+        // メソッドが重ならない、またはホストが異なれば共存できる
+        repo.add_route(route("r4", "*", "/api/users", &["DELETE"], 100)).await.unwrap();
+        repo.add_route(route("r5", "example.com", "/api/users", &["GET"], 100)).await.unwrap();
+        assert_eq!(repo.list_routes().await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_conflicts_ignore_param_names() {
+        let repo = InMemoryRouteRepository::new();
+        repo.add_route(route("r1", "*", "/users/:id", &["GET"], 100)).await.unwrap();
+        repo.add_route(route("r2", "*", "/files/*rest", &["GET"], 100)).await.unwrap();
+
+        // パラメータ名やスラッシュの数が違うだけのパターンは同じものとみなす
+        let renamed = repo.add_route(route("r3", "*", "/users/:uid", &["GET"], 100)).await;
+        assert!(renamed.unwrap_err().contains("conflicts with route r1"));
+        assert!(repo.add_route(route("r4", "*", "/users//:name/", &["GET"], 100)).await.is_err());
+        assert!(repo.add_route(route("r5", "*", "/files/*", &["GET"], 100)).await.is_err());
+
+        // 制約の違うパラメータは別のパターン
+        repo.add_route(route("r6", "*", r"/users/:id(\d+)", &["GET"], 100)).await.unwrap();
+        assert_eq!(repo.list_routes().await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_replace_routes_skips_conflicts() {
+        let repo = InMemoryRouteRepository::new();
+        let rejected = repo.replace_routes(vec![
+            route("r1", "*", "/api/users", &["GET"], 100),
+            route("r2", "*", "/api/users", &["GET"], 100),
+        ]).await;
+
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(repo.list_routes().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_equal_priority_prefers_specific_route() {
+        let repo = InMemoryRouteRepository::new();
+        // 登録順に関係なく具体的なルートが先に評価される
+        repo.add_route(route("a-wildcard", "*", "/api/*", &["GET"], 100)).await.unwrap();
+        repo.add_route(route("b-param", "*", "/api/users/:id", &["GET"], 100)).await.unwrap();
+        repo.add_route(route("c-exact", "*", "/api/users/me", &["GET"], 100)).await.unwrap();
+        repo.add_route(route("d-host", "example.com", "/api/*", &["GET"], 100)).await.unwrap();
+
+        let me = repo.match_route("localhost", "/api/users/me", "GET").await.unwrap();
+        assert_eq!(me.function_id, "fn-c-exact");
+        let user = repo.match_route("localhost", "/api/users/42", "GET").await.unwrap();
+        assert_eq!(user.function_id, "fn-b-param");
+        let host = repo.match_route("example.com", "/api/posts", "GET").await.unwrap();
+        assert_eq!(host.function_id, "fn-d-host");
+    }
+
+    #[tokio::test]
+    async fn test_remove_route() {
+        let repo = InMemoryRouteRepository::new();
+        repo.add_route(route("r1", "*", "/api/users", &["GET"], 100)).await.unwrap();
+
+        assert_eq!(repo.remove_route("r1").await.unwrap().id, "r1");
+        assert!(repo.remove_route("r1").await.is_none());
+        assert!(repo.match_route("localhost", "/api/users", "GET").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_host_wildcard() {
+        let repo = InMemoryRouteRepository::new();
+        repo.add_route(route("r1", "*.example.com", "/api/*", &["GET"], 100)).await.unwrap();
+        repo.add_route(route("r2", "api.example.com", "/api/*", &["GET"], 100)).await.unwrap();
+
+        let sub = repo.match_route("shop.example.com", "/api/items", "GET").await.unwrap();
+        assert_eq!(sub.function_id, "fn-r1");
+        let nested = repo.match_route("eu.shop.example.com:3000", "/api/items", "GET").await.unwrap();
+        assert_eq!(nested.function_id, "fn-r1");
+        // 完全一致のホストの方が具体的
+        let exact = repo.match_route("API.example.com", "/api/items", "GET").await.unwrap();
+        assert_eq!(exact.function_id, "fn-r2");
+
+        assert!(repo.match_route("example.com", "/api/items", "GET").await.is_err());
+        assert!(repo.match_route("badexample.com", "/api/items", "GET").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_regex_constrained_param() {
+        let repo = InMemoryRouteRepository::new();
+        repo.add_route(route("by-id", "*", r"/users/:id(\d+)", &["GET"], 100)).await.unwrap();
+        repo.add_route(route("by-name", "*", "/users/:name", &["GET"], 100)).await.unwrap();
+
+        let by_id = repo.match_route("localhost", "/users/42", "GET").await.unwrap();
+        assert_eq!(by_id.function_id, "fn-by-id");
+        assert_eq!(by_id.path_params.get("id"), Some(&"42".to_string()));
+
+        let by_name = repo.match_route("localhost", "/users/alice", "GET").await.unwrap();
+        assert_eq!(by_name.function_id, "fn-by-name");
+        assert_eq!(by_name.path_params.get("name"), Some(&"alice".to_string()));
+        // 正規表現はセグメント全体に一致する必要がある
+        let partial = repo.match_route("localhost", "/users/42abc", "GET").await.unwrap();
+        assert_eq!(partial.function_id, "fn-by-name");
+    }
+
+    #[tokio::test]
+    async fn test_catch_all_param() {
+        let repo = InMemoryRouteRepository::new();
+        repo.add_route(route("r1", "*", "/static/:bucket/*rest", &["GET"], 100)).await.unwrap();
+
+        let nested = repo.match_route("localhost", "/static/assets/css/site.css", "GET").await.unwrap();
+        assert_eq!(nested.path_params.get("bucket"), Some(&"assets".to_string()));
+        assert_eq!(nested.path_params.get("rest"), Some(&"css/site.css".to_string()));
+
+        let empty = repo.match_route("localhost", "/static/assets", "GET").await.unwrap();
+        assert_eq!(empty.path_params.get("rest"), Some(&String::new()));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_patterns_rejected() {
+        let repo = InMemoryRouteRepository::new();
+        assert!(repo.add_route(route("r1", "*", "/files/*rest/meta", &["GET"], 100)).await.is_err());
+        assert!(repo.add_route(route("r2", "*", r"/users/:id(\d+", &["GET"], 100)).await.is_err());
+        assert!(repo.add_route(route("r3", "*", "/users/:id([)", &["GET"], 100)).await.is_err());
+        // セグメント途中の "*" は前方一致にならない
+        assert!(repo.add_route(route("r4", "*", "/api*", &["GET"], 100)).await.is_err());
+        assert!(repo.add_route(route("r5", "*", "/static/img*.png", &["GET"], 100)).await.is_err());
+        assert!(repo.list_routes().await.is_empty());
+    }
+
+    async fn average_lookup(route_count: usize) -> std::time::Duration {
+        let repo = InMemoryRouteRepository::new();
+        let routes = (0..route_count)
+            .map(|i| route(&format!("r{}", i), &format!("tenant{}.example.com", i % 100), &format!("/svc{}/users/:id", i), &["GET"], 100))
+            .collect();
+        assert!(repo.replace_routes(routes).await.is_empty());
+
+        let lookups = 20_000;
+        let start = std::time::Instant::now();
+        for i in 0..lookups {
+            let n = (i * 7919) % route_count;
+            let host = format!("tenant{}.example.com", n % 100);
+            let path = format!("/svc{}/users/{}", n, i);
+            assert!(repo.match_route(&host, &path, "GET").await.is_ok());
+        }
+        start.elapsed() / lookups as u32
+    }
+
+    // cargo test --release -- --ignored --nocapture bench_lookup
+    #[tokio::test]
+    #[ignore]
+    async fn bench_lookup_scales_with_path_not_route_count() {
+        let small = average_lookup(100).await;
+        let large = average_lookup(10_000).await;
+        println!("avg lookup: 100 routes = {:?}, 10k routes = {:?}", small, large);
+        assert!(large < small * 5, "lookup at 10k routes ({:?}) is not close to 100 routes ({:?})", large, small);
+    }
+
+    #[tokio::test]
+    async fn test_match_reports_route_id_and_rest() {
+        let repo = InMemoryRouteRepository::new();
+        repo.add_route(route("assets", "*", "/assets/*", &["GET"], 100)).await.unwrap();
+        repo.add_route(route("user", "*", "/users/:id", &["GET"], 100)).await.unwrap();
+
+        let assets = repo.match_route("localhost", "/assets/img/logo.png", "GET").await.unwrap();
+        assert_eq!(assets.route_id, "assets");
+        assert_eq!(assets.rest.as_deref(), Some("img/logo.png"));
+        assert!(assets.path_params.is_empty());
+
+        let user = repo.match_route("localhost", "/users/7", "GET").await.unwrap();
+        assert_eq!(user.route_id, "user");
+        assert!(user.rest.is_none());
+    }
+
+    // handle の 5 組目の引数 (コンテキスト) をそのままボディとして返すゲスト
+    const ECHO_CONTEXT_GUEST: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (data (i32.const 0) "200\0d\0a\0d\0a")
+          (global $next (mut i32) (i32.const 1024))
+          (func (export "alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $ptr))
+          (func (export "dealloc") (param i32 i32))
+          (func (export "handle")
+            (param i32 i32 i32 i32 i32 i32 i32 i32) (param $ctx i32) (param $ctx_len i32) (result i64)
+            (memory.copy (i32.const 7) (local.get $ctx) (local.get $ctx_len))
+            (i64.extend_i32_u (i32.add (i32.const 7) (local.get $ctx_len)))))
+    "#;
+
+    // コンテキストを受け取らない従来の 4 組の引数のゲスト
+    const LEGACY_GUEST: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (data (i32.const 0) "204\0d\0a\0d\0a")
+          (global $next (mut i32) (i32.const 1024))
+          (func (export "alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $ptr))
+          (func (export "dealloc") (param i32 i32))
+          (func (export "handle") (param i32 i32 i32 i32 i32 i32 i32 i32) (result i64)
+            (i64.const 7)))
+    "#;
+
+    // artifact_url を指定しなければアーティファクトをキャッシュに入れておく
+    async fn invocation_service(name: &str, guest: &str, routes: Vec<Route>, artifact_url: Option<String>) -> InvocationService {
+        let wasm = guest.as_bytes();
+        let sha256 = format!("{:x}", Sha256::digest(wasm));
+        let metadata = FunctionMetadata {
+            artifact_url: artifact_url.clone().unwrap_or_default(),
+            sha256: sha256.clone(),
+            memory_pages: 1,
+            max_execution_ms: 1000,
+            ..test_support::metadata("fn-e2e", "1.0.0")
+        };
+
+        let cache = Arc::new(LocalWasmCache::new(test_support::temp_dir("routing-test", name), 1024 * 1024).unwrap());
+        if artifact_url.is_none() {
+            cache.put(&metadata.function_id, &metadata.version, wasm, &sha256).await.unwrap();
+        }
+
+        let node = TestNode::new();
+        node.deploy(metadata).await;
+        assert!(node.function_service.replace_routes(routes).await.is_empty());
+        node.invocation_service(cache)
+    }
+
+    fn get(host: &str, path: &str) -> InvocationRequest {
+        InvocationRequest {
+            request_id: "req-1".to_string(),
+            method: "GET".to_string(),
+            path: path.to_string(),
+            query: None,
+            host: host.to_string(),
+            headers: vec![("host".to_string(), host.to_string())],
+            body: Vec::new(),
+        }
+    }
+
+    fn e2e_route(id: &str, host: &str, path: &str) -> Route {
+        Route {
+            function_id: "fn-e2e".to_string(),
+            ..route(id, host, path, &["GET"], 100)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_route_context_delivered_to_guest() {
+        let service = invocation_service("context", ECHO_CONTEXT_GUEST, vec![
+            e2e_route("files", "*.example.com", "/files/:bucket/*rest"),
+            e2e_route("user", "*", r"/users/:id(\d+)"),
+        ], None).await;
+
+        let response = service.invoke(get("Shop.Example.com:8080", "/files/docs/a/b.txt")).await.unwrap();
+        assert_eq!(response.status_code, 200);
+        let context: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(context, serde_json::json!({
+            "route_id": "files",
+            "host": "Shop.Example.com:8080",
+            "params": {"bucket": "docs", "rest": "a/b.txt"},
+            "rest": "a/b.txt",
+        }));
+
+        let response = service.invoke(get("localhost", "/users/42")).await.unwrap();
+        let context: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(context, serde_json::json!({
+            "route_id": "user",
+            "host": "localhost",
+            "params": {"id": "42"},
+            "rest": null,
+        }));
+
+        assert_eq!(service.invoke(get("localhost", "/users/abc")).await.unwrap_err(), InvocationError::RouteNotFound);
+
+        let mut post = get("localhost", "/users/42");
+        post.method = "POST".to_string();
+        assert_eq!(service.invoke(post).await.unwrap_err(), InvocationError::MethodNotAllowed {
+            allowed: vec!["GET".to_string()],
+        });
+    }
+
+    #[tokio::test]
+    async fn test_legacy_handle_without_context() {
+        let service = invocation_service("legacy", LEGACY_GUEST, vec![
+            e2e_route("user", "*", "/users/:id"),
+        ], None).await;
+
+        let response = service.invoke(get("localhost", "/users/42")).await.unwrap();
+        assert_eq!(response.status_code, 204);
+        assert!(response.body.is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_missing_artifact_is_loaded_on_demand() {
+        let app = axum::Router::new().route("/guest.wasm", axum::routing::get(|| async { LEGACY_GUEST }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let service = invocation_service("on-demand", LEGACY_GUEST, vec![
+            e2e_route("user", "*", "/users/:id"),
+        ], Some(format!("http://{}/guest.wasm", addr))).await;
+
+        // 取得中は別のモジュールを実行せずに 503 (Loading) を返す
+        let loading = service.invoke(get("localhost", "/users/42")).await.unwrap_err();
+        assert_eq!(loading, InvocationError::Loading("fn-e2e".to_string()));
+
+        let mut response = None;
+        for _ in 0..50 {
+            match service.invoke(get("localhost", "/users/42")).await {
+                Ok(ok) => {
+                    response = Some(ok);
+                    break;
+                }
+                Err(InvocationError::Loading(_)) => tokio::time::sleep(std::time::Duration::from_millis(20)).await,
+                Err(e) => panic!("unexpected error: {}", e),
+            }
+        }
+        assert_eq!(response.expect("artifact was not loaded").status_code, 204);
+    }
 }
@@ -0,0 +1,191 @@
+use crate::infrastructure::metrics::TELEMETRY_OPERATIONS;
+use rusqlite::{params, Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+pub const MAX_TELEMETRY_BATCH: usize = 500;
+pub const MAX_TELEMETRY_READ: usize = 100;
+
+#[derive(Debug, PartialEq)]
+pub enum TelemetryError {
+    Unavailable,
+    InvalidReading(String),
+    Storage(String),
+}
+
+impl TelemetryError {
+    // ゲストには負の値で返す
+    pub fn code(&self) -> i32 {
+        match self {
+            TelemetryError::Unavailable => -1,
+            TelemetryError::InvalidReading(_) => -2,
+            TelemetryError::Storage(_) => -3,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for TelemetryError {
+    fn from(e: rusqlite::Error) -> Self {
+        TelemetryError::Storage(e.to_string())
+    }
+}
+
+// ゲストから受け取る読み取り値。edge-agent の TelemetryData と同じ項目
+#[derive(Deserialize)]
+pub struct TelemetryReading {
+    pub device_id: Option<String>,
+    pub sensor_id: String,
+    // UNIX 秒。省略時は現在時刻
+    pub timestamp: Option<i64>,
+    pub data_type: String,
+    pub value: f64,
+    pub unit: Option<String>,
+    pub metadata: Option<HashMap<String, serde_json::Value>>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum TelemetryBatch {
+    One(TelemetryReading),
+    Many(Vec<TelemetryReading>),
+}
+
+#[derive(Deserialize, Default)]
+pub struct TelemetryQuery {
+    pub device_id: Option<String>,
+    pub sensor_id: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TelemetryRecord {
+    pub id: String,
+    pub device_id: String,
+    pub sensor_id: String,
+    pub timestamp: i64,
+    pub data_type: String,
+    pub value: f64,
+    pub unit: Option<String>,
+    pub metadata: Option<serde_json::Value>,
+    pub sync_status: String,
+}
+
+// edge-agent の SQLite (telemetry_data テーブル) への書き込み口。
+// スキーマは edge-agent が管理するので、ここでは作成せず存在だけ確認する。
+// 書き込んだ行は sync_status = 'pending' のまま残り、edge-agent の同期処理で送られる
+pub struct TelemetryBridge {
+    conn: Mutex<Connection>,
+    default_device_id: Option<String>,
+}
+
+impl TelemetryBridge {
+    // edge-agent は EDGE_DB_SHARED=1 (WAL) で起動しておくこと
+    pub fn open(path: &str, default_device_id: Option<String>) -> Result<Self, String> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+            .map_err(|e| format!("Failed to open edge-agent database {}: {}", path, e))?;
+        Self::with_connection(conn, default_device_id)
+    }
+
+    pub(crate) fn with_connection(conn: Connection, default_device_id: Option<String>) -> Result<Self, String> {
+        conn.busy_timeout(Duration::from_secs(2)).map_err(|e| e.to_string())?;
+
+        let has_table: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'telemetry_data')",
+            [],
+            |row| row.get(0),
+        ).map_err(|e| e.to_string())?;
+        if !has_table {
+            return Err("telemetry_data table not found; start edge-agent first".to_string());
+        }
+
+        Ok(Self { conn: Mutex::new(conn), default_device_id })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // 1 件または配列を受け取り、まとめて 1 トランザクションで書き込む
+    pub fn write(&self, function_id: &str, readings: &[u8]) -> Result<usize, TelemetryError> {
+        let batch: TelemetryBatch = serde_json::from_slice(readings)
+            .map_err(|e| TelemetryError::InvalidReading(e.to_string()))?;
+        let readings = match batch {
+            TelemetryBatch::One(reading) => vec![reading],
+            TelemetryBatch::Many(readings) => readings,
+        };
+        if readings.len() > MAX_TELEMETRY_BATCH {
+            return Err(TelemetryError::InvalidReading(format!("at most {} readings per call", MAX_TELEMETRY_BATCH)));
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        let mut conn = self.lock();
+        let tx = conn.transaction()?;
+        for reading in &readings {
+            let device_id = reading.device_id.clone()
+                .or_else(|| self.default_device_id.clone())
+                .ok_or_else(|| TelemetryError::InvalidReading("device_id is required".to_string()))?;
+            if !reading.value.is_finite() {
+                return Err(TelemetryError::InvalidReading("value must be finite".to_string()));
+            }
+
+            // どの関数から書き込まれたか追跡できるようにする
+            let mut metadata = reading.metadata.clone().unwrap_or_default();
+            metadata.insert("source_function".to_string(), serde_json::Value::from(function_id));
+            let metadata_json = serde_json::to_string(&metadata).ok();
+
+            tx.execute(
+                r#"INSERT INTO telemetry_data
+                   (id, device_id, sensor_id, timestamp, data_type, value, unit, metadata, version)
+                   VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"#,
+                params![
+                    uuid::Uuid::new_v4().to_string(),
+                    device_id,
+                    reading.sensor_id,
+                    reading.timestamp.unwrap_or(now),
+                    reading.data_type,
+                    reading.value,
+                    reading.unit,
+                    metadata_json,
+                    1,
+                ],
+            )?;
+        }
+        tx.commit()?;
+
+        TELEMETRY_OPERATIONS.with_label_values(&[function_id, "write"]).inc_by(readings.len() as f64);
+        Ok(readings.len())
+    }
+
+    // 新しい順
+    pub fn read(&self, function_id: &str, query: &TelemetryQuery) -> Result<Vec<TelemetryRecord>, TelemetryError> {
+        let limit = query.limit.unwrap_or(MAX_TELEMETRY_READ).min(MAX_TELEMETRY_READ);
+        let conn = self.lock();
+        let mut stmt = conn.prepare(
+            r#"SELECT id, device_id, sensor_id, timestamp, data_type, value, unit, metadata, sync_status
+               FROM telemetry_data
+               WHERE (?1 IS NULL OR device_id = ?1) AND (?2 IS NULL OR sensor_id = ?2)
+               ORDER BY timestamp DESC
+               LIMIT ?3"#,
+        )?;
+
+        let rows = stmt.query_map(params![query.device_id, query.sensor_id, limit], |row| {
+            Ok(TelemetryRecord {
+                id: row.get(0)?,
+                device_id: row.get(1)?,
+                sensor_id: row.get(2)?,
+                timestamp: row.get(3)?,
+                data_type: row.get(4)?,
+                value: row.get(5)?,
+                unit: row.get(6)?,
+                metadata: row.get::<_, Option<String>>(7)?.and_then(|s| serde_json::from_str(&s).ok()),
+                sync_status: row.get::<_, Option<String>>(8)?.unwrap_or_default(),
+            })
+        })?;
+        let records = rows.collect::<Result<Vec<_>, _>>()?;
+
+        TELEMETRY_OPERATIONS.with_label_values(&[function_id, "read"]).inc();
+        Ok(records)
+    }
+}
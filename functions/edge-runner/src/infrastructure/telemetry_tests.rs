@@ -0,0 +1,92 @@
+#[cfg(test)]
+mod tests {
+    use crate::infrastructure::telemetry::*;
+    use rusqlite::Connection;
+
+    // edge-agent の Database::init_schema と同じテーブル
+    fn agent_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            r#"
+            CREATE TABLE telemetry_data (
+                id TEXT PRIMARY KEY,
+                device_id TEXT NOT NULL,
+                sensor_id TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                data_type TEXT NOT NULL,
+                value REAL NOT NULL,
+                unit TEXT,
+                metadata TEXT,
+                sync_status TEXT DEFAULT 'pending',
+                sync_timestamp INTEGER,
+                version INTEGER DEFAULT 1,
+                created_at INTEGER DEFAULT (strftime('%s', 'now'))
+            );
+            "#,
+        ).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_requires_agent_schema() {
+        let conn = Connection::open_in_memory().unwrap();
+        let result = TelemetryBridge::with_connection(conn, None);
+        assert!(matches!(result, Err(e) if e.contains("telemetry_data table not found")));
+    }
+
+    #[test]
+    fn test_write_single_and_batch() {
+        let bridge = TelemetryBridge::with_connection(agent_db(), Some("device-001".to_string())).unwrap();
+
+        let one = br#"{"sensor_id":"temp-1","timestamp":100,"data_type":"temperature","value":21.5,"unit":"C"}"#;
+        assert_eq!(bridge.write("func1", one), Ok(1));
+
+        let many = br#"[
+            {"device_id":"device-002","sensor_id":"temp-1","timestamp":200,"data_type":"temperature","value":22.0},
+            {"sensor_id":"hum-1","timestamp":300,"data_type":"humidity","value":40.0}
+        ]"#;
+        assert_eq!(bridge.write("func1", many), Ok(2));
+
+        let records = bridge.read("func1", &TelemetryQuery::default()).unwrap();
+        assert_eq!(records.len(), 3);
+        // 新しい順。edge-agent の同期対象になるよう pending で書き込む
+        assert_eq!(records[0].sensor_id, "hum-1");
+        assert_eq!(records[0].device_id, "device-001");
+        assert_eq!(records[0].sync_status, "pending");
+        assert_eq!(records[0].metadata.as_ref().unwrap()["source_function"], "func1");
+    }
+
+    #[test]
+    fn test_read_filters() {
+        let bridge = TelemetryBridge::with_connection(agent_db(), Some("device-001".to_string())).unwrap();
+        let many = br#"[
+            {"sensor_id":"temp-1","timestamp":100,"data_type":"temperature","value":21.0},
+            {"sensor_id":"temp-1","timestamp":200,"data_type":"temperature","value":22.0},
+            {"sensor_id":"hum-1","timestamp":300,"data_type":"humidity","value":40.0}
+        ]"#;
+        bridge.write("func1", many).unwrap();
+
+        let query = TelemetryQuery { device_id: None, sensor_id: Some("temp-1".to_string()), limit: Some(1) };
+        let records = bridge.read("func1", &query).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].timestamp, 200);
+    }
+
+    #[test]
+    fn test_rejects_invalid_readings() {
+        let bridge = TelemetryBridge::with_connection(agent_db(), None).unwrap();
+
+        // device_id の既定値がない
+        let reading = br#"{"sensor_id":"temp-1","data_type":"temperature","value":21.5}"#;
+        assert!(matches!(bridge.write("func1", reading), Err(TelemetryError::InvalidReading(_))));
+        assert!(matches!(bridge.write("func1", b"not json"), Err(TelemetryError::InvalidReading(_))));
+
+        // 途中で失敗したバッチは何も書き込まない
+        let batch = br#"[
+            {"device_id":"device-001","sensor_id":"temp-1","data_type":"temperature","value":21.5},
+            {"sensor_id":"temp-1","data_type":"temperature","value":21.5}
+        ]"#;
+        assert!(bridge.write("func1", batch).is_err());
+        assert!(bridge.read("func1", &TelemetryQuery::default()).unwrap().is_empty());
+    }
+}
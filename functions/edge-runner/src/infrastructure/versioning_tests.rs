@@ -0,0 +1,124 @@
+#[cfg(test)]
+mod tests {
+    use crate::domain::*;
+    use crate::infrastructure::*;
+    use crate::test_support;
+
+    fn metadata(version: &str) -> FunctionMetadata {
+        FunctionMetadata { sha256: format!("sha-{}", version), ..test_support::metadata("func1", version) }
+    }
+
+    #[tokio::test]
+    async fn test_register_does_not_switch_traffic() {
+        let repo = InMemoryFunctionRepository::new(3);
+        repo.register(metadata("1.0.0")).await;
+        assert!(repo.get("func1").await.is_none());
+
+        repo.activate("func1", "1.0.0").await.unwrap();
+        repo.register(metadata("2.0.0")).await;
+
+        // 検証が終わるまでは旧バージョンのまま
+        assert_eq!(repo.get("func1").await.unwrap().version, "1.0.0");
+    }
+
+    #[tokio::test]
+    async fn test_reregistered_artifact_is_used_only_after_activation() {
+        let repo = InMemoryFunctionRepository::new(3);
+        repo.register(metadata("1.0.0")).await;
+        repo.activate("func1", "1.0.0").await.unwrap();
+
+        let mut rebuilt = metadata("1.0.0");
+        rebuilt.sha256 = "sha-rebuilt".to_string();
+        assert!(repo.register(rebuilt).await.is_empty());
+
+        // 検証前は差し替え前のアーティファクトのまま
+        assert_eq!(repo.get("func1").await.unwrap().sha256, "sha-1.0.0");
+        assert_eq!(repo.get_verified("func1", "1.0.0").await.unwrap().sha256, "sha-1.0.0");
+        assert_eq!(repo.get_version("func1", "1.0.0").await.unwrap().sha256, "sha-rebuilt");
+
+        // 差し替え前のものを無効化したバージョンとして返す
+        let deactivated = repo.activate("func1", "1.0.0").await.unwrap();
+        assert_eq!(deactivated.unwrap().sha256, "sha-1.0.0");
+        assert_eq!(repo.get("func1").await.unwrap().sha256, "sha-rebuilt");
+    }
+
+    #[tokio::test]
+    async fn test_reregistered_canary_is_swapped_when_verified() {
+        let repo = InMemoryFunctionRepository::new(3);
+        repo.register(metadata("2.0.0")).await;
+        assert!(repo.mark_verified("func1", "2.0.0").await.unwrap().is_none());
+
+        let mut rebuilt = metadata("2.0.0");
+        rebuilt.sha256 = "sha-rebuilt".to_string();
+        repo.register(rebuilt).await;
+        assert_eq!(repo.get_verified("func1", "2.0.0").await.unwrap().sha256, "sha-2.0.0");
+
+        let replaced = repo.mark_verified("func1", "2.0.0").await.unwrap();
+        assert_eq!(replaced.unwrap().sha256, "sha-2.0.0");
+        assert_eq!(repo.get_verified("func1", "2.0.0").await.unwrap().sha256, "sha-rebuilt");
+    }
+
+    #[tokio::test]
+    async fn test_activate_returns_deactivated_version() {
+        let repo = InMemoryFunctionRepository::new(3);
+        repo.register(metadata("1.0.0")).await;
+        repo.register(metadata("2.0.0")).await;
+
+        assert!(repo.activate("func1", "1.0.0").await.unwrap().is_none());
+        let deactivated = repo.activate("func1", "2.0.0").await.unwrap();
+        assert_eq!(deactivated.unwrap().version, "1.0.0");
+        assert_eq!(repo.get("func1").await.unwrap().version, "2.0.0");
+
+        // 再有効化は何も無効化しない
+        assert!(repo.activate("func1", "2.0.0").await.unwrap().is_none());
+        assert!(repo.activate("func1", "3.0.0").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rollback_to_previous_version() {
+        let repo = InMemoryFunctionRepository::new(3);
+        for version in ["1.0.0", "2.0.0", "3.0.0"] {
+            repo.register(metadata(version)).await;
+            repo.activate("func1", version).await.unwrap();
+        }
+
+        assert_eq!(repo.previous("func1").await.unwrap().version, "2.0.0");
+        assert_eq!(repo.rollback("func1").await.unwrap().version, "3.0.0");
+        assert_eq!(repo.get("func1").await.unwrap().version, "2.0.0");
+
+        assert_eq!(repo.rollback("func1").await.unwrap().version, "2.0.0");
+        assert_eq!(repo.get("func1").await.unwrap().version, "1.0.0");
+        assert!(repo.rollback("func1").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_register_prunes_oldest_inactive_version() {
+        let repo = InMemoryFunctionRepository::new(2);
+        repo.register(metadata("1.0.0")).await;
+        repo.activate("func1", "1.0.0").await.unwrap();
+        repo.register(metadata("2.0.0")).await;
+
+        // 有効な 1.0.0 と登録したばかりの 3.0.0 は残す
+        let pruned = repo.register(metadata("3.0.0")).await;
+        assert_eq!(pruned.len(), 1);
+        assert_eq!(pruned[0].version, "2.0.0");
+        assert!(repo.get_version("func1", "2.0.0").await.is_none());
+        assert!(repo.get_version("func1", "3.0.0").await.is_some());
+        assert_eq!(repo.get("func1").await.unwrap().version, "1.0.0");
+    }
+
+    #[tokio::test]
+    async fn test_remove_returns_all_versions() {
+        let repo = InMemoryFunctionRepository::new(3);
+        repo.register(metadata("1.0.0")).await;
+        repo.register(metadata("2.0.0")).await;
+        repo.activate("func1", "2.0.0").await.unwrap();
+        assert_eq!(repo.function_ids().await, vec!["func1".to_string()]);
+
+        let removed = repo.remove("func1").await;
+        assert_eq!(removed.len(), 2);
+        assert!(repo.get("func1").await.is_none());
+        assert!(repo.function_ids().await.is_empty());
+        assert!(repo.remove("func1").await.is_empty());
+    }
+}
@@ -0,0 +1,665 @@
+use crate::domain::FunctionMetadata;
+use crate::infrastructure::guest_log::GuestLogStore;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use wasmer::{
+    AsStoreMut, AsStoreRef, ExternType, Function, FunctionEnv, FunctionEnvMut, FunctionType, Imports,
+    Instance, Memory, MemoryView, Module, RuntimeError, Type, Value,
+};
+
+// WASI preview1 の最小実装。
+// 標準出力/標準エラーは行ごとにゲストログ (関数ごとのレート制限付き) に流し、時計と乱数は仮想化する。
+// ファイルシステムは既定で持たず、preopen を指定した場合のみ読み取り専用で公開する。
+pub const WASI_NAMESPACE: &str = "wasi_snapshot_preview1";
+
+// ゲストから見た preopen ディレクトリのパス
+pub const PREOPEN_GUEST_PATH: &str = "/data";
+
+const ERRNO_SUCCESS: i32 = 0;
+pub(crate) const ERRNO_BADF: i32 = 8;
+const ERRNO_FAULT: i32 = 21;
+const ERRNO_INVAL: i32 = 28;
+const ERRNO_IO: i32 = 29;
+const ERRNO_MFILE: i32 = 33;
+const ERRNO_NAMETOOLONG: i32 = 37;
+pub(crate) const ERRNO_NOENT: i32 = 44;
+const ERRNO_NOSYS: i32 = 52;
+const ERRNO_NOTSUP: i32 = 58;
+const ERRNO_ROFS: i32 = 69;
+pub(crate) const ERRNO_NOTCAPABLE: i32 = 76;
+
+const FILETYPE_CHARACTER_DEVICE: u8 = 2;
+const FILETYPE_DIRECTORY: u8 = 3;
+const FILETYPE_REGULAR_FILE: u8 = 4;
+
+const RIGHTS_FD_READ: u64 = 1 << 1;
+const RIGHTS_FD_SEEK: u64 = 1 << 2;
+const RIGHTS_FD_TELL: u64 = 1 << 5;
+const RIGHTS_FD_WRITE: u64 = 1 << 6;
+const RIGHTS_PATH_OPEN: u64 = 1 << 13;
+const RIGHTS_PATH_FILESTAT_GET: u64 = 1 << 18;
+const RIGHTS_FD_FILESTAT_GET: u64 = 1 << 21;
+const RIGHTS_FILE_READ: u64 = RIGHTS_FD_READ | RIGHTS_FD_SEEK | RIGHTS_FD_TELL | RIGHTS_FD_FILESTAT_GET;
+const RIGHTS_DIR_READ: u64 = RIGHTS_PATH_OPEN | RIGHTS_PATH_FILESTAT_GET | RIGHTS_FD_FILESTAT_GET;
+
+// O_CREAT | O_EXCL | O_TRUNC
+const OFLAGS_WRITE: i32 = 1 | 4 | 8;
+const OFLAGS_DIRECTORY: i32 = 2;
+
+const CLOCK_REALTIME: i32 = 0;
+const CLOCK_RESOLUTION_NS: u64 = 1_000_000;
+
+pub(crate) const PREOPEN_FD: u32 = 3;
+const FIRST_FILE_FD: u32 = 4;
+const MAX_OPEN_FILES: usize = 32;
+
+// 改行のないまま溜まった出力はこのサイズで区切ってログに出す
+const MAX_LOG_LINE: usize = 4096;
+
+// 1 回の fd_read/fd_write で扱うバイト数の上限。超えた分は短い読み書きとしてゲストに返す
+const MAX_IO_BYTES: u32 = 1024 * 1024;
+const MAX_PATH_LEN: u32 = 4096;
+// random_get はこの単位でゲストのメモリに書き込む
+const RANDOM_CHUNK: usize = 4096;
+
+type Errno = i32;
+
+pub struct WasiState {
+    function_id: String,
+    version: String,
+    request_id: String,
+    logs: Arc<GuestLogStore>,
+    memory: Option<Memory>,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    preopen: Option<PathBuf>,
+    files: HashMap<u32, File>,
+    next_fd: u32,
+    clock: VirtualClock,
+    random: HashRandom,
+}
+
+impl WasiState {
+    pub(crate) fn new(function_id: &str, preopen: Option<PathBuf>, logs: Arc<GuestLogStore>) -> Self {
+        Self {
+            function_id: function_id.to_string(),
+            version: String::new(),
+            request_id: String::new(),
+            logs,
+            memory: None,
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            preopen,
+            files: HashMap::new(),
+            next_fd: FIRST_FILE_FD,
+            clock: VirtualClock::new(),
+            random: HashRandom::new(function_id),
+        }
+    }
+
+    fn push_output(&mut self, fd: u32, data: &[u8]) {
+        let buffer = if fd == 1 { &mut self.stdout } else { &mut self.stderr };
+        buffer.extend_from_slice(data);
+
+        let mut lines = Vec::new();
+        loop {
+            let end = match buffer.iter().position(|b| *b == b'\n') {
+                Some(pos) => pos + 1,
+                None if buffer.len() >= MAX_LOG_LINE => MAX_LOG_LINE,
+                None => break,
+            };
+            lines.push(buffer.drain(..end).collect::<Vec<u8>>());
+        }
+        for line in lines {
+            self.log_line(fd, &line);
+        }
+    }
+
+    // 改行を待ってまだログに出していない出力
+    #[cfg(test)]
+    pub(crate) fn buffered_output(&self, fd: u32) -> &[u8] {
+        if fd == 1 { &self.stdout } else { &self.stderr }
+    }
+
+    fn flush_output(&mut self) {
+        for fd in [1, 2] {
+            let buffer = if fd == 1 { &mut self.stdout } else { &mut self.stderr };
+            if !buffer.is_empty() {
+                let line = std::mem::take(buffer);
+                self.log_line(fd, &line);
+            }
+        }
+    }
+
+    // ホスト関数の log と同じトークンバケットを通す。上限を超えた行は捨てる
+    fn log_line(&self, fd: u32, line: &[u8]) {
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim_end_matches(['\r', '\n']);
+        let level = if fd == 1 { "info" } else { "error" };
+        self.logs.record(level, &self.function_id, &self.version, &self.request_id, line.to_string());
+    }
+
+    fn file(&mut self, fd: u32) -> Result<&mut File, Errno> {
+        self.files.get_mut(&fd).ok_or(ERRNO_BADF)
+    }
+
+    // preopen 配下の相対パスを解決する。シンボリックリンクで外に出るパスは拒否する
+    pub(crate) fn resolve(&self, dir_fd: u32, path: &str) -> Result<PathBuf, Errno> {
+        let root = match (&self.preopen, dir_fd) {
+            (Some(root), PREOPEN_FD) => root,
+            _ => return Err(ERRNO_BADF),
+        };
+
+        let mut resolved = root.clone();
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(part) => resolved.push(part),
+                Component::CurDir => {}
+                _ => return Err(ERRNO_NOTCAPABLE),
+            }
+        }
+
+        let resolved = resolved.canonicalize().map_err(|_| ERRNO_NOENT)?;
+        if !resolved.starts_with(root) {
+            return Err(ERRNO_NOTCAPABLE);
+        }
+        Ok(resolved)
+    }
+}
+
+// 実時間は呼び出し開始時刻で固定し、単調時計はインスタンス生成からの経過時間を返す。
+// どちらも 1ms 単位に丸めてタイミング計測に使えないようにする
+struct VirtualClock {
+    started: Instant,
+    realtime_ns: u64,
+}
+
+impl VirtualClock {
+    fn new() -> Self {
+        let mut clock = Self { started: Instant::now(), realtime_ns: 0 };
+        clock.freeze();
+        clock
+    }
+
+    fn freeze(&mut self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        self.realtime_ns = quantize(now);
+    }
+
+    fn now(&self, clock_id: i32) -> u64 {
+        if clock_id == CLOCK_REALTIME {
+            self.realtime_ns
+        } else {
+            quantize(self.started.elapsed().as_nanos() as u64)
+        }
+    }
+}
+
+fn quantize(ns: u64) -> u64 {
+    ns - ns % CLOCK_RESOLUTION_NS
+}
+
+// インスタンスごとにホストの乱数でシードし、SHA-256(seed || counter) を出力する
+struct HashRandom {
+    seed: [u8; 32],
+    counter: u64,
+}
+
+impl HashRandom {
+    fn new(function_id: &str) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(function_id.as_bytes());
+        hasher.update(uuid::Uuid::new_v4().as_bytes());
+        hasher.update(uuid::Uuid::new_v4().as_bytes());
+        Self { seed: hasher.finalize().into(), counter: 0 }
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(32) {
+            let mut hasher = Sha256::new();
+            hasher.update(self.seed);
+            hasher.update(self.counter.to_le_bytes());
+            self.counter += 1;
+            let block = hasher.finalize();
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+    }
+}
+
+pub fn imports_wasi(module: &Module) -> bool {
+    module.imports().any(|import| import.module() == WASI_NAMESPACE)
+}
+
+// モジュールが要求する WASI 関数を import_object に登録する。
+// 未実装の関数は ENOSYS を返すスタブにして、インスタンス化自体は失敗させない
+pub fn define_imports(
+    store: &mut impl AsStoreMut,
+    module: &Module,
+    import_object: &mut Imports,
+    function_id: &str,
+    preopen_dir: Option<&str>,
+    logs: Arc<GuestLogStore>,
+) -> Result<FunctionEnv<WasiState>, String> {
+    let preopen = match preopen_dir {
+        Some(dir) => Some(
+            Path::new(dir).canonicalize()
+                .map_err(|e| format!("WASI preopen directory {} is not accessible: {}", dir, e))?
+        ),
+        None => None,
+    };
+    let env = FunctionEnv::new(store, WasiState::new(function_id, preopen, logs));
+
+    for import in module.imports() {
+        if import.module() != WASI_NAMESPACE {
+            continue;
+        }
+        let function = match host_function(store, &env, import.name()) {
+            Some(function) => function,
+            None => match import.ty() {
+                ExternType::Function(ty) => unsupported(store, &env, ty),
+                _ => return Err(format!("Unsupported WASI import {}", import.name())),
+            },
+        };
+        import_object.define(WASI_NAMESPACE, import.name(), function);
+    }
+
+    Ok(env)
+}
+
+fn host_function(store: &mut impl AsStoreMut, env: &FunctionEnv<WasiState>, name: &str) -> Option<Function> {
+    let function = match name {
+        "args_sizes_get" => Function::new_typed_with_env(store, env, zero_sizes),
+        "args_get" => Function::new_typed_with_env(store, env, empty_list),
+        "environ_sizes_get" => Function::new_typed_with_env(store, env, zero_sizes),
+        "environ_get" => Function::new_typed_with_env(store, env, empty_list),
+        "clock_res_get" => Function::new_typed_with_env(store, env, clock_res_get),
+        "clock_time_get" => Function::new_typed_with_env(store, env, clock_time_get),
+        "random_get" => Function::new_typed_with_env(store, env, random_get),
+        "fd_write" => Function::new_typed_with_env(store, env, fd_write),
+        "fd_read" => Function::new_typed_with_env(store, env, fd_read),
+        "fd_seek" => Function::new_typed_with_env(store, env, fd_seek),
+        "fd_close" => Function::new_typed_with_env(store, env, fd_close),
+        "fd_fdstat_get" => Function::new_typed_with_env(store, env, fd_fdstat_get),
+        "fd_filestat_get" => Function::new_typed_with_env(store, env, fd_filestat_get),
+        "fd_prestat_get" => Function::new_typed_with_env(store, env, fd_prestat_get),
+        "fd_prestat_dir_name" => Function::new_typed_with_env(store, env, fd_prestat_dir_name),
+        "path_open" => Function::new_typed_with_env(store, env, path_open),
+        "path_filestat_get" => Function::new_typed_with_env(store, env, path_filestat_get),
+        "sched_yield" => Function::new_typed_with_env(store, env, sched_yield),
+        "proc_exit" => Function::new_typed_with_env(store, env, proc_exit),
+        _ => return None,
+    };
+    Some(function)
+}
+
+// インポートと同じシグネチャにしないとインスタンス化が IncompatibleType で失敗する
+fn unsupported(store: &mut impl AsStoreMut, env: &FunctionEnv<WasiState>, ty: &FunctionType) -> Function {
+    let returns_errno = ty.results() == [Type::I32];
+    Function::new_with_env(store, env, ty.clone(), move |_env, _args| {
+        if returns_errno {
+            Ok(vec![Value::I32(ERRNO_NOSYS)])
+        } else {
+            Err(RuntimeError::new("Unsupported WASI call"))
+        }
+    })
+}
+
+// インスタンス化後に線形メモリを紐付け、reactor モジュールなら初期化関数を呼ぶ
+pub fn initialize(store: &mut impl AsStoreMut, env: &FunctionEnv<WasiState>, instance: &Instance) -> Result<(), String> {
+    let memory = instance.exports.get_memory("memory")
+        .map_err(|_| "WASI module does not export memory".to_string())?
+        .clone();
+    env.as_mut(store).memory = Some(memory);
+
+    if let Ok(init) = instance.exports.get_typed_function::<(), ()>(&*store, "_initialize") {
+        init.call(store).map_err(|e| format!("WASI _initialize failed: {}", e))?;
+    }
+
+    Ok(())
+}
+
+pub fn begin_invocation(store: &mut impl AsStoreMut, env: &FunctionEnv<WasiState>, metadata: &FunctionMetadata, request_id: &str) {
+    let state = env.as_mut(store);
+    state.version = metadata.version.clone();
+    state.request_id = request_id.to_string();
+    state.clock.freeze();
+}
+
+pub fn end_invocation(store: &mut impl AsStoreMut, env: &FunctionEnv<WasiState>) {
+    env.as_mut(store).flush_output();
+}
+
+// IsolationPolicy::Reset 用。メモリと一緒に開いたファイルも初期状態に戻す
+pub fn reset(store: &mut impl AsStoreMut, env: &FunctionEnv<WasiState>) {
+    let state = env.as_mut(store);
+    state.files.clear();
+    state.next_fd = FIRST_FILE_FD;
+}
+
+fn errno(result: Result<(), Errno>) -> Errno {
+    match result {
+        Ok(()) => ERRNO_SUCCESS,
+        Err(e) => e,
+    }
+}
+
+fn view<'a>(state: &WasiState, store: &'a impl AsStoreRef) -> Result<MemoryView<'a>, Errno> {
+    state.memory.as_ref().map(|memory| memory.view(store)).ok_or(ERRNO_FAULT)
+}
+
+// 確保する前に範囲を確認し、ゲストのメモリより大きなバッファをホストに作らせない
+fn check_bounds(view: &MemoryView, ptr: i32, len: u32) -> Result<(), Errno> {
+    if ptr as u32 as u64 + len as u64 > view.data_size() {
+        return Err(ERRNO_FAULT);
+    }
+    Ok(())
+}
+
+fn read_bytes(view: &MemoryView, ptr: i32, len: u32) -> Result<Vec<u8>, Errno> {
+    check_bounds(view, ptr, len)?;
+    let mut data = vec![0u8; len as usize];
+    view.read(ptr as u32 as u64, &mut data).map_err(|_| ERRNO_FAULT)?;
+    Ok(data)
+}
+
+fn read_path(view: &MemoryView, ptr: i32, len: i32) -> Result<String, Errno> {
+    let len = len.max(0) as u32;
+    if len > MAX_PATH_LEN {
+        return Err(ERRNO_NAMETOOLONG);
+    }
+    String::from_utf8(read_bytes(view, ptr, len)?).map_err(|_| ERRNO_INVAL)
+}
+
+fn write_bytes(view: &MemoryView, ptr: i32, data: &[u8]) -> Result<(), Errno> {
+    view.write(ptr as u32 as u64, data).map_err(|_| ERRNO_FAULT)
+}
+
+fn read_u32(view: &MemoryView, ptr: i32) -> Result<u32, Errno> {
+    let mut bytes = [0u8; 4];
+    view.read(ptr as u32 as u64, &mut bytes).map_err(|_| ERRNO_FAULT)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn write_u32(view: &MemoryView, ptr: i32, value: u32) -> Result<(), Errno> {
+    write_bytes(view, ptr, &value.to_le_bytes())
+}
+
+fn write_u64(view: &MemoryView, ptr: i32, value: u64) -> Result<(), Errno> {
+    write_bytes(view, ptr, &value.to_le_bytes())
+}
+
+// ciovec/iovec の配列 (buf: u32, len: u32) を読む
+fn iovecs(view: &MemoryView, iovs: i32, iovs_len: i32) -> Result<Vec<(i32, u32)>, Errno> {
+    (0..iovs_len.max(0))
+        .map(|i| {
+            let base = iovs.wrapping_add(i * 8);
+            Ok((read_u32(view, base)? as i32, read_u32(view, base.wrapping_add(4))?))
+        })
+        .collect()
+}
+
+fn zero_sizes(mut env: FunctionEnvMut<WasiState>, count_ptr: i32, size_ptr: i32) -> Errno {
+    let (state, store) = env.data_and_store_mut();
+    errno(view(state, &store).and_then(|view| {
+        write_u32(&view, count_ptr, 0)?;
+        write_u32(&view, size_ptr, 0)
+    }))
+}
+
+fn empty_list(_env: FunctionEnvMut<WasiState>, _list_ptr: i32, _buf_ptr: i32) -> Errno {
+    ERRNO_SUCCESS
+}
+
+fn clock_res_get(mut env: FunctionEnvMut<WasiState>, _clock_id: i32, res_ptr: i32) -> Errno {
+    let (state, store) = env.data_and_store_mut();
+    errno(view(state, &store).and_then(|view| write_u64(&view, res_ptr, CLOCK_RESOLUTION_NS)))
+}
+
+fn clock_time_get(mut env: FunctionEnvMut<WasiState>, clock_id: i32, _precision: i64, time_ptr: i32) -> Errno {
+    let (state, store) = env.data_and_store_mut();
+    let now = state.clock.now(clock_id);
+    errno(view(state, &store).and_then(|view| write_u64(&view, time_ptr, now)))
+}
+
+fn random_get(mut env: FunctionEnvMut<WasiState>, buf: i32, len: i32) -> Errno {
+    let (state, store) = env.data_and_store_mut();
+    errno((|| {
+        let view = view(state, &store)?;
+        let len = len.max(0) as u32;
+        check_bounds(&view, buf, len)?;
+
+        let mut chunk = [0u8; RANDOM_CHUNK];
+        let mut offset = 0u32;
+        while offset < len {
+            let n = (len - offset).min(RANDOM_CHUNK as u32) as usize;
+            state.random.fill(&mut chunk[..n]);
+            write_bytes(&view, buf.wrapping_add(offset as i32), &chunk[..n])?;
+            offset += n as u32;
+        }
+        Ok(())
+    })())
+}
+
+fn fd_write(mut env: FunctionEnvMut<WasiState>, fd: i32, iovs: i32, iovs_len: i32, nwritten_ptr: i32) -> Errno {
+    let (state, store) = env.data_and_store_mut();
+    let fd = fd as u32;
+    errno((|| {
+        let view = view(state, &store)?;
+        if fd != 1 && fd != 2 {
+            // 開いたファイルはすべて読み取り専用
+            return Err(if state.files.contains_key(&fd) { ERRNO_ROFS } else { ERRNO_BADF });
+        }
+
+        let mut written = 0u32;
+        for (ptr, len) in iovecs(&view, iovs, iovs_len)? {
+            let len = len.min(MAX_IO_BYTES - written);
+            let data = read_bytes(&view, ptr, len)?;
+            state.push_output(fd, &data);
+            written += len;
+            if written == MAX_IO_BYTES {
+                break;
+            }
+        }
+        write_u32(&view, nwritten_ptr, written)
+    })())
+}
+
+fn fd_read(mut env: FunctionEnvMut<WasiState>, fd: i32, iovs: i32, iovs_len: i32, nread_ptr: i32) -> Errno {
+    let (state, store) = env.data_and_store_mut();
+    let fd = fd as u32;
+    errno((|| {
+        let view = view(state, &store)?;
+        // 標準入力は常に EOF
+        if fd == 0 {
+            return write_u32(&view, nread_ptr, 0);
+        }
+
+        let file = state.file(fd)?;
+        let mut total = 0u32;
+        for (ptr, len) in iovecs(&view, iovs, iovs_len)? {
+            let len = len.min(MAX_IO_BYTES - total);
+            check_bounds(&view, ptr, len)?;
+            let mut data = vec![0u8; len as usize];
+            let n = file.read(&mut data).map_err(|_| ERRNO_IO)?;
+            write_bytes(&view, ptr, &data[..n])?;
+            total += n as u32;
+            if n < len as usize || total == MAX_IO_BYTES {
+                break;
+            }
+        }
+        write_u32(&view, nread_ptr, total)
+    })())
+}
+
+fn fd_seek(mut env: FunctionEnvMut<WasiState>, fd: i32, offset: i64, whence: i32, newoffset_ptr: i32) -> Errno {
+    let (state, store) = env.data_and_store_mut();
+    errno((|| {
+        let view = view(state, &store)?;
+        let pos = match whence {
+            0 => SeekFrom::Start(u64::try_from(offset).map_err(|_| ERRNO_INVAL)?),
+            1 => SeekFrom::Current(offset),
+            2 => SeekFrom::End(offset),
+            _ => return Err(ERRNO_INVAL),
+        };
+        let new_offset = state.file(fd as u32)?.seek(pos).map_err(|_| ERRNO_INVAL)?;
+        write_u64(&view, newoffset_ptr, new_offset)
+    })())
+}
+
+fn fd_close(mut env: FunctionEnvMut<WasiState>, fd: i32) -> Errno {
+    match env.data_mut().files.remove(&(fd as u32)) {
+        Some(_) => ERRNO_SUCCESS,
+        None => ERRNO_BADF,
+    }
+}
+
+fn fd_fdstat_get(mut env: FunctionEnvMut<WasiState>, fd: i32, stat_ptr: i32) -> Errno {
+    let (state, store) = env.data_and_store_mut();
+    let fd = fd as u32;
+    errno((|| {
+        let view = view(state, &store)?;
+        let (filetype, rights) = match fd {
+            0 => (FILETYPE_CHARACTER_DEVICE, RIGHTS_FD_READ),
+            1 | 2 => (FILETYPE_CHARACTER_DEVICE, RIGHTS_FD_WRITE),
+            PREOPEN_FD if state.preopen.is_some() => (FILETYPE_DIRECTORY, RIGHTS_DIR_READ),
+            _ if state.files.contains_key(&fd) => (FILETYPE_REGULAR_FILE, RIGHTS_FILE_READ),
+            _ => return Err(ERRNO_BADF),
+        };
+
+        // fdstat: filetype u8, flags u16, rights_base u64, rights_inheriting u64
+        let mut stat = [0u8; 24];
+        stat[0] = filetype;
+        stat[8..16].copy_from_slice(&rights.to_le_bytes());
+        if filetype == FILETYPE_DIRECTORY {
+            stat[16..24].copy_from_slice(&RIGHTS_FILE_READ.to_le_bytes());
+        }
+        write_bytes(&view, stat_ptr, &stat)
+    })())
+}
+
+// filestat: dev u64, ino u64, filetype u8, nlink u64, size u64, atim u64, mtim u64, ctim u64
+fn write_filestat(view: &MemoryView, ptr: i32, filetype: u8, metadata: Option<&std::fs::Metadata>) -> Result<(), Errno> {
+    let mut stat = [0u8; 64];
+    stat[16] = filetype;
+    if let Some(metadata) = metadata {
+        let mtime = metadata.modified().ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        stat[24..32].copy_from_slice(&1u64.to_le_bytes());
+        stat[32..40].copy_from_slice(&metadata.len().to_le_bytes());
+        for offset in [40, 48, 56] {
+            stat[offset..offset + 8].copy_from_slice(&mtime.to_le_bytes());
+        }
+    }
+    write_bytes(view, ptr, &stat)
+}
+
+fn fd_filestat_get(mut env: FunctionEnvMut<WasiState>, fd: i32, stat_ptr: i32) -> Errno {
+    let (state, store) = env.data_and_store_mut();
+    let fd = fd as u32;
+    errno((|| {
+        let view = view(state, &store)?;
+        match fd {
+            0..=2 => write_filestat(&view, stat_ptr, FILETYPE_CHARACTER_DEVICE, None),
+            PREOPEN_FD if state.preopen.is_some() => write_filestat(&view, stat_ptr, FILETYPE_DIRECTORY, None),
+            _ => {
+                let metadata = state.file(fd)?.metadata().map_err(|_| ERRNO_IO)?;
+                write_filestat(&view, stat_ptr, FILETYPE_REGULAR_FILE, Some(&metadata))
+            }
+        }
+    })())
+}
+
+fn fd_prestat_get(mut env: FunctionEnvMut<WasiState>, fd: i32, prestat_ptr: i32) -> Errno {
+    let (state, store) = env.data_and_store_mut();
+    errno((|| {
+        let view = view(state, &store)?;
+        if fd as u32 != PREOPEN_FD || state.preopen.is_none() {
+            return Err(ERRNO_BADF);
+        }
+        // prestat: tag u8 (0 = dir), name_len u32
+        write_u32(&view, prestat_ptr, 0)?;
+        write_u32(&view, prestat_ptr.wrapping_add(4), PREOPEN_GUEST_PATH.len() as u32)
+    })())
+}
+
+fn fd_prestat_dir_name(mut env: FunctionEnvMut<WasiState>, fd: i32, path_ptr: i32, path_len: i32) -> Errno {
+    let (state, store) = env.data_and_store_mut();
+    errno((|| {
+        let view = view(state, &store)?;
+        if fd as u32 != PREOPEN_FD || state.preopen.is_none() {
+            return Err(ERRNO_BADF);
+        }
+        let name = PREOPEN_GUEST_PATH.as_bytes();
+        write_bytes(&view, path_ptr, &name[..name.len().min(path_len.max(0) as usize)])
+    })())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn path_open(
+    mut env: FunctionEnvMut<WasiState>,
+    dir_fd: i32,
+    _dirflags: i32,
+    path_ptr: i32,
+    path_len: i32,
+    oflags: i32,
+    rights_base: i64,
+    _rights_inheriting: i64,
+    _fdflags: i32,
+    fd_ptr: i32,
+) -> Errno {
+    let (state, store) = env.data_and_store_mut();
+    errno((|| {
+        let view = view(state, &store)?;
+        if oflags & OFLAGS_WRITE != 0 || rights_base as u64 & RIGHTS_FD_WRITE != 0 {
+            return Err(ERRNO_ROFS);
+        }
+
+        let path = read_path(&view, path_ptr, path_len)?;
+        let resolved = state.resolve(dir_fd as u32, &path)?;
+
+        // ディレクトリの列挙はサポートしない
+        if oflags & OFLAGS_DIRECTORY != 0 || resolved.is_dir() {
+            return Err(ERRNO_NOTSUP);
+        }
+        if state.files.len() >= MAX_OPEN_FILES {
+            return Err(ERRNO_MFILE);
+        }
+
+        let file = File::open(&resolved).map_err(|_| ERRNO_NOENT)?;
+        let fd = state.next_fd;
+        state.next_fd += 1;
+        state.files.insert(fd, file);
+        write_u32(&view, fd_ptr, fd)
+    })())
+}
+
+fn path_filestat_get(mut env: FunctionEnvMut<WasiState>, dir_fd: i32, _flags: i32, path_ptr: i32, path_len: i32, stat_ptr: i32) -> Errno {
+    let (state, store) = env.data_and_store_mut();
+    errno((|| {
+        let view = view(state, &store)?;
+        let path = read_path(&view, path_ptr, path_len)?;
+        let resolved = state.resolve(dir_fd as u32, &path)?;
+
+        let metadata = std::fs::metadata(&resolved).map_err(|_| ERRNO_NOENT)?;
+        let filetype = if metadata.is_dir() { FILETYPE_DIRECTORY } else { FILETYPE_REGULAR_FILE };
+        write_filestat(&view, stat_ptr, filetype, Some(&metadata))
+    })())
+}
+
+fn sched_yield(_env: FunctionEnvMut<WasiState>) -> Errno {
+    ERRNO_SUCCESS
+}
+
+fn proc_exit(_env: FunctionEnvMut<WasiState>, code: i32) -> Result<(), RuntimeError> {
+    Err(RuntimeError::new(format!("Guest called proc_exit({})", code)))
+}
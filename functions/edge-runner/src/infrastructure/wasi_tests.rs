@@ -0,0 +1,144 @@
+#[cfg(test)]
+mod tests {
+    use crate::infrastructure::guest_log::GuestLogStore;
+    use crate::infrastructure::wasi::*;
+    use crate::test_support;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use wasmer::{FunctionEnv, Imports, Instance, Module, Store};
+
+    // fd_write と、ランナーが実装していない WASI 関数をインポートするモジュール
+    const GUEST: &str = r#"
+        (module
+          (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+          (import "wasi_snapshot_preview1" "poll_oneoff" (func $poll_oneoff (param i32 i32 i32 i32) (result i32)))
+          (import "wasi_snapshot_preview1" "sock_shutdown" (func $sock_shutdown (param i32 i32) (result i32)))
+          (import "wasi_snapshot_preview1" "random_get" (func $random_get (param i32 i32) (result i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 64) "hello\nwor")
+          ;; iovec (buf, len) を 0 に置き、書き込んだバイト数を 8 に受け取る
+          (func (export "write") (param $len i32) (result i32)
+            (i32.store (i32.const 0) (i32.const 64))
+            (i32.store (i32.const 4) (local.get $len))
+            (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))
+          (func (export "written") (result i32)
+            (i32.load (i32.const 8)))
+          (func (export "poll") (result i32)
+            (call $poll_oneoff (i32.const 0) (i32.const 0) (i32.const 0) (i32.const 0)))
+          (func (export "random") (param $ptr i32) (param $len i32) (result i32)
+            (call $random_get (local.get $ptr) (local.get $len))))
+    "#;
+
+    fn logs() -> Arc<GuestLogStore> {
+        Arc::new(GuestLogStore::new("node-1".to_string(), 100, 10, 10))
+    }
+
+    fn instantiate() -> (Store, FunctionEnv<WasiState>, Instance) {
+        instantiate_with_logs(logs())
+    }
+
+    fn instantiate_with_logs(logs: Arc<GuestLogStore>) -> (Store, FunctionEnv<WasiState>, Instance) {
+        let mut store = Store::default();
+        let module = Module::new(&store, GUEST).unwrap();
+        let mut imports = Imports::new();
+        let env = define_imports(&mut store, &module, &mut imports, "fn-1", None, logs).unwrap();
+        let instance = Instance::new(&mut store, &module, &imports).unwrap();
+        initialize(&mut store, &env, &instance).unwrap();
+        (store, env, instance)
+    }
+
+    fn preopen_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wasi-test-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::write(dir.join("nested").join("data.txt"), b"hello").unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    #[test]
+    fn test_resolve_inside_preopen() {
+        let root = preopen_dir("inside");
+        let state = WasiState::new("fn-1", Some(root.clone()), logs());
+
+        let resolved = state.resolve(PREOPEN_FD, "./nested/data.txt").unwrap();
+        assert_eq!(resolved, root.join("nested").join("data.txt"));
+        assert_eq!(state.resolve(PREOPEN_FD, "missing.txt"), Err(ERRNO_NOENT));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_rejects_escape() {
+        let root = preopen_dir("escape");
+        let state = WasiState::new("fn-1", Some(root.clone()), logs());
+
+        assert_eq!(state.resolve(PREOPEN_FD, "../etc/passwd"), Err(ERRNO_NOTCAPABLE));
+        assert_eq!(state.resolve(PREOPEN_FD, "nested/../../x"), Err(ERRNO_NOTCAPABLE));
+        assert_eq!(state.resolve(PREOPEN_FD, "/etc/passwd"), Err(ERRNO_NOTCAPABLE));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_no_filesystem_without_preopen() {
+        let state = WasiState::new("fn-1", None, logs());
+        assert_eq!(state.resolve(PREOPEN_FD, "data.txt"), Err(ERRNO_BADF));
+    }
+
+    #[test]
+    fn test_unsupported_imports_return_enosys() {
+        let (mut store, _env, instance) = instantiate();
+
+        let poll = instance.exports.get_typed_function::<(), i32>(&store, "poll").unwrap();
+        assert_eq!(poll.call(&mut store).unwrap(), 52);
+    }
+
+    #[test]
+    fn test_fd_write_buffers_until_newline() {
+        let (mut store, env, instance) = instantiate();
+        let write = instance.exports.get_typed_function::<i32, i32>(&store, "write").unwrap();
+        let written = instance.exports.get_typed_function::<(), i32>(&store, "written").unwrap();
+
+        assert_eq!(write.call(&mut store, 9).unwrap(), 0);
+        assert_eq!(written.call(&mut store).unwrap(), 9);
+        // 改行までの "hello" はログに出て、残りはバッファに残る
+        assert_eq!(env.as_ref(&store).buffered_output(1), b"wor");
+
+        end_invocation(&mut store, &env);
+        assert!(env.as_ref(&store).buffered_output(1).is_empty());
+    }
+
+    #[test]
+    fn test_out_of_bounds_lengths_fault_without_allocating() {
+        let (mut store, env, instance) = instantiate();
+        let write = instance.exports.get_typed_function::<i32, i32>(&store, "write").unwrap();
+        let random = instance.exports.get_typed_function::<(i32, i32), i32>(&store, "random").unwrap();
+
+        // 1 ページ (64KiB) のメモリを超える長さは EFAULT
+        assert_eq!(write.call(&mut store, i32::MAX).unwrap(), 21);
+        assert_eq!(random.call(&mut store, 0, i32::MAX).unwrap(), 21);
+        assert!(env.as_ref(&store).buffered_output(1).is_empty());
+
+        assert_eq!(random.call(&mut store, 0, 65536).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_output_goes_through_rate_limited_guest_log() {
+        // 補充なしで 2 行まで
+        let logs = Arc::new(GuestLogStore::new("node-1".to_string(), 100, 0, 2));
+        let (mut store, env, instance) = instantiate_with_logs(logs.clone());
+        let write = instance.exports.get_typed_function::<i32, i32>(&store, "write").unwrap();
+        begin_invocation(&mut store, &env, &test_support::metadata("fn-1", "2.0.0"), "req-1");
+
+        for _ in 0..3 {
+            assert_eq!(write.call(&mut store, 9).unwrap(), 0);
+        }
+        end_invocation(&mut store, &env);
+
+        let records = logs.recent("fn-1", 10);
+        let messages: Vec<_> = records.iter().map(|r| r.message.as_str()).collect();
+        assert_eq!(messages, vec!["worhello", "hello"]);
+        assert_eq!(records[0].level, "info");
+        assert_eq!(records[0].version, "2.0.0");
+        assert_eq!(records[0].request_id, "req-1");
+    }
+}
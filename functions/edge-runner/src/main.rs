@@ -2,8 +2,10 @@ mod domain;
 mod application;
 mod infrastructure;
 mod presentation;
+#[cfg(test)]
+mod test_support;
 
-use axum::{Router, routing::{any, get}, extract::State, http::Request, body::Body, response::IntoResponse};
+use axum::{Router, routing::{any, get, put}, extract::State, http::Request, body::Body, response::IntoResponse};
 use std::sync::Arc;
 use uuid::Uuid;
 use std::time::Duration;
@@ -11,8 +13,13 @@ use std::time::Duration;
 use domain::NodeInfo;
 use infrastructure::{
     InMemoryFunctionRepository, InMemoryRouteRepository, InMemoryCacheRepository,
-    HotInstancePool, ControlPlaneClient, LocalWasmCache,
+    HotInstancePool, ControlPlaneClient, LocalWasmCache, ArtifactLoader, WasmExecutor,
 };
+use infrastructure::host::HostServices;
+use infrastructure::guest_log::GuestLogStore;
+use infrastructure::http_fetch::HttpFetcher;
+use infrastructure::kv::{KvQuota, KvStore};
+use infrastructure::telemetry::TelemetryBridge;
 use application::{FunctionService, HeartbeatService, InvocationService};
 use presentation::HttpHandler;
 
@@ -24,10 +31,15 @@ struct AppState {
 
 #[tokio::main]
 async fn main() {
-    let wasm_path = std::env::args().nth(1).expect("Usage: edge-runner <wasm_file>");
-    let cp_url = std::env::args().nth(2).unwrap_or_else(|| "http://localhost:8080".to_string());
-    
-    let wasm_bytes = std::fs::read(&wasm_path).expect("Failed to read WASM file");
+    // Functions are loaded from artifacts deployed through the control plane.
+    // The old `edge-runner <wasm_file> [control_plane_url]` form is still accepted; the file is ignored
+    let mut args = std::env::args().skip(1);
+    let mut cp_url = args.next();
+    if let Some(wasm_path) = cp_url.as_ref().filter(|arg| !arg.contains("://")) {
+        eprintln!("Ignoring WASM file argument {}: functions are deployed through the control plane", wasm_path);
+        cp_url = args.next();
+    }
+    let cp_url = cp_url.unwrap_or_else(|| "http://localhost:8080".to_string());
     
     let node_id = Uuid::new_v4().to_string();
     let pop_id = "default-pop".to_string();
@@ -38,16 +50,57 @@ async fn main() {
     };
     
     // Initialize repositories
-    let function_repo = Arc::new(InMemoryFunctionRepository::new());
+    // Keep up to 3 versions per function for rollback
+    let function_repo = Arc::new(InMemoryFunctionRepository::new(3));
     let route_repo = Arc::new(InMemoryRouteRepository::new());
     let cache_repo = Arc::new(InMemoryCacheRepository::new());
     
-    // Initialize cache (10 GB limit)
+    // Initialize cache (10 GB limit, fsync artifacts so they survive power loss)
     let wasm_cache = Arc::new(LocalWasmCache::new("/var/cache/wasm", 10 * 1024 * 1024 * 1024)
-        .unwrap_or_else(|_| LocalWasmCache::new("/tmp/wasm-cache", 10 * 1024 * 1024 * 1024).unwrap()));
+        .unwrap_or_else(|_| LocalWasmCache::new("/tmp/wasm-cache", 10 * 1024 * 1024 * 1024).unwrap())
+        .with_fsync(true));
+    // Downloads missing artifacts, one request per function version at a time
+    let artifacts = Arc::new(ArtifactLoader::new(wasm_cache.clone()));
+    
+    // Initialize KV store (namespaced by function_id)
+    let kv = Arc::new(KvStore::open("/var/lib/edge-runner/kv.sqlite3", KvQuota::default())
+        .unwrap_or_else(|_| KvStore::open("/tmp/edge-runner-kv.sqlite3", KvQuota::default()).unwrap()));
     
-    // Initialize pool
-    let pool = Arc::new(HotInstancePool::new(10, 300));
+    // Initialize guest logs (keep 1000 records per function, 100 records/s with bursts of 200)
+    let guest_logs = Arc::new(GuestLogStore::new(node_id.clone(), 1000, 100, 200));
+    
+    // Bridge to the edge-agent telemetry database on the same device (optional)
+    let telemetry = std::env::var("EDGE_AGENT_DB_PATH").ok().and_then(|path| {
+        match TelemetryBridge::open(&path, std::env::var("DEVICE_ID").ok()) {
+            Ok(bridge) => Some(Arc::new(bridge)),
+            Err(e) => {
+                eprintln!("Telemetry bridge disabled: {}", e);
+                None
+            }
+        }
+    });
+    
+    // Initialize host functions exposed to guests
+    let host_services = Arc::new(HostServices {
+        fetcher: HttpFetcher::new(tokio::runtime::Handle::current()),
+        kv: kv.clone(),
+        logs: guest_logs.clone(),
+        telemetry,
+    });
+    
+    // Initialize pool (10 instances, 300s idle timeout, 5s queue wait,
+    // compiled modules stored next to the cached artifacts)
+    let pool = Arc::new(HotInstancePool::new(
+        10,
+        300,
+        5000,
+        Some(wasm_cache.cache_dir().to_path_buf()),
+        host_services,
+    ));
+    
+    // Initialize WASM workers (one per core, bounded queue)
+    let workers = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4);
+    let executor = Arc::new(WasmExecutor::new(workers, 256));
     
     // Initialize services
     let function_service = Arc::new(FunctionService::new(
@@ -62,14 +115,17 @@ async fn main() {
         cp_client,
         function_service.clone(),
         cache_repo,
-        wasm_cache.clone(),
+        artifacts.clone(),
+        pool.clone(),
+        executor.clone(),
+        kv,
     ));
     
     let invocation_service = Arc::new(InvocationService::new(
-        function_service,
-        pool,
-        wasm_bytes,
-        wasm_cache,
+        function_service.clone(),
+        pool.clone(),
+        executor,
+        artifacts,
     ));
     
     let http_handler = Arc::new(HttpHandler::new(invocation_service));
@@ -82,6 +138,9 @@ async fn main() {
     
     let state_arc = Arc::new(state);
     
+    // Report artifacts restored from the on-disk cache in the first heartbeat
+    state_arc.heartbeat_service.restore_cached().await;
+    
     // Start heartbeat task
     let heartbeat_state = state_arc.clone();
     let node_info_clone = node_info.clone();
@@ -90,20 +149,49 @@ async fn main() {
         loop {
             interval.tick().await;
             match heartbeat_state.heartbeat_service.send_heartbeat(&node_info_clone).await {
-                Ok((deployments, routes)) => {
-                    heartbeat_state.heartbeat_service.handle_deployments(deployments).await;
-                    heartbeat_state.heartbeat_service.handle_routes(routes).await;
+                Ok(response) => {
+                    heartbeat_state.heartbeat_service.handle_deployments(response.deployments).await;
+                    heartbeat_state.heartbeat_service.handle_function_commands(response.function_commands).await;
+                    heartbeat_state.heartbeat_service.reconcile(response.revision, response.functions, response.routes).await;
+                    heartbeat_state.heartbeat_service.handle_kv_commands(response.kv_commands).await;
                 }
                 Err(e) => eprintln!("Heartbeat error: {}", e),
             }
         }
     });
     
+    // Start idle instance reaper
+    let reaper_pool = pool.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(30));
+        loop {
+            interval.tick().await;
+            let reaped = reaper_pool.reap_idle();
+            if reaped > 0 {
+                println!("Reaped {} idle instance(s)", reaped);
+            }
+        }
+    });
+    
     let app = Router::new()
         .route("/metrics", get(presentation::metrics_handler))
         .route("/*path", any(handler))
         .with_state(state_arc);
     
+    // Admin endpoints are only reachable from the node itself
+    let admin_app = Router::new()
+        .route("/admin/functions/:function_id/logs", get(presentation::admin::guest_logs_handler))
+        .with_state(guest_logs)
+        .merge(Router::new()
+            .route("/admin/routes", get(presentation::admin::list_routes_handler))
+            .route("/admin/routes/:id", put(presentation::admin::put_route_handler).delete(presentation::admin::delete_route_handler))
+            .with_state(function_service));
+    let admin_listener = tokio::net::TcpListener::bind("127.0.0.1:3001").await.unwrap();
+    println!("Edge Runner admin listening on http://127.0.0.1:3001");
+    tokio::spawn(async move {
+        axum::serve(admin_listener, admin_app).await.unwrap();
+    });
+    
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
     println!("Edge Runner listening on http://0.0.0.0:3000 (node_id: {})", node_id);
     axum::serve(listener, app).await.unwrap();
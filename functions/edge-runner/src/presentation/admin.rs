@@ -0,0 +1,51 @@
+use axum::{extract::{Path, Query, State}, http::StatusCode, response::IntoResponse, Json};
+use serde::Deserialize;
+use std::sync::Arc;
+use crate::application::FunctionService;
+use crate::presentation::handlers::error_response;
+use crate::domain::Route;
+use crate::infrastructure::guest_log::GuestLogStore;
+
+const DEFAULT_LOG_LIMIT: usize = 100;
+
+#[derive(Deserialize)]
+pub struct LogQuery {
+    limit: Option<usize>,
+}
+
+// デバッグ用。ローカルの管理ポートでのみ公開する
+pub async fn guest_logs_handler(
+    State(logs): State<Arc<GuestLogStore>>,
+    Path(function_id): Path<String>,
+    Query(query): Query<LogQuery>,
+) -> impl IntoResponse {
+    Json(logs.recent(&function_id, query.limit.unwrap_or(DEFAULT_LOG_LIMIT)))
+}
+
+// マッチングの評価順に返す
+pub async fn list_routes_handler(State(functions): State<Arc<FunctionService>>) -> impl IntoResponse {
+    Json(functions.list_routes().await)
+}
+
+// 次のハートビートでルートが同期されると上書きされる
+pub async fn put_route_handler(
+    State(functions): State<Arc<FunctionService>>,
+    Path(id): Path<String>,
+    Json(mut route): Json<Route>,
+) -> impl IntoResponse {
+    route.id = id;
+    match functions.upsert_route(route).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => error_response(StatusCode::CONFLICT, "route_conflict", e),
+    }
+}
+
+pub async fn delete_route_handler(
+    State(functions): State<Arc<FunctionService>>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match functions.remove_route(&id).await {
+        Some(_) => StatusCode::NO_CONTENT.into_response(),
+        None => error_response(StatusCode::NOT_FOUND, "route_not_found", format!("Route {} not found", id)),
+    }
+}
@@ -0,0 +1,101 @@
+#[cfg(test)]
+mod tests {
+    use crate::application::FunctionService;
+    use crate::domain::Route;
+    use crate::infrastructure::guest_log::GuestLogStore;
+    use crate::presentation::admin::*;
+    use crate::test_support::TestNode;
+    use axum::{extract::{Path, Query, State}, http::StatusCode, response::{IntoResponse, Response}, Json};
+    use std::sync::Arc;
+
+    fn function_service() -> Arc<FunctionService> {
+        TestNode::new().function_service
+    }
+
+    // 管理 API に送られる JSON 本文と同じ形で組み立てる
+    fn body(path: &str, methods: &[&str]) -> Json<Route> {
+        Json(serde_json::from_value(serde_json::json!({
+            "id": "ignored",
+            "host": "*",
+            "path": path,
+            "function_id": "func1",
+            "methods": methods,
+            "priority": 100,
+        })).unwrap())
+    }
+
+    async fn put(functions: &Arc<FunctionService>, id: &str, path: &str, methods: &[&str]) -> Response {
+        put_route_handler(State(functions.clone()), Path(id.to_string()), body(path, methods)).await.into_response()
+    }
+
+    async fn delete(functions: &Arc<FunctionService>, id: &str) -> Response {
+        delete_route_handler(State(functions.clone()), Path(id.to_string())).await.into_response()
+    }
+
+    async fn json(response: Response) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_put_and_list_routes() {
+        let functions = function_service();
+
+        assert_eq!(put(&functions, "users", "/users/:id", &["GET"]).await.status(), StatusCode::NO_CONTENT);
+        assert_eq!(put(&functions, "health", "/health", &["GET"]).await.status(), StatusCode::NO_CONTENT);
+        // 同じ id への PUT は置き換え
+        assert_eq!(put(&functions, "users", "/users/:id", &["GET", "POST"]).await.status(), StatusCode::NO_CONTENT);
+
+        let routes = json(list_routes_handler(State(functions.clone())).await.into_response()).await;
+        let mut ids: Vec<_> = routes.as_array().unwrap().iter().map(|r| r["id"].as_str().unwrap().to_string()).collect();
+        ids.sort();
+        // 本文の id ではなくパスの id で登録される
+        assert_eq!(ids, vec!["health", "users"]);
+        let users = routes.as_array().unwrap().iter().find(|r| r["id"] == "users").unwrap();
+        assert_eq!(users["methods"], serde_json::json!(["GET", "POST"]));
+    }
+
+    #[tokio::test]
+    async fn test_conflicting_route_returns_409() {
+        let functions = function_service();
+        put(&functions, "users", "/users/:id", &["GET"]).await;
+
+        let response = put(&functions, "users-by-uid", "/users/:uid", &["GET"]).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(json(response).await["error"], "route_conflict");
+
+        // メソッドが重ならなければ登録できる
+        assert_eq!(put(&functions, "users-post", "/users/:uid", &["POST"]).await.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_delete_route() {
+        let functions = function_service();
+        put(&functions, "users", "/users/:id", &["GET"]).await;
+
+        assert_eq!(delete(&functions, "users").await.status(), StatusCode::NO_CONTENT);
+        assert!(functions.list_routes().await.is_empty());
+
+        let missing = delete(&functions, "users").await;
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+        assert_eq!(json(missing).await["error"], "route_not_found");
+    }
+
+    #[tokio::test]
+    async fn test_guest_logs_handler() {
+        let logs = Arc::new(GuestLogStore::new("node-1".to_string(), 100, 10, 10));
+        for i in 0..3 {
+            logs.record("info", "func1", "1.0.0", "req", format!("line {}", i));
+        }
+        logs.record("info", "func2", "1.0.0", "req", "other".to_string());
+
+        let response = guest_logs_handler(
+            State(logs),
+            Path("func1".to_string()),
+            Query(serde_json::from_value(serde_json::json!({ "limit": 2 })).unwrap()),
+        ).await.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        let records = json(response).await;
+        assert_eq!(records.as_array().unwrap().len(), 2);
+    }
+}
@@ -1,9 +1,15 @@
-use axum::{http::{Request, StatusCode}, body::Body, response::IntoResponse};
+use axum::{http::{header, HeaderValue, Request, StatusCode}, body::Body, response::{IntoResponse, Response}, Json};
 use std::sync::Arc;
 use crate::application::InvocationService;
+use crate::application::dto::{InvocationRequest, InvocationResponse};
+use crate::domain::InvocationError;
 use crate::infrastructure::INVOKE_COUNT;
 use prometheus::Encoder;
 
+const MAX_BODY_BYTES: usize = 10 * 1024 * 1024;
+// アーティファクトの取得中に返す 503 の Retry-After (秒)
+const LOADING_RETRY_AFTER_SECS: &str = "1";
+
 pub struct HttpHandler {
     invocation_service: Arc<InvocationService>,
 }
@@ -14,40 +20,122 @@ impl HttpHandler {
     }
     
     pub async fn handle_request(&self, req: Request<Body>) -> impl IntoResponse {
+        let request_id = uuid::Uuid::new_v4().to_string();
+        let mut response = self.invoke(req, request_id.clone()).await;
+        
+        // ゲストのログと突き合わせられるようにリクエスト ID を返す
+        if let Ok(value) = HeaderValue::from_str(&request_id) {
+            response.headers_mut().insert("x-request-id", value);
+        }
+        response
+    }
+    
+    async fn invoke(&self, req: Request<Body>, request_id: String) -> Response {
         let start = std::time::Instant::now();
         INVOKE_COUNT.inc();
         
-        let method = req.method().as_str().to_string();
-        let path = req.uri().path().to_string();
-        let host = req.headers()
+        let (parts, body) = req.into_parts();
+        let method = parts.method.as_str().to_string();
+        let path = parts.uri.path().to_string();
+        let query = parts.uri.query().map(|q| q.to_string());
+        let host = parts.headers
             .get("host")
             .and_then(|h| h.to_str().ok())
             .unwrap_or("localhost")
             .to_string();
+        let headers = parts.headers
+            .iter()
+            .map(|(name, value)| (name.as_str().to_string(), String::from_utf8_lossy(value.as_bytes()).into_owned()))
+            .collect();
+        
+        let body = match axum::body::to_bytes(body, MAX_BODY_BYTES).await {
+            Ok(bytes) => bytes.to_vec(),
+            Err(e) => {
+                crate::infrastructure::INVOKE_ERRORS.inc();
+                return error_response(StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large", format!("Failed to read request body: {}", e));
+            }
+        };
+        
+        let request = InvocationRequest {
+            request_id,
+            method,
+            path: path.clone(),
+            query,
+            host,
+            headers,
+            body,
+        };
         
-        match self.invocation_service.invoke(&host, &path, &method).await {
+        match self.invocation_service.invoke(request).await {
             Ok(response) => {
                 crate::infrastructure::INVOKE_LATENCY.with_label_values(&[&path]).observe(start.elapsed().as_secs_f64());
-                (StatusCode::OK, response).into_response()
+                into_http_response(response)
             }
             Err(e) => {
                 crate::infrastructure::INVOKE_ERRORS.inc();
                 crate::infrastructure::INVOKE_LATENCY.with_label_values(&[&path]).observe(start.elapsed().as_secs_f64());
                 
-                let status = if e.contains("Route not found") {
-                    StatusCode::NOT_FOUND
-                } else if e.contains("method not allowed") {
-                    StatusCode::METHOD_NOT_ALLOWED
-                } else {
-                    StatusCode::INTERNAL_SERVER_ERROR
-                };
+                match &e {
+                    InvocationError::Timeout { .. } => crate::infrastructure::INVOKE_TIMEOUTS.inc(),
+                    InvocationError::Throttled(_) => crate::infrastructure::INVOKE_THROTTLED.inc(),
+                    _ => {}
+                }
                 
-                (status, e).into_response()
+                let mut response = error_response(status_of(&e), e.kind(), e.to_string());
+                match &e {
+                    InvocationError::MethodNotAllowed { allowed } => {
+                        if let Ok(value) = HeaderValue::from_str(&allowed.join(", ")) {
+                            response.headers_mut().insert(header::ALLOW, value);
+                        }
+                    }
+                    InvocationError::Loading(_) => {
+                        response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from_static(LOADING_RETRY_AFTER_SECS));
+                    }
+                    _ => {}
+                }
+                response
             }
         }
     }
 }
 
+fn into_http_response(response: InvocationResponse) -> Response {
+    let mut builder = Response::builder().status(response.status_code);
+    for (name, value) in &response.headers {
+        builder = builder.header(name.as_str(), value.as_str());
+    }
+    
+    builder.body(Body::from(response.body)).unwrap_or_else(|e| {
+        crate::infrastructure::INVOKE_ERRORS.inc();
+        let e = InvocationError::InvalidResponse(e.to_string());
+        error_response(status_of(&e), e.kind(), e.to_string())
+    })
+}
+
+pub(crate) fn status_of(e: &InvocationError) -> StatusCode {
+    match e {
+        InvocationError::RouteNotFound | InvocationError::FunctionNotFound(_) => StatusCode::NOT_FOUND,
+        InvocationError::MethodNotAllowed { .. } => StatusCode::METHOD_NOT_ALLOWED,
+        InvocationError::Throttled(_) => StatusCode::TOO_MANY_REQUESTS,
+        InvocationError::Overloaded(_) | InvocationError::Loading(_) => StatusCode::SERVICE_UNAVAILABLE,
+        InvocationError::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+        InvocationError::InvalidResponse(_)
+        | InvocationError::Download(_)
+        | InvocationError::ArtifactTooLarge { .. }
+        | InvocationError::ControlPlane(_) => StatusCode::BAD_GATEWAY,
+        InvocationError::Instantiation(_)
+        | InvocationError::Execution(_)
+        | InvocationError::ChecksumMismatch { .. }
+        | InvocationError::Cache(_)
+        | InvocationError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+// ランナー自身が返すエラーは {"error": "<種別>", "message": "..."} に揃える
+pub fn error_response(status: StatusCode, error: &str, message: String) -> Response {
+    (status, Json(serde_json::json!({ "error": error, "message": message }))).into_response()
+}
+
 pub async fn metrics_handler() -> impl IntoResponse {
     let encoder = prometheus::TextEncoder::new();
     let metric_families = prometheus::gather();
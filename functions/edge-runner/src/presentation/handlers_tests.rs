@@ -0,0 +1,158 @@
+#[cfg(test)]
+mod tests {
+    use crate::domain::*;
+    use crate::infrastructure::*;
+    use crate::presentation::{status_of, HttpHandler};
+    use crate::test_support::{self, TestNode};
+    use axum::{http::{Request, StatusCode}, body::Body, routing::{any, get}, Router};
+    use sha2::{Digest, Sha256};
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    // ヘッダーと本文を持つレスポンスエンベロープを返すゲスト
+    const GUEST: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (data (i32.const 0) "201\0d\0aContent-Type: text/plain\0d\0a\0d\0ahi")
+          (global $next (mut i32) (i32.const 1024))
+          (func (export "alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $ptr))
+          (func (export "dealloc") (param i32 i32))
+          (func (export "handle") (param i32 i32 i32 i32 i32 i32 i32 i32) (result i64)
+            (i64.const 35)))
+    "#;
+
+    fn route(id: &str, path: &str, function_id: &str, methods: &[&str]) -> Route {
+        Route {
+            id: id.to_string(),
+            host: "*".to_string(),
+            path: path.to_string(),
+            function_id: function_id.to_string(),
+            methods: methods.iter().map(|m| m.to_string()).collect(),
+            priority: 100,
+            split: None,
+        }
+    }
+
+    // 応答しないアーティファクトストア。取得中のまま (Loading) にしておく
+    async fn stalled_store() -> SocketAddr {
+        let app = Router::new().route("/guest.wasm", get(|| async {
+            tokio::time::sleep(Duration::from_secs(30)).await;
+            ""
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    // main.rs と同じく、すべてのパスを HttpHandler に渡すルーターを起動する
+    async fn serve(name: &str) -> String {
+        let cache = Arc::new(LocalWasmCache::new(test_support::temp_dir("handlers-test", name), 1024 * 1024).unwrap());
+        let sha256 = format!("{:x}", Sha256::digest(GUEST.as_bytes()));
+        cache.put("func1", "1.0.0", GUEST.as_bytes(), &sha256).await.unwrap();
+
+        let node = TestNode::new();
+        node.deploy(FunctionMetadata { sha256, ..test_support::metadata("func1", "1.0.0") }).await;
+        node.deploy(FunctionMetadata {
+            artifact_url: format!("http://{}/guest.wasm", stalled_store().await),
+            sha256: "0".repeat(64),
+            ..test_support::metadata("slow", "1.0.0")
+        }).await;
+        assert!(node.function_service.replace_routes(vec![
+            route("hello", "/hello", "func1", &["GET", "PUT"]),
+            route("slow", "/slow", "slow", &["GET"]),
+        ]).await.is_empty());
+
+        let handler = Arc::new(HttpHandler::new(Arc::new(node.invocation_service(cache))));
+        let app = Router::new().route("/*path", any(move |req: Request<Body>| {
+            let handler = handler.clone();
+            async move { handler.handle_request(req).await }
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    async fn error_body(response: reqwest::Response) -> serde_json::Value {
+        assert_eq!(response.headers()["content-type"], "application/json");
+        response.json().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_guest_response_is_forwarded() {
+        let base = serve("ok").await;
+
+        let response = reqwest::get(format!("{}/hello", base)).await.unwrap();
+        assert_eq!(response.status().as_u16(), StatusCode::CREATED.as_u16());
+        assert_eq!(response.headers()["content-type"], "text/plain");
+        assert!(response.headers().contains_key("x-request-id"));
+        assert_eq!(response.text().await.unwrap(), "hi");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_path_returns_json_error() {
+        let base = serve("not-found").await;
+
+        let response = reqwest::get(format!("{}/missing", base)).await.unwrap();
+        assert_eq!(response.status().as_u16(), StatusCode::NOT_FOUND.as_u16());
+        // エラーでもリクエスト ID を返す
+        assert!(response.headers().contains_key("x-request-id"));
+        assert_eq!(error_body(response).await, serde_json::json!({
+            "error": "route_not_found",
+            "message": "Route not found",
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_wrong_method_returns_405_with_allow() {
+        let base = serve("method").await;
+
+        let response = reqwest::Client::new().post(format!("{}/hello", base)).send().await.unwrap();
+        assert_eq!(response.status().as_u16(), StatusCode::METHOD_NOT_ALLOWED.as_u16());
+        assert_eq!(response.headers()["allow"], "GET, PUT");
+        assert_eq!(error_body(response).await["error"], "method_not_allowed");
+    }
+
+    #[tokio::test]
+    async fn test_loading_artifact_returns_503_with_retry_after() {
+        let base = serve("loading").await;
+
+        let response = reqwest::get(format!("{}/slow", base)).await.unwrap();
+        assert_eq!(response.status().as_u16(), StatusCode::SERVICE_UNAVAILABLE.as_u16());
+        assert_eq!(response.headers()["retry-after"], "1");
+        let body = error_body(response).await;
+        assert_eq!(body["error"], "loading");
+        assert!(body["message"].is_string());
+    }
+
+    #[test]
+    fn test_status_of_every_error() {
+        let cases = [
+            (InvocationError::RouteNotFound, StatusCode::NOT_FOUND),
+            (InvocationError::MethodNotAllowed { allowed: vec!["GET".to_string()] }, StatusCode::METHOD_NOT_ALLOWED),
+            (InvocationError::FunctionNotFound("f".to_string()), StatusCode::NOT_FOUND),
+            (InvocationError::Throttled("f".to_string()), StatusCode::TOO_MANY_REQUESTS),
+            (InvocationError::Overloaded("full".to_string()), StatusCode::SERVICE_UNAVAILABLE),
+            (InvocationError::Loading("f".to_string()), StatusCode::SERVICE_UNAVAILABLE),
+            (InvocationError::Timeout { limit_ms: 100 }, StatusCode::GATEWAY_TIMEOUT),
+            (InvocationError::Instantiation("e".to_string()), StatusCode::INTERNAL_SERVER_ERROR),
+            (InvocationError::Execution("e".to_string()), StatusCode::INTERNAL_SERVER_ERROR),
+            (InvocationError::InvalidResponse("e".to_string()), StatusCode::BAD_GATEWAY),
+            (InvocationError::Download("e".to_string()), StatusCode::BAD_GATEWAY),
+            (InvocationError::ArtifactTooLarge { limit_bytes: 1 }, StatusCode::BAD_GATEWAY),
+            (InvocationError::ChecksumMismatch { expected: "a".to_string(), actual: "b".to_string() }, StatusCode::INTERNAL_SERVER_ERROR),
+            (InvocationError::Cache("e".to_string()), StatusCode::INTERNAL_SERVER_ERROR),
+            (InvocationError::ControlPlane("e".to_string()), StatusCode::BAD_GATEWAY),
+            (InvocationError::Internal("e".to_string()), StatusCode::INTERNAL_SERVER_ERROR),
+        ];
+        for (error, status) in cases {
+            assert_eq!(status_of(&error), status, "{}", error.kind());
+        }
+    }
+}
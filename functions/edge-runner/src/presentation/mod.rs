@@ -1,3 +1,6 @@
 pub mod handlers;
+pub mod admin;
+mod handlers_tests;
+mod admin_tests;
 
 pub use handlers::*;
@@ -0,0 +1,93 @@
+// 各テストで共有するフィクスチャ。FunctionMetadata やプールの組み立てはここだけで行う
+use crate::application::{FunctionService, InvocationService};
+use crate::domain::{FunctionMetadata, IsolationPolicy};
+use crate::infrastructure::guest_log::GuestLogStore;
+use crate::infrastructure::host::HostServices;
+use crate::infrastructure::http_fetch::HttpFetcher;
+use crate::infrastructure::kv::{KvQuota, KvStore};
+use crate::infrastructure::*;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+// 必要な項目だけ `FunctionMetadata { sha256, ..metadata("func1", "1.0.0") }` のように上書きする
+pub(crate) fn metadata(function_id: &str, version: &str) -> FunctionMetadata {
+    FunctionMetadata {
+        function_id: function_id.to_string(),
+        version: version.to_string(),
+        artifact_url: String::new(),
+        sha256: String::new(),
+        memory_pages: 16,
+        max_execution_ms: 100,
+        max_concurrency: 0,
+        min_warm: 0,
+        isolation: IsolationPolicy::Reuse,
+        wasi: false,
+        wasi_preopen_dir: None,
+        allowed_hosts: Vec::new(),
+        telemetry: false,
+    }
+}
+
+// テストごとに空の一時ディレクトリ
+pub(crate) fn temp_dir(prefix: &str, name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("{}-{}-{}", prefix, name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+// Tokio ランタイムの中で呼ぶこと
+pub(crate) fn host_services(kv: Arc<KvStore>) -> Arc<HostServices> {
+    Arc::new(HostServices {
+        fetcher: HttpFetcher::new(tokio::runtime::Handle::current()),
+        kv,
+        logs: Arc::new(GuestLogStore::new("node-1".to_string(), 100, 10, 10)),
+        telemetry: None,
+    })
+}
+
+pub(crate) fn kv_store() -> Arc<KvStore> {
+    Arc::new(KvStore::in_memory(KvQuota::default()).unwrap())
+}
+
+pub(crate) fn pool(max_instances: usize, queue_timeout_ms: u64) -> HotInstancePool {
+    HotInstancePool::new(max_instances, 300, queue_timeout_ms, None, host_services(kv_store()))
+}
+
+// インメモリのリポジトリとプールで組んだノード
+pub(crate) struct TestNode {
+    pub pool: Arc<HotInstancePool>,
+    pub cache_repo: Arc<InMemoryCacheRepository>,
+    pub function_service: Arc<FunctionService>,
+}
+
+impl TestNode {
+    pub(crate) fn new() -> Self {
+        Self::with_pool(Arc::new(pool(4, 50)))
+    }
+
+    pub(crate) fn with_pool(pool: Arc<HotInstancePool>) -> Self {
+        let cache_repo = Arc::new(InMemoryCacheRepository::new());
+        let function_service = Arc::new(FunctionService::new(
+            Arc::new(InMemoryFunctionRepository::new(3)),
+            Arc::new(InMemoryRouteRepository::new()),
+            cache_repo.clone(),
+            pool.clone(),
+        ));
+        Self { pool, cache_repo, function_service }
+    }
+
+    pub(crate) async fn deploy(&self, metadata: FunctionMetadata) {
+        let (function_id, version) = (metadata.function_id.clone(), metadata.version.clone());
+        self.function_service.register_function(metadata).await;
+        self.function_service.activate_function(&function_id, &version).await.unwrap();
+    }
+
+    pub(crate) fn invocation_service(&self, cache: Arc<LocalWasmCache>) -> InvocationService {
+        InvocationService::new(
+            self.function_service.clone(),
+            self.pool.clone(),
+            Arc::new(WasmExecutor::new(1, 4)),
+            Arc::new(ArtifactLoader::new(cache)),
+        )
+    }
+}
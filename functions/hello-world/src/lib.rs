@@ -1,25 +1,54 @@
 use std::slice;
-use std::str;
 
+#[no_mangle]
+pub extern "C" fn alloc(len: usize) -> *mut u8 {
+    let mut buf = Vec::<u8>::with_capacity(len);
+    let ptr = buf.as_mut_ptr();
+    std::mem::forget(buf);
+    ptr
+}
+
+/// # Safety
+/// `ptr` は `alloc(len)` が返したポインタであること
+#[no_mangle]
+pub unsafe extern "C" fn dealloc(ptr: *mut u8, len: usize) {
+    drop(Vec::from_raw_parts(ptr, 0, len));
+}
+
+// ランナーが渡した (ptr, len) の入力を読む
+fn input<'a>(ptr: *const u8, len: usize) -> &'a [u8] {
+    if len == 0 {
+        return &[];
+    }
+    // SAFETY: ランナーは `alloc(len)` で確保して len バイトを書き込んだ領域を渡し、
+    // `handle` から戻るまで `dealloc` しない。返したスライスは handle の中でのみ使う
+    unsafe { slice::from_raw_parts(ptr, len) }
+}
+
+/// 入力はランナーが `alloc` で確保して書き込み、呼び出し後に `dealloc` する。
+/// 戻り値は `(ptr << 32) | len` 形式のレスポンスエンベロープ。
 #[no_mangle]
 pub extern "C" fn handle(
     method_ptr: *const u8, method_len: usize,
     path_ptr: *const u8, path_len: usize,
-    _headers_ptr: *const u8, _headers_len: usize,
-    _body_ptr: *const u8, _body_len: usize,
-    response_ptr: *mut u8, response_cap: usize
-) -> i32 {
-    let method = unsafe { str::from_utf8_unchecked(slice::from_raw_parts(method_ptr, method_len)) };
-    let path = unsafe { str::from_utf8_unchecked(slice::from_raw_parts(path_ptr, path_len)) };
-    
-    let response = format!("{{\"message\":\"Hello from WASM!\",\"method\":\"{}\",\"path\":\"{}\"}}",
-        method, path);
-    let bytes = response.as_bytes();
-    let len = bytes.len().min(response_cap);
-    
-    unsafe {
-        std::ptr::copy_nonoverlapping(bytes.as_ptr(), response_ptr, len);
-    }
-    
-    len as i32
+    headers_ptr: *const u8, headers_len: usize,
+    _body_ptr: *const u8, body_len: usize,
+) -> i64 {
+    let method = String::from_utf8_lossy(input(method_ptr, method_len));
+    // path には `?query` が含まれる場合がある
+    let path = String::from_utf8_lossy(input(path_ptr, path_len));
+    // ヘッダーは `Name: value\r\n` の繰り返し
+    let headers = input(headers_ptr, headers_len);
+    let header_count = headers.windows(2).filter(|w| w == b"\r\n").count();
+
+    let body = format!("{{\"message\":\"Hello from WASM!\",\"method\":\"{}\",\"path\":\"{}\",\"headers\":{},\"body_bytes\":{}}}",
+        method, path, header_count, body_len);
+    // レスポンスエンベロープ: ステータス行、ヘッダー、空行、ボディ
+    let response = format!("200\r\nContent-Type: application/json\r\n\r\n{}", body);
+
+    let bytes = response.into_bytes().into_boxed_slice();
+    let len = bytes.len();
+    let ptr = Box::into_raw(bytes) as *mut u8;
+
+    (((ptr as u64) << 32) | len as u64) as i64
 }